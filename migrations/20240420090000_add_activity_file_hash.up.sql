@@ -0,0 +1,5 @@
+-- Add up migration script here
+ALTER TABLE activities
+    ADD COLUMN file_hash text;
+
+CREATE INDEX IF NOT EXISTS IX_activities_user_hash ON activities (user_id, file_hash);
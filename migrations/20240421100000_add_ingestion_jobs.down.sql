@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS ingestion_jobs;
+
+DROP TYPE IF EXISTS ingestion_status;
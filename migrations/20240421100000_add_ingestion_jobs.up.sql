@@ -0,0 +1,24 @@
+-- Add up migration script here
+CREATE TYPE ingestion_status AS ENUM (
+    'queued',
+    'running',
+    'done'
+);
+
+CREATE TABLE IF NOT EXISTS ingestion_jobs (
+    id bigserial NOT NULL PRIMARY KEY,
+    user_id integer NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    file_name text NOT NULL,
+    data bytea,
+    duplicates text NOT NULL,
+    status ingestion_status NOT NULL DEFAULT 'queued',
+    attempts integer NOT NULL DEFAULT 0,
+    run_after timestamp with time zone NOT NULL DEFAULT now(),
+    result jsonb,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    updated_at timestamp with time zone NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS IX_ingestion_jobs_queue ON ingestion_jobs (status, run_after);
+
+CREATE INDEX IF NOT EXISTS IX_ingestion_jobs_user ON ingestion_jobs (user_id);
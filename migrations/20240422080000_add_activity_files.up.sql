@@ -0,0 +1,9 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS activity_files (
+    activity_id integer NOT NULL PRIMARY KEY REFERENCES activities (id) ON DELETE CASCADE,
+    file_name text NOT NULL,
+    data bytea,
+    path text,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    CHECK (data IS NOT NULL OR path IS NOT NULL)
+);
@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE records
+    ALTER COLUMN cadence TYPE smallint,
+    DROP COLUMN IF EXISTS vertical_oscillation,
+    DROP COLUMN IF EXISTS ground_contact_time,
+    DROP COLUMN IF EXISTS temperature;
@@ -0,0 +1,6 @@
+-- Add up migration script here
+ALTER TABLE records
+    ALTER COLUMN cadence TYPE NUMERIC(5, 2),
+    ADD COLUMN vertical_oscillation NUMERIC(5, 1),
+    ADD COLUMN ground_contact_time NUMERIC(5, 1),
+    ADD COLUMN temperature smallint;
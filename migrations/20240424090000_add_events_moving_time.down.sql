@@ -0,0 +1,7 @@
+-- Add down migration script here
+ALTER TABLE activities
+    DROP COLUMN IF EXISTS moving_time;
+
+DROP INDEX IF EXISTS IX_events_activity;
+ALTER TABLE events
+    DROP COLUMN IF EXISTS event;
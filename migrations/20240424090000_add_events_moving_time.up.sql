@@ -0,0 +1,9 @@
+-- Add up migration script here
+ALTER TABLE events
+    ADD COLUMN event varchar(100) NOT NULL DEFAULT 'timer';
+ALTER TABLE events
+    ALTER COLUMN event DROP DEFAULT;
+CREATE INDEX IF NOT EXISTS IX_events_activity ON events (activity_id);
+
+ALTER TABLE activities
+    ADD COLUMN moving_time NUMERIC(8,1);
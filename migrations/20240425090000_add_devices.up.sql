@@ -0,0 +1,13 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS devices (
+    id BIGSERIAL PRIMARY KEY,
+    activity_id integer NOT NULL REFERENCES activities (id) ON DELETE CASCADE,
+    device_index smallint,
+    device_type varchar(100),
+    source_type varchar(100),
+    manufacturer varchar(100),
+    product varchar(100),
+    serial_number bigint,
+    software_version NUMERIC(8,2)
+);
+CREATE INDEX IF NOT EXISTS IX_devices_activity ON devices (activity_id);
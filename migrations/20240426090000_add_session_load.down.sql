@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE sessions
+    DROP COLUMN IF EXISTS load;
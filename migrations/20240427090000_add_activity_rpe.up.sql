@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE activities
+    ADD COLUMN rpe smallint;
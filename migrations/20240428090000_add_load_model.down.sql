@@ -0,0 +1,5 @@
+-- Add down migration script here
+ALTER TABLE activities
+    DROP COLUMN IF EXISTS load_model;
+
+DROP TYPE IF EXISTS load_model;
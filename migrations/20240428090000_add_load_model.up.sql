@@ -0,0 +1,22 @@
+-- Add up migration script here
+CREATE TYPE load_model AS ENUM (
+    'heartrate',
+    'average_heartrate',
+    'session_rpe'
+);
+
+ALTER TABLE activities
+    ADD COLUMN load_model load_model;
+
+UPDATE activities
+SET load_model = CASE
+    WHEN EXISTS (
+        SELECT 1
+        FROM records
+        WHERE records.activity_id = activities.id
+            AND records.heartrate IS NOT NULL
+    ) THEN 'heartrate'::load_model
+    WHEN avg_heartrate IS NOT NULL THEN 'average_heartrate'::load_model
+    ELSE 'session_rpe'::load_model
+END
+WHERE load IS NOT NULL;
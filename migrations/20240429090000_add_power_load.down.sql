@@ -0,0 +1,14 @@
+-- Add down migration script here
+ALTER TABLE activities
+    DROP COLUMN IF EXISTS normalized_power,
+    DROP COLUMN IF EXISTS intensity_factor,
+    DROP COLUMN IF EXISTS tss;
+
+ALTER TABLE user_preferences
+    DROP COLUMN IF EXISTS ftp,
+    DROP COLUMN IF EXISTS power_load_sports;
+
+-- enum values can't be dropped, power loads are recalculated on the next reprocessing
+UPDATE activities
+SET load_model = 'heartrate'
+WHERE load_model = 'power';
@@ -0,0 +1,11 @@
+-- Add up migration script here
+ALTER TYPE load_model ADD VALUE IF NOT EXISTS 'power';
+
+ALTER TABLE user_preferences
+    ADD COLUMN ftp integer,
+    ADD COLUMN power_load_sports text[] NOT NULL DEFAULT '{}';
+
+ALTER TABLE activities
+    ADD COLUMN normalized_power integer,
+    ADD COLUMN intensity_factor NUMERIC(4,3),
+    ADD COLUMN tss integer;
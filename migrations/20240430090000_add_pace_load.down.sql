@@ -0,0 +1,14 @@
+-- Add down migration script here
+ALTER TABLE activities
+    DROP COLUMN IF EXISTS normalized_graded_speed,
+    DROP COLUMN IF EXISTS pace_intensity_factor,
+    DROP COLUMN IF EXISTS rtss;
+
+ALTER TABLE user_preferences
+    DROP COLUMN IF EXISTS threshold_pace,
+    DROP COLUMN IF EXISTS pace_load_sports;
+
+-- enum values can't be dropped, pace loads are recalculated on the next reprocessing
+UPDATE activities
+SET load_model = 'heartrate'
+WHERE load_model = 'pace';
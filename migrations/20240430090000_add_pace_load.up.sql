@@ -0,0 +1,11 @@
+-- Add up migration script here
+ALTER TYPE load_model ADD VALUE IF NOT EXISTS 'pace';
+
+ALTER TABLE user_preferences
+    ADD COLUMN threshold_pace integer,
+    ADD COLUMN pace_load_sports text[] NOT NULL DEFAULT '{}';
+
+ALTER TABLE activities
+    ADD COLUMN normalized_graded_speed NUMERIC(6,3),
+    ADD COLUMN pace_intensity_factor NUMERIC(4,3),
+    ADD COLUMN rtss integer;
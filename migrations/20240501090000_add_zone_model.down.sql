@@ -0,0 +1,7 @@
+-- Add down migration script here
+ALTER TABLE user_preferences
+    DROP COLUMN IF EXISTS zone_model,
+    DROP COLUMN IF EXISTS resting_heartrate,
+    DROP COLUMN IF EXISTS lactate_threshold_heartrate;
+
+DROP TYPE IF EXISTS zone_model;
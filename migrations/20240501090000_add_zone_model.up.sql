@@ -0,0 +1,12 @@
+-- Add up migration script here
+CREATE TYPE zone_model AS ENUM (
+    'three_zone',
+    'max_heartrate',
+    'heartrate_reserve',
+    'lactate_threshold'
+);
+
+ALTER TABLE user_preferences
+    ADD COLUMN zone_model zone_model NOT NULL DEFAULT 'three_zone',
+    ADD COLUMN resting_heartrate integer,
+    ADD COLUMN lactate_threshold_heartrate integer;
@@ -0,0 +1,10 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS time_in_zones (
+    id BIGSERIAL PRIMARY KEY,
+    activity_id integer NOT NULL REFERENCES activities (id) ON DELETE CASCADE,
+    lap_id bigint REFERENCES laps (id) ON DELETE CASCADE,
+    zone_model zone_model NOT NULL,
+    zone smallint NOT NULL,
+    seconds NUMERIC(8,1) NOT NULL
+);
+CREATE INDEX IF NOT EXISTS IX_time_in_zones_activity ON time_in_zones (activity_id);
@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS daily_time_in_zones;
+DROP TABLE IF EXISTS daily_heartrates;
+DROP TABLE IF EXISTS daily_totals;
+DROP TABLE IF EXISTS activity_heartrates;
+DROP TABLE IF EXISTS activity_totals;
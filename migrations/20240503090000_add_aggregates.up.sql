@@ -0,0 +1,94 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS activity_totals (
+    activity_id integer NOT NULL REFERENCES activities (id) ON DELETE CASCADE,
+    day timestamp with time zone NOT NULL,
+    sport varchar(50) NOT NULL,
+    load integer,
+    distance NUMERIC(9,2),
+    duration NUMERIC(8,1) NOT NULL,
+    PRIMARY KEY (activity_id, sport)
+);
+
+CREATE TABLE IF NOT EXISTS activity_heartrates (
+    activity_id integer NOT NULL REFERENCES activities (id) ON DELETE CASCADE,
+    heartrate smallint NOT NULL,
+    count integer NOT NULL,
+    PRIMARY KEY (activity_id, heartrate)
+);
+
+CREATE TABLE IF NOT EXISTS daily_totals (
+    user_id integer NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    day timestamp with time zone NOT NULL,
+    sport varchar(50) NOT NULL,
+    activities integer NOT NULL,
+    load integer,
+    distance NUMERIC(11,2),
+    duration NUMERIC(10,1) NOT NULL,
+    PRIMARY KEY (user_id, day, sport)
+);
+
+CREATE TABLE IF NOT EXISTS daily_heartrates (
+    user_id integer NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    day timestamp with time zone NOT NULL,
+    heartrate smallint NOT NULL,
+    count integer NOT NULL,
+    PRIMARY KEY (user_id, day, heartrate)
+);
+
+CREATE TABLE IF NOT EXISTS daily_time_in_zones (
+    user_id integer NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    day timestamp with time zone NOT NULL,
+    zone_model zone_model NOT NULL,
+    zone smallint NOT NULL,
+    seconds NUMERIC(10,1) NOT NULL,
+    PRIMARY KEY (user_id, day, zone_model, zone)
+);
+
+INSERT INTO activity_totals (activity_id, day, sport, load, distance, duration)
+SELECT activity_id, day, sport, SUM(load), SUM(distance), SUM(duration)
+FROM (
+    SELECT
+        sessions.activity_id,
+        date_trunc('day', activities.start_time) as day,
+        sessions.sport,
+        CASE
+            WHEN COUNT(sessions.load) OVER (PARTITION BY activities.id) > 0
+                THEN sessions.load
+            WHEN ROW_NUMBER() OVER (PARTITION BY activities.id ORDER BY sessions.start_time) = 1
+                THEN activities.load
+        END as load,
+        sessions.distance,
+        EXTRACT(EPOCH FROM sessions.end_time - sessions.start_time) as duration
+    FROM sessions
+    JOIN activities ON activities.id = sessions.activity_id
+) totals
+GROUP BY activity_id, day, sport;
+
+INSERT INTO activity_heartrates (activity_id, heartrate, count)
+SELECT activity_id, heartrate, COUNT(*)
+FROM records
+WHERE heartrate IS NOT NULL
+GROUP BY activity_id, heartrate;
+
+INSERT INTO daily_totals (user_id, day, sport, activities, load, distance, duration)
+SELECT activities.user_id, activity_totals.day, activity_totals.sport, COUNT(*),
+    SUM(activity_totals.load), SUM(activity_totals.distance), SUM(activity_totals.duration)
+FROM activity_totals
+JOIN activities ON activities.id = activity_totals.activity_id
+GROUP BY activities.user_id, activity_totals.day, activity_totals.sport;
+
+INSERT INTO daily_heartrates (user_id, day, heartrate, count)
+SELECT activities.user_id, date_trunc('day', activities.start_time), activity_heartrates.heartrate,
+    SUM(activity_heartrates.count)
+FROM activity_heartrates
+JOIN activities ON activities.id = activity_heartrates.activity_id
+GROUP BY activities.user_id, date_trunc('day', activities.start_time), activity_heartrates.heartrate;
+
+INSERT INTO daily_time_in_zones (user_id, day, zone_model, zone, seconds)
+SELECT activities.user_id, date_trunc('day', activities.start_time), time_in_zones.zone_model,
+    time_in_zones.zone, SUM(time_in_zones.seconds)
+FROM time_in_zones
+JOIN activities ON activities.id = time_in_zones.activity_id
+WHERE time_in_zones.lap_id IS NULL
+GROUP BY activities.user_id, date_trunc('day', activities.start_time), time_in_zones.zone_model,
+    time_in_zones.zone;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS performance_models;
+DROP TABLE IF EXISTS benchmarks;
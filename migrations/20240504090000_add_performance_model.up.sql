@@ -0,0 +1,20 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS benchmarks (
+    id serial PRIMARY KEY,
+    user_id integer NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    day timestamp with time zone NOT NULL,
+    performance double precision NOT NULL,
+    note varchar(200)
+);
+CREATE INDEX IF NOT EXISTS benchmarks_user_id_idx ON benchmarks (user_id, day);
+
+CREATE TABLE IF NOT EXISTS performance_models (
+    user_id integer PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+    p0 double precision NOT NULL,
+    k1 double precision NOT NULL,
+    k2 double precision NOT NULL,
+    fitness_tau double precision NOT NULL,
+    fatigue_tau double precision NOT NULL,
+    rmse double precision NOT NULL,
+    fitted_at timestamp with time zone NOT NULL DEFAULT now()
+);
@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS daily_fitness;
+ALTER TABLE user_preferences
+    DROP COLUMN IF EXISTS atl_days,
+    DROP COLUMN IF EXISTS ctl_days;
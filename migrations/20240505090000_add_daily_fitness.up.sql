@@ -0,0 +1,13 @@
+-- Add up migration script here
+ALTER TABLE user_preferences
+    ADD COLUMN IF NOT EXISTS ctl_days integer NOT NULL DEFAULT 42,
+    ADD COLUMN IF NOT EXISTS atl_days integer NOT NULL DEFAULT 7;
+
+CREATE TABLE IF NOT EXISTS daily_fitness (
+    user_id integer NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    day timestamp with time zone NOT NULL,
+    load double precision NOT NULL,
+    ctl double precision NOT NULL,
+    atl double precision NOT NULL,
+    PRIMARY KEY (user_id, day)
+);
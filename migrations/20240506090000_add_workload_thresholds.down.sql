@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE user_preferences
+    DROP COLUMN IF EXISTS acwr_low,
+    DROP COLUMN IF EXISTS acwr_high,
+    DROP COLUMN IF EXISTS monotony_threshold,
+    DROP COLUMN IF EXISTS strain_threshold;
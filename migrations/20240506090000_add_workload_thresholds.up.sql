@@ -0,0 +1,6 @@
+-- Add up migration script here
+ALTER TABLE user_preferences
+    ADD COLUMN IF NOT EXISTS acwr_low double precision NOT NULL DEFAULT 0.8,
+    ADD COLUMN IF NOT EXISTS acwr_high double precision NOT NULL DEFAULT 1.3,
+    ADD COLUMN IF NOT EXISTS monotony_threshold double precision NOT NULL DEFAULT 2.0,
+    ADD COLUMN IF NOT EXISTS strain_threshold double precision;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS best_efforts;
+DROP TYPE IF EXISTS best_effort_kind;
@@ -0,0 +1,19 @@
+-- Add up migration script here
+CREATE TYPE best_effort_kind AS ENUM (
+    'distance',
+    'heartrate',
+    'speed',
+    'power'
+);
+
+CREATE TABLE IF NOT EXISTS best_efforts (
+    activity_id integer NOT NULL REFERENCES activities (id) ON DELETE CASCADE,
+    sport varchar(50) NOT NULL,
+    kind best_effort_kind NOT NULL,
+    target integer NOT NULL,
+    value float8 NOT NULL,
+    start_time timestamp with time zone NOT NULL,
+    PRIMARY KEY (activity_id, sport, kind, target)
+);
+
+CREATE INDEX IF NOT EXISTS IX_best_efforts ON best_efforts (sport, kind, target);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE activities
+    DROP COLUMN IF EXISTS merged;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE activities
+    ADD COLUMN IF NOT EXISTS merged boolean NOT NULL DEFAULT false;
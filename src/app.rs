@@ -2,12 +2,13 @@ use crate::{
     authentication,
     error_template::{AppError, ErrorTemplate},
     pages::{
-        activity_overview::ActivityList,
+        activity_overview::{manual_entry::ManualActivityForm, ActivityList},
         auth::{login::Login, signup::Signup},
         fit_upload::FitUploadForm,
         home::Home,
         landing::Landing,
-        overview::Overview,
+        overview::{workload_chart::AcwrWarning, Overview},
+        records::PersonalRecords,
         user::UserSettings,
         workout_schedule::WorkoutCalendar,
     },
@@ -41,6 +42,7 @@ pub fn App() -> impl IntoView {
     provide_context(FitFileUploaded(uploaded));
     let (show_upload, set_show_upload) = create_signal(false);
     let show_settings = create_rw_signal(false);
+    let show_manual_entry = create_rw_signal(false);
     let login = create_server_action::<authentication::Login>();
     let logout = create_server_action::<authentication::Logout>();
     let signup = create_server_action::<authentication::Signup>();
@@ -129,6 +131,10 @@ pub fn App() -> impl IntoView {
                                                         <A href="/calendar" class="navbar-item">
                                                             Calendar
                                                         </A>
+
+                                                        <A href="/records" class="navbar-item">
+                                                            Records
+                                                        </A>
                                                         <a
                                                             href="#"
                                                             class="navbar-item"
@@ -138,6 +144,7 @@ pub fn App() -> impl IntoView {
                                                         </a>
                                                     </div>
                                                     <div class="navbar-end">
+                                                        <AcwrWarning/>
                                                         <div class="navbar-item">
                                                             <div class="buttons">
                                                                 <a
@@ -147,6 +154,13 @@ pub fn App() -> impl IntoView {
                                                                     Upload
                                                                     <i class="material-symbols-rounded right">upload</i>
                                                                 </a>
+                                                                <a
+                                                                    class="button"
+                                                                    on:click=move |_| { show_manual_entry.update(|v| *v = !*v) }
+                                                                >
+                                                                    Add
+                                                                    <i class="material-symbols-rounded right">add</i>
+                                                                </a>
                                                                 <ActionForm action=logout>
                                                                     <button type="submit" class="button">
                                                                         "Log Out"
@@ -161,6 +175,7 @@ pub fn App() -> impl IntoView {
                                                 <Outlet/>
                                                 <FitUploadForm show=show_upload show_set=set_show_upload/>
                                                 <UserSettings show=show_settings/>
+                                                <ManualActivityForm show=show_manual_entry/>
                                             </main>
                                         }
                                             .into_view()
@@ -178,6 +193,7 @@ pub fn App() -> impl IntoView {
 
                     <Route path="/activities" view=ActivityList/>
                     <Route path="/calendar" view=WorkoutCalendar/>
+                    <Route path="/records" view=PersonalRecords/>
 
                 </Route>
             </Routes>
@@ -6,6 +6,8 @@ cfg_if::cfg_if! {
         };
         use once_cell::sync::OnceCell;
         use serde::Deserialize;
+        use std::path::PathBuf;
+        use crate::pages::fit_upload::watch::WatchDirectory;
     }
 }
 
@@ -24,6 +26,28 @@ cfg_if::cfg_if! {
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub database_url: String,
+    /// Number of background workers importing uploaded files.
+    #[serde(default = "default_ingestion_workers")]
+    pub ingestion_workers: usize,
+    /// Directories to import activity files from automatically.
+    #[serde(default)]
+    pub watch_directories: Vec<WatchDirectory>,
+    /// Seconds between scans of the watch directories.
+    #[serde(default = "default_watch_interval")]
+    pub watch_interval: u64,
+    /// Directory to keep uploaded files in, they are stored in the database if not set.
+    #[serde(default)]
+    pub file_store: Option<PathBuf>,
+}
+
+#[cfg(feature = "ssr")]
+fn default_ingestion_workers() -> usize {
+    2
+}
+
+#[cfg(feature = "ssr")]
+fn default_watch_interval() -> u64 {
+    30
 }
 
 #[cfg(feature = "ssr")]
@@ -1,13 +1,17 @@
 use cfg_if::cfg_if;
 cfg_if! {
     if #[cfg(feature = "ssr")] {
-        use axum::{routing::{get,post}, Router, response::{Response,IntoResponse}, extract::{Path, State }, http::{Request }, body::Body as AxumBody};
+        use axum::{routing::{get,post}, Router, response::{Response,IntoResponse}, extract::{DefaultBodyLimit, Path, State }, http::{Request }, body::Body as AxumBody};
         use leptos::logging::log;
         use leptos::*;
         use leptos_axum::{generate_route_list, LeptosRoutes, handle_server_fns_with_context};
         use toedirs::app::*;
         use toedirs::authentication::*;
-        use toedirs::pages::fit_upload::upload_fit_file;
+        use toedirs::models::aggregates::backfill_time_in_zones;
+        use toedirs::pages::fit_upload::{jobs::start_workers, upload_fit_file, watch::start_watchers};
+        use toedirs::pages::activity_overview::export::export_activity;
+        use toedirs::pages::overview::fitness_level_chart::export_daily_fitness;
+        use toedirs::pages::user::archive::{export_account, import_account, MAX_ARCHIVE_SIZE};
         use toedirs::state::AppState;
         use toedirs::config::Config;
         use toedirs::fileserv::file_and_error_handler;
@@ -82,8 +86,29 @@ async fn main() {
             .await
             .expect("couldn't create session store");
     migrate!().run(&pool).await.expect("migrations to run");
-
+    // before the workers and watchers, to see what they log on startup
     simple_logger::init_with_level(log::Level::Warn).expect("couldn't initialize logging");
+    start_workers(pool.clone(), config.ingestion_workers)
+        .await
+        .expect("couldn't start ingestion workers");
+    start_watchers(
+        pool.clone(),
+        &config.watch_directories,
+        std::time::Duration::from_secs(config.watch_interval),
+    )
+    .await
+    .expect("couldn't start watching import directories");
+    // activities imported before the time in zones was stored
+    tokio::spawn({
+        let pool = pool.clone();
+        async move {
+            match backfill_time_in_zones(None, &pool).await {
+                Ok(0) => {}
+                Ok(count) => log!("Calculated the time in zones of {} activities", count),
+                Err(e) => leptos::logging::error!("Couldn't calculate time in zones: {}", e),
+            }
+        }
+    });
 
     // Setting get_configuration(None) means we'll be using cargo-leptos's env values
     // For deployment these variables are:
@@ -107,6 +132,16 @@ async fn main() {
             get(server_fn_handler).post(server_fn_handler),
         )
         .route("/api/upload_fit_file", post(upload_fit_file))
+        .route(
+            "/api/activities/:activity_id/export/:format",
+            get(export_activity),
+        )
+        .route("/api/account/export", get(export_account))
+        .route(
+            "/api/account/import",
+            post(import_account).layer(DefaultBodyLimit::max(MAX_ARCHIVE_SIZE)),
+        )
+        .route("/api/fitness", get(export_daily_fitness))
         .leptos_routes_with_handler(routes, get(leptos_routes_handler))
         .fallback(file_and_error_handler)
         .layer(NewSentryLayer::new_from_top())
@@ -1,21 +1,43 @@
 use chrono::{DateTime, Duration, Local};
 use fitparser::{profile::MesgNum, FitDataRecord, Value};
+use serde::{Deserialize, Serialize};
 #[cfg(feature = "ssr")]
 use sqlx::{query, Row};
 
 #[cfg(feature = "ssr")]
 use super::base::Stored;
 use super::base::{DatabaseEntry, ModelError, New};
+use super::load::{LoadModel, PowerMetrics};
+use super::pace::PaceMetrics;
 
 #[non_exhaustive]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Activity {
     pub user_id: Option<i64>,
     pub start_time: DateTime<Local>,
     pub end_time: DateTime<Local>,
+    /// Timer time in seconds.
     pub duration: f64,
+    /// Time in seconds spent moving, excluding pauses and standing still with the timer running.
+    #[serde(default)]
+    pub moving_time: Option<f64>,
     pub load: Option<u32>,
     pub avg_heartrate: Option<u16>,
+    /// Sha256 of the file the activity was imported from, used to detect duplicate uploads.
+    #[serde(default)]
+    pub file_hash: Option<String>,
+    /// Rate of perceived exertion from 1 to 10, entered by the user.
+    #[serde(default)]
+    pub rpe: Option<u8>,
+    #[serde(default)]
+    pub load_model: Option<LoadModel>,
+    /// Normalized power, intensity factor and TSS, if there is power data and an FTP.
+    #[serde(default)]
+    pub power: Option<PowerMetrics>,
+    /// Normalized graded pace, intensity factor and rTSS, if there is speed data and a threshold
+    /// pace.
+    #[serde(default)]
+    pub pace: Option<PaceMetrics>,
 }
 impl TryFrom<FitDataRecord> for DatabaseEntry<New, Activity> {
     type Error = ModelError;
@@ -54,14 +76,22 @@ impl TryFrom<FitDataRecord> for DatabaseEntry<New, Activity> {
             }
         };
 
+        // the activity message is written when the activity ends, without pauses the timer time
+        // is how long before it started
         Ok(DatabaseEntry {
             state: Box::new(Activity {
                 user_id: None,
-                start_time: timestamp,
-                end_time: timestamp + Duration::try_seconds(duration as i64).unwrap(),
+                start_time: timestamp - Duration::try_seconds(duration as i64).unwrap(),
+                end_time: timestamp,
                 duration,
+                moving_time: None,
                 load: None,
                 avg_heartrate: None,
+                file_hash: None,
+                rpe: None,
+                load_model: None,
+                power: None,
+                pace: None,
             }),
             extra: New,
         })
@@ -76,8 +106,8 @@ pub async fn insert_activity(
 ) -> Result<DatabaseEntry<Stored, Activity>, ModelError> {
     let result = query(
         r#"
-        INSERT INTO activities (user_id, start_time, end_time, duration,avg_heartrate,load)
-        VALUES ($1, $2, $3,$4,$5,$6)
+        INSERT INTO activities (user_id, start_time, end_time, duration,avg_heartrate,load,file_hash,moving_time,rpe,load_model,normalized_power,intensity_factor,tss,normalized_graded_speed,pace_intensity_factor,rtss)
+        VALUES ($1, $2, $3,$4,$5,$6,$7,$8,$9,$10,$11,$12,$13,$14,$15,$16)
         RETURNING id
         "#,
     )
@@ -87,6 +117,16 @@ pub async fn insert_activity(
     .bind(activity.state.duration)
     .bind(activity.state.avg_heartrate.map(|v| v as i32))
     .bind(activity.state.load.map(|v| v as i32))
+    .bind(&activity.state.file_hash)
+    .bind(activity.state.moving_time)
+    .bind(activity.state.rpe.map(|v| v as i16))
+    .bind(activity.state.load_model)
+    .bind(activity.state.power.map(|p| p.normalized_power as i32))
+    .bind(activity.state.power.map(|p| p.intensity_factor))
+    .bind(activity.state.power.map(|p| p.tss as i32))
+    .bind(activity.state.pace.map(|p| p.normalized_graded_speed))
+    .bind(activity.state.pace.map(|p| p.intensity_factor))
+    .bind(activity.state.pace.map(|p| p.rtss as i32))
     .fetch_one(executor)
     .await
     .map_err(|e| ModelError::InsertError(format!("Couldn't insert activity: {}", e)))?;
@@ -99,3 +139,40 @@ pub async fn insert_activity(
         extra: Stored { activity_id },
     })
 }
+
+/// Update the times, heart rate, moving time, load and power and pace metrics of a stored
+/// activity, e.g. after another file was merged into it or it was reprocessed.
+#[cfg(feature = "ssr")]
+pub async fn update_activity_summary(
+    activity_id: i64,
+    activity: &Activity,
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE activities
+        SET start_time=$2, end_time=$3, duration=$4::float8, avg_heartrate=$5, load=$6,
+            moving_time=$7::float8, load_model=$8, normalized_power=$9,
+            intensity_factor=$10::float8, tss=$11, normalized_graded_speed=$12::float8,
+            pace_intensity_factor=$13::float8, rtss=$14
+        WHERE id=$1
+        "#,
+        activity_id,
+        activity.start_time,
+        activity.end_time,
+        activity.duration,
+        activity.avg_heartrate.map(|v| v as i16),
+        activity.load.map(|v| v as i32),
+        activity.moving_time,
+        activity.load_model as Option<LoadModel>,
+        activity.power.map(|p| p.normalized_power as i32),
+        activity.power.map(|p| p.intensity_factor),
+        activity.power.map(|p| p.tss as i32),
+        activity.pace.map(|p| p.normalized_graded_speed),
+        activity.pace.map(|p| p.intensity_factor),
+        activity.pace.map(|p| p.rtss as i32),
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,207 @@
+#[cfg(feature = "ssr")]
+use chrono::{DateTime, Local};
+
+#[cfg(feature = "ssr")]
+use super::{
+    best_efforts::update_best_efforts, fitness::invalidate_daily_fitness,
+    zones::update_time_in_zones,
+};
+
+/// Days the aggregates of an activity are currently counted on.
+#[cfg(feature = "ssr")]
+pub async fn aggregated_days(
+    activity_id: i64,
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<DateTime<Local>>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT DISTINCT day as "day: DateTime<Local>" FROM activity_totals WHERE activity_id=$1"#,
+        activity_id as i32
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Recalculate the time in zones, best efforts, totals per sport and heart rate histogram of an
+/// activity, as well as the daily aggregates of the days it was and now is on.
+///
+/// Has to be called whenever an activity is stored, changed or reprocessed, so the overview
+/// doesn't have to scan all records.
+#[cfg(feature = "ssr")]
+pub async fn update_aggregates(
+    activity_id: i64,
+    user_id: i64,
+    conn: &mut sqlx::PgConnection,
+) -> Result<(), sqlx::Error> {
+    let mut days = aggregated_days(activity_id, &mut *conn).await?;
+    update_time_in_zones(activity_id, user_id, &mut *conn).await?;
+    update_best_efforts(activity_id, &mut *conn).await?;
+    sqlx::query!(
+        r#"
+        WITH totals AS (DELETE FROM activity_totals WHERE activity_id=$1)
+        DELETE FROM activity_heartrates WHERE activity_id=$1
+        "#,
+        activity_id as i32
+    )
+    .execute(&mut *conn)
+    .await?;
+    // sessions of multisport activities count towards their own sport, activities without
+    // session loads count towards the sport of their first session
+    sqlx::query!(
+        r#"
+        INSERT INTO activity_totals (activity_id, day, sport, load, distance, duration)
+        SELECT activity_id, day, sport, SUM(load), SUM(distance), SUM(duration)
+        FROM (
+            SELECT
+                sessions.activity_id,
+                date_trunc('day', activities.start_time) as day,
+                sessions.sport,
+                CASE
+                    WHEN COUNT(sessions.load) OVER (PARTITION BY activities.id) > 0
+                        THEN sessions.load
+                    WHEN ROW_NUMBER() OVER (PARTITION BY activities.id ORDER BY sessions.start_time) = 1
+                        THEN activities.load
+                END as load,
+                sessions.distance,
+                EXTRACT(EPOCH FROM sessions.end_time - sessions.start_time) as duration
+            FROM sessions
+            JOIN activities ON activities.id = sessions.activity_id
+            WHERE activities.id = $1
+        ) totals
+        GROUP BY activity_id, day, sport
+        "#,
+        activity_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO activity_heartrates (activity_id, heartrate, count)
+        SELECT activity_id, heartrate, COUNT(*)
+        FROM records
+        WHERE activity_id = $1 AND heartrate IS NOT NULL
+        GROUP BY activity_id, heartrate
+        "#,
+        activity_id as i32
+    )
+    .execute(&mut *conn)
+    .await?;
+    let day = sqlx::query_scalar!(
+        r#"SELECT date_trunc('day', start_time) as "day!: DateTime<Local>" FROM activities WHERE id=$1"#,
+        activity_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    if !days.contains(&day) {
+        days.push(day);
+    }
+    update_daily_aggregates(user_id, &days, conn).await
+}
+
+/// Calculate the time in zones of the activities with heart rates that have none stored, e.g.
+/// the ones imported before it was stored or after the zone model changed, optionally only of
+/// one user.
+///
+/// Every activity is updated in its own transaction, returns how many were updated.
+#[cfg(feature = "ssr")]
+pub async fn backfill_time_in_zones(
+    user_id: Option<i64>,
+    pool: &sqlx::PgPool,
+) -> Result<usize, sqlx::Error> {
+    let activities = sqlx::query!(
+        r#"
+        SELECT id, user_id
+        FROM activities
+        WHERE ($1::int8 IS NULL OR user_id = $1)
+            AND NOT EXISTS (SELECT 1 FROM time_in_zones WHERE activity_id = activities.id)
+            AND EXISTS (
+                SELECT 1 FROM records WHERE activity_id = activities.id AND heartrate IS NOT NULL
+            )
+        ORDER BY id
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+    for activity in &activities {
+        let mut tx = pool.begin().await?;
+        update_time_in_zones(activity.id, activity.user_id.into(), &mut tx).await?;
+        let days = aggregated_days(activity.id, &mut *tx).await?;
+        update_daily_aggregates(activity.user_id.into(), &days, &mut tx).await?;
+        tx.commit().await?;
+    }
+    Ok(activities.len())
+}
+
+/// Recalculate the daily aggregates of a user's days from the aggregates of their activities.
+///
+/// The cached daily fitness from the first of the days on is dropped, as it depends on them.
+#[cfg(feature = "ssr")]
+pub async fn update_daily_aggregates(
+    user_id: i64,
+    days: &[DateTime<Local>],
+    conn: &mut sqlx::PgConnection,
+) -> Result<(), sqlx::Error> {
+    if days.is_empty() {
+        return Ok(());
+    }
+    sqlx::query!(
+        r#"
+        WITH totals AS (DELETE FROM daily_totals WHERE user_id=$1 AND day = ANY($2)),
+            heartrates AS (DELETE FROM daily_heartrates WHERE user_id=$1 AND day = ANY($2))
+        DELETE FROM daily_time_in_zones WHERE user_id=$1 AND day = ANY($2)
+        "#,
+        user_id as i32,
+        days as _
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO daily_totals (user_id, day, sport, activities, load, distance, duration)
+        SELECT activities.user_id, activity_totals.day, activity_totals.sport, COUNT(*),
+            SUM(activity_totals.load), SUM(activity_totals.distance), SUM(activity_totals.duration)
+        FROM activity_totals
+        JOIN activities ON activities.id = activity_totals.activity_id
+        WHERE activities.user_id = $1 AND activity_totals.day = ANY($2)
+        GROUP BY activities.user_id, activity_totals.day, activity_totals.sport
+        "#,
+        user_id as i32,
+        days as _
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO daily_heartrates (user_id, day, heartrate, count)
+        SELECT activities.user_id, date_trunc('day', activities.start_time),
+            activity_heartrates.heartrate, SUM(activity_heartrates.count)
+        FROM activity_heartrates
+        JOIN activities ON activities.id = activity_heartrates.activity_id
+        WHERE activities.user_id = $1 AND date_trunc('day', activities.start_time) = ANY($2)
+        GROUP BY activities.user_id, date_trunc('day', activities.start_time),
+            activity_heartrates.heartrate
+        "#,
+        user_id as i32,
+        days as _
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO daily_time_in_zones (user_id, day, zone_model, zone, seconds)
+        SELECT activities.user_id, date_trunc('day', activities.start_time),
+            time_in_zones.zone_model, time_in_zones.zone, SUM(time_in_zones.seconds)
+        FROM time_in_zones
+        JOIN activities ON activities.id = time_in_zones.activity_id
+        WHERE activities.user_id = $1 AND date_trunc('day', activities.start_time) = ANY($2)
+            AND time_in_zones.lap_id IS NULL
+        GROUP BY activities.user_id, date_trunc('day', activities.start_time),
+            time_in_zones.zone_model, time_in_zones.zone
+        "#,
+        user_id as i32,
+        days as _
+    )
+    .execute(&mut *conn)
+    .await?;
+    invalidate_daily_fitness(user_id, days.iter().min().copied(), &mut *conn).await
+}
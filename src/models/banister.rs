@@ -0,0 +1,354 @@
+use chrono::{DateTime, Local};
+use nalgebra::{DMatrix, DVector};
+use serde::{Deserialize, Serialize};
+
+/// Banister fitness-fatigue model, predicting performance from the daily training loads.
+///
+/// Every load adds to fitness and fatigue, which decay exponentially with their time constants
+/// in days. Performance is `p0 + k1 * fitness - k2 * fatigue`, both responses are normalized so
+/// that a constant daily load results in a response of that load.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BanisterModel {
+    /// Base performance without any training.
+    pub p0: f64,
+    /// Gain of fitness.
+    pub k1: f64,
+    /// Gain of fatigue.
+    pub k2: f64,
+    pub fitness_tau: f64,
+    pub fatigue_tau: f64,
+    /// Root mean square error of the fit to the benchmarks, `None` if not fitted.
+    pub rmse: Option<f64>,
+    pub fitted_at: Option<DateTime<Local>>,
+}
+
+impl Default for BanisterModel {
+    /// Unfitted model with the usual 42 and 7 day time constants, where form is fitness minus
+    /// fatigue.
+    fn default() -> Self {
+        Self {
+            p0: 0.0,
+            k1: 1.0,
+            k2: 1.0,
+            fitness_tau: 42.0,
+            fatigue_tau: 7.0,
+            rmse: None,
+            fitted_at: None,
+        }
+    }
+}
+
+/// Fewest benchmarks to fit a model to, there are five parameters.
+pub const MIN_BENCHMARKS: usize = 5;
+
+/// Fitness time constants in days tried when fitting.
+const FITNESS_TAUS: std::ops::RangeInclusive<u32> = 10..=60;
+/// Fatigue time constants in days tried when fitting, always shorter than the fitness one.
+const FATIGUE_TAUS: std::ops::RangeInclusive<u32> = 1..=20;
+
+/// Normalized response to the daily loads at the start of each day up to and including `days`,
+/// so a day's load only counts from the next day on.
+fn response(loads: &[f64], tau: f64, days: usize) -> Vec<f64> {
+    let decay = (-1.0 / tau).exp();
+    let mut value = 0.0;
+    (0..=days)
+        .map(|day| {
+            let start = value;
+            value = value * decay + loads.get(day).copied().unwrap_or(0.0) * (1.0 - decay);
+            start
+        })
+        .collect()
+}
+
+/// Fitness, fatigue and predicted performance of a day.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ModelDay {
+    pub date: DateTime<Local>,
+    /// Weighted fitness, `k1 * fitness`.
+    pub fitness: f64,
+    /// Weighted fatigue, `k2 * fatigue`.
+    pub fatigue: f64,
+    /// Performance above the base performance.
+    pub form: f64,
+    pub performance: f64,
+}
+
+impl BanisterModel {
+    /// Weighted fitness and fatigue at the start of each day from the first load up to `days`,
+    /// days after the last load only decay.
+    pub fn simulate(&self, loads: &[f64], days: usize) -> Vec<(f64, f64)> {
+        response(loads, self.fitness_tau, days)
+            .into_iter()
+            .zip(response(loads, self.fatigue_tau, days))
+            .map(|(fitness, fatigue)| (self.k1 * fitness, self.k2 * fatigue))
+            .collect()
+    }
+
+    /// Model days of the daily loads, the first load being on `start`.
+    pub fn model_days(&self, start: DateTime<Local>, loads: &[f64], days: usize) -> Vec<ModelDay> {
+        self.simulate(loads, days)
+            .into_iter()
+            .enumerate()
+            .map(|(day, (fitness, fatigue))| ModelDay {
+                date: start + chrono::Duration::try_days(day as i64).unwrap(),
+                fitness,
+                fatigue,
+                form: fitness - fatigue,
+                performance: self.p0 + fitness - fatigue,
+            })
+            .collect()
+    }
+
+    /// Fit the model to benchmark performances, given as the day since the first load and the
+    /// performance on that day.
+    ///
+    /// The time constants are searched on a grid of whole days, for each pair the base
+    /// performance and gains are the least squares solution. Fits with negative gains are
+    /// rejected, as they would mean training makes slower. `None` if there are too few
+    /// benchmarks or no fit is possible.
+    pub fn fit(loads: &[f64], benchmarks: &[(usize, f64)]) -> Option<BanisterModel> {
+        if benchmarks.len() < MIN_BENCHMARKS {
+            return None;
+        }
+        let days = benchmarks.iter().map(|(day, _)| *day).max()?;
+        let observations = DVector::from_iterator(
+            benchmarks.len(),
+            benchmarks.iter().map(|(_, performance)| *performance),
+        );
+        let fatigues: Vec<_> = FATIGUE_TAUS
+            .map(|tau| (tau, response(loads, tau as f64, days)))
+            .collect();
+        let mut best: Option<BanisterModel> = None;
+        for fitness_tau in FITNESS_TAUS {
+            let fitness = response(loads, fitness_tau as f64, days);
+            for (fatigue_tau, fatigue) in fatigues.iter().filter(|(tau, _)| *tau < fitness_tau) {
+                let design = DMatrix::from_fn(benchmarks.len(), 3, |i, j| {
+                    let day = benchmarks[i].0;
+                    match j {
+                        0 => 1.0,
+                        1 => fitness[day],
+                        _ => -fatigue[day],
+                    }
+                });
+                let Ok(solution) = design.clone().svd(true, true).solve(&observations, 1e-9) else {
+                    continue;
+                };
+                if solution[1] <= 0.0 || solution[2] <= 0.0 {
+                    continue;
+                }
+                let rmse = ((design * &solution - &observations).norm_squared()
+                    / benchmarks.len() as f64)
+                    .sqrt();
+                if best
+                    .as_ref()
+                    .and_then(|best| best.rmse)
+                    .map_or(true, |best| rmse < best)
+                {
+                    best = Some(BanisterModel {
+                        p0: solution[0],
+                        k1: solution[1],
+                        k2: solution[2],
+                        fitness_tau: fitness_tau as f64,
+                        fatigue_tau: *fatigue_tau as f64,
+                        rmse: Some(rmse),
+                        fitted_at: Some(Local::now()),
+                    });
+                }
+            }
+        }
+        best
+    }
+}
+
+/// A race result or test effort the model is fitted to, higher performance is better.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Benchmark {
+    pub id: i32,
+    pub day: DateTime<Local>,
+    pub performance: f64,
+    pub note: Option<String>,
+}
+
+/// Load of every day from the first activity up to and including `to`, from the daily totals.
+#[cfg(feature = "ssr")]
+pub async fn daily_loads(
+    user_id: i64,
+    to: DateTime<Local>,
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<(DateTime<Local>, f64)>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT d.dt as "dt!: DateTime<Local>", COALESCE(SUM(daily_totals.load), 0)::float8 as "load!"
+        FROM
+            generate_series(
+                (SELECT MIN(day) FROM daily_totals WHERE user_id=$1::bigint),
+                date_trunc('day', $2::timestamptz),
+                '1 day') d(dt)
+        LEFT JOIN daily_totals ON daily_totals.day = d.dt AND daily_totals.user_id=$1::bigint
+        GROUP BY d.dt
+        ORDER BY d.dt ASC
+        "#,
+        user_id,
+        to,
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().map(|row| (row.dt, row.load)).collect())
+}
+
+#[cfg(feature = "ssr")]
+pub async fn get_benchmarks(
+    user_id: i64,
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<Benchmark>, sqlx::Error> {
+    sqlx::query_as!(
+        Benchmark,
+        "SELECT id, day, performance, note FROM benchmarks WHERE user_id=$1 ORDER BY day ASC",
+        user_id as i32,
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// The fitted model of a user, or the default one if it was never fitted.
+#[cfg(feature = "ssr")]
+pub async fn get_banister_model(
+    user_id: i64,
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<BanisterModel, sqlx::Error> {
+    let model = sqlx::query_as!(
+        BanisterModel,
+        r#"
+        SELECT p0, k1, k2, fitness_tau, fatigue_tau, rmse, fitted_at as "fitted_at: DateTime<Local>"
+        FROM performance_models
+        WHERE user_id=$1
+        "#,
+        user_id as i32,
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(model.unwrap_or_default())
+}
+
+/// Fit the model of a user to their benchmarks and store it, replacing the previous fit.
+///
+/// Benchmarks before the first activity can't be explained by training and are ignored.
+#[cfg(feature = "ssr")]
+pub async fn fit_banister_model(
+    user_id: i64,
+    conn: &mut sqlx::PgConnection,
+) -> Result<Option<BanisterModel>, sqlx::Error> {
+    let benchmarks = get_benchmarks(user_id, &mut *conn).await?;
+    let Some(last) = benchmarks.last() else {
+        return Ok(None);
+    };
+    let loads = daily_loads(user_id, last.day, &mut *conn).await?;
+    let Some((start, _)) = loads.first() else {
+        return Ok(None);
+    };
+    let benchmarks: Vec<_> = benchmarks
+        .iter()
+        .filter(|benchmark| benchmark.day >= *start)
+        .map(|benchmark| {
+            (
+                (benchmark.day - *start).num_days() as usize,
+                benchmark.performance,
+            )
+        })
+        .collect();
+    let loads: Vec<_> = loads.into_iter().map(|(_, load)| load).collect();
+    let Some(model) = BanisterModel::fit(&loads, &benchmarks) else {
+        return Ok(None);
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO performance_models (user_id, p0, k1, k2, fitness_tau, fatigue_tau, rmse, fitted_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (user_id) DO UPDATE
+        SET p0=EXCLUDED.p0, k1=EXCLUDED.k1, k2=EXCLUDED.k2, fitness_tau=EXCLUDED.fitness_tau,
+            fatigue_tau=EXCLUDED.fatigue_tau, rmse=EXCLUDED.rmse, fitted_at=EXCLUDED.fitted_at
+        "#,
+        user_id as i32,
+        model.p0,
+        model.k1,
+        model.k2,
+        model.fitness_tau,
+        model.fatigue_tau,
+        model.rmse,
+        model.fitted_at,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(Some(model))
+}
+
+/// Model days of a user from their first activity up to and including `to`, which may be in
+/// the future, assuming no further training.
+#[cfg(feature = "ssr")]
+pub async fn model_days(
+    user_id: i64,
+    to: DateTime<Local>,
+    conn: &mut sqlx::PgConnection,
+) -> Result<Vec<ModelDay>, sqlx::Error> {
+    let model = get_banister_model(user_id, &mut *conn).await?;
+    let loads = daily_loads(user_id, to, &mut *conn).await?;
+    let Some((start, _)) = loads.first().copied() else {
+        return Ok(Vec::new());
+    };
+    let loads: Vec<_> = loads.into_iter().map(|(_, load)| load).collect();
+    Ok(model.model_days(start, &loads, loads.len().saturating_sub(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loads() -> Vec<f64> {
+        // alternating blocks of harder and easier training with some daily variation
+        (0..400)
+            .map(|day| ((day * 37) % 11) as f64 * 10.0 + ((day / 30) % 2) as f64 * 60.0)
+            .collect()
+    }
+
+    #[test]
+    fn responses() {
+        let model = BanisterModel::default();
+        let days = model.simulate(&[100.0], 2);
+        assert_eq!(days[0], (0.0, 0.0));
+        assert!((days[1].0 - 100.0 * (1.0 - (-1.0 / 42.0f64).exp())).abs() < 1e-9);
+        assert!((days[1].1 - 100.0 * (1.0 - (-1.0 / 7.0f64).exp())).abs() < 1e-9);
+        // no more load, both decay
+        assert!(days[2].0 < days[1].0 && days[2].1 < days[1].1);
+
+        let constant = model.simulate(&[50.0; 1000], 999);
+        assert!((constant[999].0 - 50.0).abs() < 0.1);
+        assert!((constant[999].1 - 50.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn fit_recovers_model() {
+        let truth = BanisterModel {
+            p0: 300.0,
+            k1: 1.5,
+            k2: 2.5,
+            fitness_tau: 38.0,
+            fatigue_tau: 9.0,
+            ..Default::default()
+        };
+        let loads = loads();
+        let simulated = truth.simulate(&loads, 399);
+        let benchmarks: Vec<_> = (40..400)
+            .step_by(23)
+            .map(|day| (day, truth.p0 + simulated[day].0 - simulated[day].1))
+            .collect();
+        let fit = BanisterModel::fit(&loads, &benchmarks).unwrap();
+        assert_eq!(fit.fitness_tau, 38.0);
+        assert_eq!(fit.fatigue_tau, 9.0);
+        assert!((fit.p0 - 300.0).abs() < 1e-6);
+        assert!((fit.k1 - 1.5).abs() < 1e-6);
+        assert!((fit.k2 - 2.5).abs() < 1e-6);
+        assert!(fit.rmse.unwrap() < 1e-6);
+
+        assert_eq!(BanisterModel::fit(&loads, &benchmarks[..4]), None);
+    }
+}
@@ -14,6 +14,12 @@ pub struct DatabaseEntry<S: DatabaseState, T> {
     pub extra: S,
 }
 
+impl<S: DatabaseState, T> AsRef<T> for DatabaseEntry<S, T> {
+    fn as_ref(&self) -> &T {
+        &self.state
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct New;
 #[non_exhaustive]
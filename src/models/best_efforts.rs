@@ -0,0 +1,422 @@
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+
+use super::zones::MAX_SAMPLE_GAP;
+
+/// Standard distances of best efforts in meters.
+pub const STANDARD_DISTANCES: [(i32, &str); 6] = [
+    (400, "400 m"),
+    (1000, "1 km"),
+    (5000, "5 km"),
+    (10000, "10 km"),
+    (21097, "Half Marathon"),
+    (42195, "Marathon"),
+];
+
+/// Durations of best average heart rate, speed and power in seconds.
+pub const DURATIONS: [(i32, &str); 4] = [
+    (60, "1 min"),
+    (300, "5 min"),
+    (1200, "20 min"),
+    (3600, "60 min"),
+];
+
+/// What a best effort is the best of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "ssr", derive(sqlx::Type))]
+#[cfg_attr(
+    feature = "ssr",
+    sqlx(type_name = "best_effort_kind", rename_all = "snake_case")
+)]
+pub enum BestEffortKind {
+    /// Fastest time in seconds over a standard distance.
+    Distance,
+    /// Highest average heart rate in bpm over a duration.
+    Heartrate,
+    /// Highest average speed in m/s over a duration.
+    Speed,
+    /// Highest average power in W over a duration.
+    Power,
+}
+
+impl BestEffortKind {
+    pub const ALL: [BestEffortKind; 4] = [
+        BestEffortKind::Distance,
+        BestEffortKind::Heartrate,
+        BestEffortKind::Speed,
+        BestEffortKind::Power,
+    ];
+
+    /// Value of the kind, same as in the database.
+    pub fn value(&self) -> &'static str {
+        match self {
+            BestEffortKind::Distance => "distance",
+            BestEffortKind::Heartrate => "heartrate",
+            BestEffortKind::Speed => "speed",
+            BestEffortKind::Power => "power",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BestEffortKind::Distance => "Distance",
+            BestEffortKind::Heartrate => "Heart Rate",
+            BestEffortKind::Speed => "Speed",
+            BestEffortKind::Power => "Power",
+        }
+    }
+
+    /// Targets of the kind with their labels, distances in meters or durations in seconds.
+    pub fn targets(&self) -> &'static [(i32, &'static str)] {
+        match self {
+            BestEffortKind::Distance => &STANDARD_DISTANCES,
+            _ => &DURATIONS,
+        }
+    }
+
+    pub fn target_label(&self, target: i32) -> String {
+        self.targets()
+            .iter()
+            .find(|(t, _)| *t == target)
+            .map_or_else(|| target.to_string(), |(_, label)| label.to_string())
+    }
+
+    /// Whether lower values are better, i.e. times over a distance.
+    pub fn lower_is_better(&self) -> bool {
+        *self == BestEffortKind::Distance
+    }
+
+    pub fn format(&self, value: f64) -> String {
+        match self {
+            BestEffortKind::Distance => {
+                let seconds = value.round() as i64;
+                if seconds >= 3600 {
+                    format!(
+                        "{}:{:02}:{:02}",
+                        seconds / 3600,
+                        seconds % 3600 / 60,
+                        seconds % 60
+                    )
+                } else {
+                    format!("{}:{:02}", seconds / 60, seconds % 60)
+                }
+            }
+            BestEffortKind::Heartrate => format!("{:.0} bpm", value),
+            BestEffortKind::Speed => format!("{:.1} km/h", value * 3.6),
+            BestEffortKind::Power => format!("{:.0} W", value),
+        }
+    }
+}
+
+/// Best effort of a session of an activity.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BestEffort {
+    pub activity_id: i64,
+    pub sport: String,
+    pub kind: BestEffortKind,
+    pub target: i32,
+    pub value: f64,
+    /// Start of the effort within the activity.
+    pub start_time: DateTime<Local>,
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.num_milliseconds() as f64 / 1000.0
+}
+
+/// Fastest time in seconds to cover a distance from cumulative distances, with its start.
+///
+/// The start is interpolated between samples, so the effort covers exactly the distance.
+pub fn fastest_distance(
+    samples: &[(DateTime<Local>, f64)],
+    distance: f64,
+) -> Option<(DateTime<Local>, f64)> {
+    let mut best: Option<(DateTime<Local>, f64)> = None;
+    let mut start = 0;
+    for end in 1..samples.len() {
+        let (end_time, end_distance) = samples[end];
+        // move the start up to the last sample the distance is still covered from
+        while start + 1 < end && end_distance - samples[start + 1].1 >= distance {
+            start += 1;
+        }
+        let (start_time, start_distance) = samples[start];
+        if end_distance - start_distance < distance {
+            continue;
+        }
+        let (next_time, next_distance) = samples[start + 1];
+        let fraction = if next_distance > start_distance {
+            ((end_distance - distance - start_distance) / (next_distance - start_distance))
+                .clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let offset = seconds(next_time - start_time) * fraction;
+        let time = seconds(end_time - start_time) - offset;
+        if best.map_or(true, |(_, best)| time < best) {
+            let effort_start =
+                start_time + Duration::try_milliseconds((offset * 1000.0) as i64).unwrap();
+            best = Some((effort_start, time));
+        }
+    }
+    best
+}
+
+/// Highest time weighted average over a duration in seconds, with its start.
+///
+/// Like for the time in zones, each sample counts until the next one, at most
+/// [`MAX_SAMPLE_GAP`] seconds, so pauses in recording don't count towards the duration.
+pub fn best_average(
+    samples: &[(DateTime<Local>, f64)],
+    duration: f64,
+) -> Option<(DateTime<Local>, f64)> {
+    let weights: Vec<f64> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, (timestamp, _))| {
+            samples.get(i + 1).map_or(1.0, |(next, _)| {
+                seconds(*next - *timestamp).clamp(0.0, MAX_SAMPLE_GAP)
+            })
+        })
+        .collect();
+    let mut best: Option<(DateTime<Local>, f64)> = None;
+    let mut start = 0;
+    let mut time = 0.0;
+    let mut total = 0.0;
+    for end in 0..samples.len() {
+        time += weights[end];
+        total += samples[end].1 * weights[end];
+        // drop samples from the start while the rest still lasts the duration
+        while start < end && time - weights[start] >= duration {
+            time -= weights[start];
+            total -= samples[start].1 * weights[start];
+            start += 1;
+        }
+        if time >= duration {
+            let average = total / time;
+            if best.map_or(true, |(_, best)| average > best) {
+                best = Some((samples[start].0, average));
+            }
+        }
+    }
+    best
+}
+
+/// Store the best efforts of each session of an activity, replacing the stored ones.
+#[cfg(feature = "ssr")]
+pub async fn update_best_efforts(
+    activity_id: i64,
+    conn: &mut sqlx::PgConnection,
+) -> Result<(), sqlx::Error> {
+    type Sample = (
+        DateTime<Local>,
+        Option<f64>,
+        Option<f64>,
+        Option<f64>,
+        Option<f64>,
+    );
+    let records: Vec<Sample> = sqlx::query!(
+        r#"
+        SELECT timestamp, distance::float8, heartrate::float8, speed::float8, power::float8
+        FROM records
+        WHERE activity_id=$1
+        ORDER BY timestamp
+        "#,
+        activity_id as i32,
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|record| {
+        (
+            record.timestamp.into(),
+            record.distance,
+            record.heartrate,
+            record.speed,
+            record.power,
+        )
+    })
+    .collect();
+    let sessions = sqlx::query!(
+        r#"
+        SELECT sport, start_time as "start_time: DateTime<Local>", end_time as "end_time: DateTime<Local>"
+        FROM sessions
+        WHERE activity_id=$1 AND sport <> 'transition'
+        "#,
+        activity_id as i32,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut sports = Vec::new();
+    let mut kinds = Vec::new();
+    let mut targets = Vec::new();
+    let mut values = Vec::new();
+    let mut start_times = Vec::new();
+    for session in sessions {
+        let (sport, start_time, end_time) = (session.sport, session.start_time, session.end_time);
+        let session: Vec<_> = records
+            .iter()
+            .filter(|(timestamp, ..)| start_time <= *timestamp && *timestamp <= end_time)
+            .collect();
+        let series = |value: fn(&Sample) -> Option<f64>| -> Vec<(DateTime<Local>, f64)> {
+            session
+                .iter()
+                .filter_map(|record| Some((record.0, value(record)?)))
+                .collect()
+        };
+        for kind in BestEffortKind::ALL {
+            let samples = match kind {
+                BestEffortKind::Distance => series(|record| record.1),
+                BestEffortKind::Heartrate => series(|record| record.2),
+                BestEffortKind::Speed => series(|record| record.3),
+                BestEffortKind::Power => series(|record| record.4),
+            };
+            for (target, _) in kind.targets() {
+                let effort = match kind {
+                    BestEffortKind::Distance => fastest_distance(&samples, *target as f64),
+                    _ => best_average(&samples, *target as f64),
+                };
+                if let Some((effort_start, value)) = effort {
+                    sports.push(sport.clone());
+                    kinds.push(kind.value());
+                    targets.push(*target);
+                    values.push(value);
+                    start_times.push(effort_start);
+                }
+            }
+        }
+    }
+
+    sqlx::query!(
+        "DELETE FROM best_efforts WHERE activity_id=$1",
+        activity_id as i32
+    )
+    .execute(&mut *conn)
+    .await?;
+    // sessions of the same sport, e.g. repeated swims of a multisport activity, keep the best
+    sqlx::query!(
+        r#"
+        INSERT INTO best_efforts (activity_id, sport, kind, target, value, start_time)
+        SELECT DISTINCT ON (sport, kind, target) $1, sport, kind::best_effort_kind, target, value,
+            start_time
+        FROM UNNEST($2::text[], $3::text[], $4::int4[], $5::float8[], $6::timestamptz[])
+            AS efforts(sport, kind, target, value, start_time)
+        ORDER BY sport, kind, target,
+            CASE WHEN kind = 'distance' THEN value ELSE -value END
+        "#,
+        activity_id as i32,
+        &sports[..],
+        &kinds[..] as _,
+        &targets[..],
+        &values[..],
+        &start_times[..] as _,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Best of each best effort of a user, optionally only of the activities of one calendar year.
+#[cfg(feature = "ssr")]
+pub async fn personal_records(
+    user_id: i64,
+    year: Option<i32>,
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<BestEffort>, sqlx::Error> {
+    sqlx::query_as!(
+        BestEffort,
+        r#"
+        SELECT DISTINCT ON (best_efforts.sport, best_efforts.kind, best_efforts.target)
+            activities.id AS activity_id, best_efforts.sport,
+            best_efforts.kind AS "kind: BestEffortKind", best_efforts.target, best_efforts.value, best_efforts.start_time
+        FROM best_efforts
+        JOIN activities ON activities.id = best_efforts.activity_id
+        WHERE activities.user_id = $1
+            AND ($2::int4 IS NULL OR EXTRACT(YEAR FROM activities.start_time)::int4 = $2)
+        ORDER BY best_efforts.sport, best_efforts.kind, best_efforts.target,
+            CASE WHEN best_efforts.kind = 'distance' THEN best_efforts.value
+                ELSE -best_efforts.value END,
+            best_efforts.start_time
+        "#,
+        user_id as i32,
+        year,
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Calendar years with best efforts of a user, latest first.
+#[cfg(feature = "ssr")]
+pub async fn record_years(
+    user_id: i64,
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<i32>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT DISTINCT EXTRACT(YEAR FROM activities.start_time)::int4 AS "year!"
+        FROM best_efforts
+        JOIN activities ON activities.id = best_efforts.activity_id
+        WHERE activities.user_id = $1
+        ORDER BY 1 DESC
+        "#,
+        user_id as i32,
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(values: &[(i64, f64)]) -> Vec<(DateTime<Local>, f64)> {
+        let start = Local::now();
+        values
+            .iter()
+            .map(|(second, value)| (start + Duration::try_seconds(*second).unwrap(), *value))
+            .collect()
+    }
+
+    #[test]
+    fn fastest_distances() {
+        // 4 m/s for 100 s, then 5 m/s for 100 s
+        let samples = series(
+            &(0..=200)
+                .map(|s| {
+                    (
+                        s,
+                        if s <= 100 {
+                            4.0 * s as f64
+                        } else {
+                            400.0 + 5.0 * (s - 100) as f64
+                        },
+                    )
+                })
+                .collect::<Vec<_>>(),
+        );
+        let (start, time) = fastest_distance(&samples, 400.0).unwrap();
+        assert!((time - 80.0).abs() < 1e-9);
+        assert_eq!(start, samples[100].0);
+        // the start is interpolated between samples
+        let (_, time) = fastest_distance(&samples, 402.0).unwrap();
+        assert!((time - 80.4).abs() < 1e-6);
+        assert_eq!(fastest_distance(&samples, 1000.0), None);
+    }
+
+    #[test]
+    fn best_averages() {
+        let mut values: Vec<_> = (0..60).map(|s| (s, 100.0)).collect();
+        values.extend((300..420).map(|s| (s, 150.0)));
+        values.extend((420..480).map(|s| (s, 200.0)));
+        let samples = series(&values);
+        let (start, average) = best_average(&samples, 60.0).unwrap();
+        assert_eq!(start, samples[180].0);
+        assert!((average - 200.0).abs() < 1e-9);
+        let (start, average) = best_average(&samples, 120.0).unwrap();
+        assert_eq!(start, samples[120].0);
+        assert!((average - 175.0).abs() < 1e-9);
+        // the pause in recording only counts for the longest sample gap
+        assert_eq!(best_average(&samples, 250.0), None);
+    }
+}
@@ -0,0 +1,268 @@
+use fitparser::{profile::MesgNum, FitDataRecord, Value};
+use serde::{Deserialize, Serialize};
+
+use super::base::{DatabaseEntry, ModelError, New};
+
+/// What kind of sensor a device is, as far as it matters for the recorded values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Sensor {
+    /// Heart rate measured optically by the watch itself.
+    WristHeartRate,
+    HeartRateStrap,
+    PowerMeter,
+    FootPod,
+    SpeedCadence,
+}
+
+impl Sensor {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Sensor::WristHeartRate => "Wrist heart rate",
+            Sensor::HeartRateStrap => "Heart rate strap",
+            Sensor::PowerMeter => "Power meter",
+            Sensor::FootPod => "Foot pod",
+            Sensor::SpeedCadence => "Speed/cadence sensor",
+        }
+    }
+
+    /// The values this sensor records.
+    pub fn provides(&self) -> &'static str {
+        match self {
+            Sensor::WristHeartRate | Sensor::HeartRateStrap => "Heart rate",
+            Sensor::PowerMeter => "Power",
+            Sensor::FootPod => "Speed, cadence, running dynamics",
+            Sensor::SpeedCadence => "Speed, cadence",
+        }
+    }
+}
+
+/// A device that recorded an activity, either the recording device itself or a connected sensor.
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Device {
+    /// Index of the device within the file, 0 is the recording device.
+    pub device_index: Option<i16>,
+    pub device_type: Option<String>,
+    /// How the device is connected, e.g. `local`, `antplus` or `bluetooth_low_energy`.
+    pub source_type: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_number: Option<i64>,
+    pub software_version: Option<f64>,
+}
+
+impl Device {
+    pub fn is_recording_device(&self) -> bool {
+        self.device_index == Some(0)
+    }
+
+    pub fn sensor(&self) -> Option<Sensor> {
+        match self.device_type.as_deref()? {
+            "whr" => Some(Sensor::WristHeartRate),
+            // the watch's own heart rate is a local device, straps are connected ones
+            "heart_rate" if self.source_type.as_deref() == Some("local") => {
+                Some(Sensor::WristHeartRate)
+            }
+            "heart_rate" => Some(Sensor::HeartRateStrap),
+            "bike_power" => Some(Sensor::PowerMeter),
+            "stride_speed_distance" => Some(Sensor::FootPod),
+            "bike_speed_cadence" | "bike_cadence" | "bike_speed" => Some(Sensor::SpeedCadence),
+            _ => None,
+        }
+    }
+
+    /// Whether both entries describe the same physical device.
+    pub fn same_device(&self, other: &Device) -> bool {
+        match (self.serial_number, other.serial_number) {
+            (Some(a), Some(b)) => a == b,
+            _ => {
+                self.manufacturer == other.manufacturer
+                    && self.product == other.product
+                    && self.device_type == other.device_type
+            }
+        }
+    }
+
+    fn fill_missing(&mut self, other: &Device) {
+        self.device_type = self
+            .device_type
+            .take()
+            .or_else(|| other.device_type.clone());
+        self.source_type = self
+            .source_type
+            .take()
+            .or_else(|| other.source_type.clone());
+        self.manufacturer = self
+            .manufacturer
+            .take()
+            .or_else(|| other.manufacturer.clone());
+        self.product = self.product.take().or_else(|| other.product.clone());
+        self.serial_number = self.serial_number.or(other.serial_number);
+        self.software_version = self.software_version.or(other.software_version);
+    }
+}
+
+/// Whether an activity's heart rate was measured at the wrist rather than with a strap.
+///
+/// Returns `None` if the file didn't say which devices were used.
+pub fn optical_heartrate<D: AsRef<Device>>(devices: &[D]) -> Option<bool> {
+    let sensors: Vec<Sensor> = devices.iter().filter_map(|d| d.as_ref().sensor()).collect();
+    if sensors.contains(&Sensor::HeartRateStrap) {
+        Some(false)
+    } else if sensors.contains(&Sensor::WristHeartRate) {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+/// Devices write their info several times per file, combine the messages per device index.
+pub fn combine_devices(
+    devices: Vec<DatabaseEntry<New, Device>>,
+) -> Vec<DatabaseEntry<New, Device>> {
+    let mut combined: Vec<DatabaseEntry<New, Device>> = Vec::new();
+    for device in devices {
+        let existing = combined.iter_mut().find(|d| {
+            d.state.device_index.is_some() && d.state.device_index == device.state.device_index
+        });
+        match existing {
+            Some(existing) => existing.state.fill_missing(&device.state),
+            None => combined.push(device),
+        }
+    }
+    combined
+}
+
+impl AsRef<Device> for Device {
+    fn as_ref(&self) -> &Device {
+        self
+    }
+}
+
+impl TryFrom<FitDataRecord> for DatabaseEntry<New, Device> {
+    type Error = ModelError;
+
+    fn try_from(value: FitDataRecord) -> Result<Self, Self::Error> {
+        match value.kind() {
+            MesgNum::DeviceInfo => {}
+            _ => return Err(ModelError::ParseError("Not a DeviceInfo".to_string())),
+        };
+        let fields = value.fields();
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|&f| f.name() == name)
+                .map(|f| f.clone().into_value())
+        };
+        let text = |name: &str| {
+            field(name).map(|v| match v {
+                Value::String(v) => v,
+                v => v.to_string(),
+            })
+        };
+        let device_index = field("device_index").and_then(|v| match v {
+            Value::UInt8(v) => Some(i16::from(v)),
+            Value::String(v) if v == "creator" => Some(0),
+            _ => None,
+        });
+        let serial_number = field("serial_number").and_then(|v| match v {
+            Value::UInt32(v) | Value::UInt32z(v) => Some(i64::from(v)),
+            _ => None,
+        });
+        let software_version = field("software_version").and_then(|v| match v {
+            Value::Float64(v) => Some(v),
+            Value::UInt16(v) => Some(f64::from(v) / 100.0),
+            _ => None,
+        });
+
+        Ok(DatabaseEntry {
+            state: Box::new(Device {
+                device_index,
+                device_type: text("device_type"),
+                source_type: text("source_type"),
+                manufacturer: text("manufacturer"),
+                product: text("product_name").or_else(|| text("product")),
+                serial_number,
+                software_version,
+            }),
+            extra: New,
+        })
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub async fn insert_devices(
+    devices: Vec<DatabaseEntry<New, Device>>,
+    activity_id: i64,
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<(), ModelError> {
+    let num_devices = devices.len();
+    let activity_ids: Vec<i64> = std::iter::repeat(activity_id).take(num_devices).collect();
+    let mut device_index = Vec::with_capacity(num_devices);
+    let mut device_type = Vec::with_capacity(num_devices);
+    let mut source_type = Vec::with_capacity(num_devices);
+    let mut manufacturer = Vec::with_capacity(num_devices);
+    let mut product = Vec::with_capacity(num_devices);
+    let mut serial_number = Vec::with_capacity(num_devices);
+    let mut software_version = Vec::with_capacity(num_devices);
+    for device in devices {
+        device_index.push(device.state.device_index);
+        device_type.push(device.state.device_type);
+        source_type.push(device.state.source_type);
+        manufacturer.push(device.state.manufacturer);
+        product.push(device.state.product);
+        serial_number.push(device.state.serial_number);
+        software_version.push(device.state.software_version);
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO devices(activity_id, device_index, device_type, source_type, manufacturer, product, serial_number, software_version)
+        SELECT *
+        FROM UNNEST($1::bigint[], $2::smallint[], $3::varchar[], $4::varchar[], $5::varchar[], $6::varchar[], $7::bigint[], $8::float8[])
+        "#,
+        &activity_ids[..],
+        &device_index[..] as _,
+        &device_type[..] as _,
+        &source_type[..] as _,
+        &manufacturer[..] as _,
+        &product[..] as _,
+        &serial_number[..] as _,
+        &software_version[..] as _
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| ModelError::InsertError(format!("Couldn't insert devices: {}", e)))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(index: i16, device_type: &str, source_type: &str) -> DatabaseEntry<New, Device> {
+        DatabaseEntry {
+            state: Box::new(Device {
+                device_index: Some(index),
+                device_type: Some(device_type.to_string()),
+                source_type: Some(source_type.to_string()),
+                ..Default::default()
+            }),
+            extra: New,
+        }
+    }
+
+    #[test]
+    fn optical_heartrate_unless_strap_connected() {
+        let mut devices = combine_devices(vec![
+            device(0, "whr", "local"),
+            device(1, "bike_power", "antplus"),
+            device(0, "whr", "local"),
+        ]);
+        assert_eq!(devices.len(), 2);
+        assert_eq!(optical_heartrate(&devices), Some(true));
+        devices.push(device(2, "heart_rate", "antplus"));
+        assert_eq!(optical_heartrate(&devices), Some(false));
+        assert_eq!(optical_heartrate(&devices[1..2]), None);
+    }
+}
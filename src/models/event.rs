@@ -0,0 +1,235 @@
+use chrono::{DateTime, Local};
+use fitparser::{profile::MesgNum, FitDataRecord, Value};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    base::{DatabaseEntry, ModelError, New},
+    record::Record,
+};
+
+/// Speed in m/s below which a record counts as standing still.
+const MIN_MOVING_SPEED: f64 = 0.3;
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub timestamp: DateTime<Local>,
+    /// What the event is about, e.g. `timer` or `lap`.
+    pub event: String,
+    /// What happened, e.g. `start`, `stop` or `stop_all`.
+    pub event_type: String,
+}
+
+impl Event {
+    pub fn new(timestamp: DateTime<Local>, event: &str, event_type: &str) -> Self {
+        Event {
+            timestamp,
+            event: event.to_string(),
+            event_type: event_type.to_string(),
+        }
+    }
+
+    pub fn starts_timer(&self) -> bool {
+        self.event == "timer" && self.event_type == "start"
+    }
+
+    /// Timer stops include pauses (`stop`, `stop_all`) as well as the final stop of an activity.
+    pub fn stops_timer(&self) -> bool {
+        self.event == "timer" && self.event_type.starts_with("stop")
+    }
+}
+
+impl AsRef<Event> for Event {
+    fn as_ref(&self) -> &Event {
+        self
+    }
+}
+
+impl TryFrom<FitDataRecord> for DatabaseEntry<New, Event> {
+    type Error = ModelError;
+
+    fn try_from(value: FitDataRecord) -> Result<Self, Self::Error> {
+        match value.kind() {
+            MesgNum::Event => {}
+            _ => return Err(ModelError::ParseError("Not an Event".to_string())),
+        };
+        let fields = value.fields();
+        let timestamp = fields
+            .iter()
+            .find(|&f| f.name() == "timestamp")
+            .ok_or(ModelError::ParseError("no timestamp in event".to_string()))?;
+        let timestamp = match timestamp.clone().into_value() {
+            Value::Timestamp(date) => date,
+            _ => {
+                return Err(ModelError::ParseError(
+                    "timestamp field is not a date".to_string(),
+                ))
+            }
+        };
+        let text_field = |name: &str| {
+            fields
+                .iter()
+                .find(|&f| f.name() == name)
+                .map(|f| f.clone().into_value())
+                .map(|v| match v {
+                    Value::String(v) => v,
+                    v => v.to_string(),
+                })
+        };
+        let event =
+            text_field("event").ok_or(ModelError::ParseError("no event in event".to_string()))?;
+        let event_type = text_field("event_type")
+            .ok_or(ModelError::ParseError("no event_type in event".to_string()))?;
+
+        Ok(DatabaseEntry {
+            state: Box::new(Event {
+                timestamp,
+                event,
+                event_type,
+            }),
+            extra: New,
+        })
+    }
+}
+
+/// Whether the athlete was moving from each record to the next one.
+///
+/// A record counts as moving while the timer runs and, if the record has a speed, the speed is
+/// above standing still. Without timer events the timer is assumed to run throughout.
+pub fn moving_mask<R: AsRef<Record>, E: AsRef<Event>>(records: &[R], events: &[E]) -> Vec<bool> {
+    let mut timer_events: Vec<&Event> = events
+        .iter()
+        .map(|e| e.as_ref())
+        .filter(|e| e.starts_timer() || e.stops_timer())
+        .collect();
+    timer_events.sort_by_key(|e| e.timestamp);
+    let mut next_event = 0;
+    let mut running = true;
+    records
+        .iter()
+        .map(|r| r.as_ref())
+        .map(|record| {
+            // events at the same time as the record already apply to it
+            while next_event < timer_events.len()
+                && timer_events[next_event].timestamp <= record.timestamp
+            {
+                running = timer_events[next_event].starts_timer();
+                next_event += 1;
+            }
+            running && record.speed.map_or(true, |s| s >= MIN_MOVING_SPEED)
+        })
+        .collect()
+}
+
+/// Time in seconds spent moving, see [`moving_mask`].
+///
+/// Returns `None` if there aren't enough records to tell.
+pub fn moving_time<R: AsRef<Record>, E: AsRef<Event>>(records: &[R], events: &[E]) -> Option<f64> {
+    if records.len() < 2 {
+        return None;
+    }
+    let moving = moving_mask(records, events);
+    Some(
+        records
+            .windows(2)
+            .zip(moving)
+            .filter(|(_, moving)| *moving)
+            .map(|(pair, _)| {
+                (pair[1].as_ref().timestamp - pair[0].as_ref().timestamp).num_milliseconds() as f64
+                    / 1000.0
+            })
+            .sum(),
+    )
+}
+
+#[cfg(feature = "ssr")]
+pub async fn insert_events(
+    events: Vec<DatabaseEntry<New, Event>>,
+    activity_id: i64,
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<(), ModelError> {
+    let num_events = events.len();
+    let activity_ids: Vec<i64> = std::iter::repeat(activity_id).take(num_events).collect();
+    let mut timestamp = Vec::with_capacity(num_events);
+    let mut event = Vec::with_capacity(num_events);
+    let mut event_type = Vec::with_capacity(num_events);
+    for entry in events {
+        timestamp.push(entry.state.timestamp);
+        event.push(entry.state.event);
+        event_type.push(entry.state.event_type);
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO events(activity_id, date_recorded, event, event_type)
+        SELECT *
+        FROM UNNEST($1::bigint[], $2::timestamptz[], $3::varchar[], $4::varchar[])
+        "#,
+        &activity_ids[..],
+        &timestamp[..] as _,
+        &event[..],
+        &event_type[..]
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| ModelError::InsertError(format!("Couldn't insert events: {}", e)))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+
+    fn at(start: DateTime<Local>, seconds: i64) -> DateTime<Local> {
+        start + Duration::try_seconds(seconds).unwrap()
+    }
+
+    fn record(start: DateTime<Local>, seconds: i64, speed: Option<f64>) -> Record {
+        Record {
+            timestamp: at(start, seconds),
+            heartrate: None,
+            latitude: None,
+            longitude: None,
+            distance: None,
+            speed,
+            altitude: None,
+            cadence: None,
+            power: None,
+            step_length: None,
+            vertical_oscillation: None,
+            ground_contact_time: None,
+            temperature: None,
+        }
+    }
+
+    #[test]
+    fn moving_time_excludes_pauses_and_stops() {
+        let start = Local.with_ymd_and_hms(2024, 4, 1, 10, 0, 0).unwrap();
+        let records = vec![
+            record(start, 0, Some(3.0)),
+            record(start, 10, Some(3.0)),
+            // paused from 20 until 80
+            record(start, 20, Some(3.0)),
+            record(start, 80, Some(3.0)),
+            // standing at a crossing with the timer running
+            record(start, 90, Some(0.0)),
+            record(start, 120, Some(3.0)),
+            record(start, 130, None),
+        ];
+        let events = vec![
+            Event::new(start, "timer", "start"),
+            Event::new(at(start, 20), "timer", "stop_all"),
+            Event::new(at(start, 80), "timer", "start"),
+            Event::new(at(start, 130), "timer", "stop_disable_all"),
+        ];
+        assert_eq!(
+            moving_mask(&records, &events),
+            vec![true, true, false, true, false, true, false]
+        );
+        assert_eq!(moving_time(&records, &events), Some(40.0));
+        assert_eq!(moving_time::<_, Event>(&records, &[]), Some(100.0));
+        assert_eq!(moving_time(&records[..1], &events), None);
+    }
+}
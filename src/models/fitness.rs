@@ -0,0 +1,166 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// Chronic and acute training load of a day, including that day's load.
+///
+/// Both are exponentially weighted moving averages of the daily loads, with the time constants
+/// of the user preferences.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DailyFitness {
+    pub day: DateTime<Local>,
+    pub load: f64,
+    /// Chronic training load, the fitness.
+    pub ctl: f64,
+    /// Acute training load, the fatigue.
+    pub atl: f64,
+    /// Training stress balance `ctl - atl`, positive when fresh.
+    pub form: f64,
+}
+
+/// Moving average of the previous day's value and a day's load with a time constant in days.
+pub fn moving_average(previous: f64, load: f64, days: i32) -> f64 {
+    let decay = (-1.0 / days.max(1) as f64).exp();
+    previous * decay + load * (1.0 - decay)
+}
+
+/// Chronic and acute load of each day, continuing from `previous`.
+pub fn fitness(
+    previous: (f64, f64),
+    loads: &[(DateTime<Local>, f64)],
+    ctl_days: i32,
+    atl_days: i32,
+) -> Vec<DailyFitness> {
+    loads
+        .iter()
+        .scan(previous, |(ctl, atl), &(day, load)| {
+            *ctl = moving_average(*ctl, load, ctl_days);
+            *atl = moving_average(*atl, load, atl_days);
+            Some(DailyFitness {
+                day,
+                load,
+                ctl: *ctl,
+                atl: *atl,
+                form: *ctl - *atl,
+            })
+        })
+        .collect()
+}
+
+/// Daily fitness of a user from `from` up to and including `to`, which may be in the future.
+///
+/// Missing days are calculated from the daily totals and cached, with the time constants of the
+/// current user preferences.
+#[cfg(feature = "ssr")]
+pub async fn get_daily_fitness(
+    user_id: i64,
+    from: DateTime<Local>,
+    to: DateTime<Local>,
+    conn: &mut sqlx::PgConnection,
+) -> Result<Vec<DailyFitness>, sqlx::Error> {
+    use super::{banister::daily_loads, user_preferences::get_user_preferences};
+    use itertools::MultiUnzip;
+
+    let last = sqlx::query_as!(
+        DailyFitness,
+        r#"
+        SELECT day, load, ctl, atl, ctl - atl AS "form!"
+        FROM daily_fitness
+        WHERE user_id=$1
+        ORDER BY day DESC
+        LIMIT 1
+        "#,
+        user_id as i32,
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    if last.as_ref().map_or(true, |last| last.day < to) {
+        let preferences = get_user_preferences(user_id, Local::now(), &mut *conn).await;
+        let loads: Vec<_> = daily_loads(user_id, to, &mut *conn)
+            .await?
+            .into_iter()
+            .filter(|(day, _)| last.as_ref().map_or(true, |last| *day > last.day))
+            .collect();
+        let days = fitness(
+            last.map_or((0.0, 0.0), |last| (last.ctl, last.atl)),
+            &loads,
+            preferences.ctl_days,
+            preferences.atl_days,
+        );
+        let (day, load, ctl, atl): (Vec<_>, Vec<_>, Vec<_>, Vec<_>) = days
+            .into_iter()
+            .map(|fitness| (fitness.day, fitness.load, fitness.ctl, fitness.atl))
+            .multiunzip();
+        sqlx::query!(
+            r#"
+            INSERT INTO daily_fitness (user_id, day, load, ctl, atl)
+            SELECT $1, *
+            FROM UNNEST($2::timestamptz[], $3::float8[], $4::float8[], $5::float8[])
+            ON CONFLICT (user_id, day) DO NOTHING
+            "#,
+            user_id as i32,
+            &day[..] as _,
+            &load[..],
+            &ctl[..],
+            &atl[..],
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    sqlx::query_as!(
+        DailyFitness,
+        r#"
+        SELECT day, load, ctl, atl, ctl - atl AS "form!"
+        FROM daily_fitness
+        WHERE user_id=$1 AND day >= date_trunc('day', $2::timestamptz) AND day <= $3
+        ORDER BY day ASC
+        "#,
+        user_id as i32,
+        from,
+        to,
+    )
+    .fetch_all(&mut *conn)
+    .await
+}
+
+/// Drop the cached daily fitness of a user from a day on, or all of it if `from` is `None`.
+///
+/// Has to be called whenever the daily totals or the time constants change.
+#[cfg(feature = "ssr")]
+pub async fn invalidate_daily_fitness(
+    user_id: i64,
+    from: Option<DateTime<Local>>,
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM daily_fitness WHERE user_id=$1 AND ($2::timestamptz IS NULL OR day >= $2)",
+        user_id as i32,
+        from,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moving_averages() {
+        let start = Local::now();
+        let loads: Vec<_> = (0..200)
+            .map(|day| (start + chrono::Duration::try_days(day).unwrap(), 100.0))
+            .collect();
+        let days = fitness((0.0, 0.0), &loads, 42, 7);
+        assert!((days[0].ctl - 100.0 * (1.0 - (-1.0 / 42.0f64).exp())).abs() < 1e-9);
+        assert!((days[0].atl - 100.0 * (1.0 - (-1.0 / 7.0f64).exp())).abs() < 1e-9);
+        // the acute load follows faster, until both reach the constant load
+        assert!(days[10].form < 0.0);
+        assert!((days[199].atl - 100.0).abs() < 1e-6);
+        assert!((days[199].ctl - 100.0).abs() < 1.0);
+
+        // continuing from a cached day gives the same values
+        let continued = fitness((days[99].ctl, days[99].atl), &loads[100..], 42, 7);
+        assert_eq!(continued, days[100..]);
+    }
+}
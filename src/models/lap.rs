@@ -2,6 +2,7 @@
 
 use chrono::{DateTime, Local };
 use fitparser::{profile::MesgNum, FitDataRecord, Value};
+use serde::{Deserialize, Serialize};
 #[cfg(feature = "ssr")]
 use itertools::Itertools;
 
@@ -10,7 +11,7 @@ use super::base::{DatabaseEntry, ModelError, New};
 
 
 #[non_exhaustive]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Lap {
     pub start_time: DateTime<Local>,
     pub end_time: DateTime<Local>,
@@ -192,6 +193,7 @@ pub async fn insert_laps(
 ) -> Result<(), ModelError> {
     let num_laps = laps.len();
     let activity_ids: Vec<i64> = std::iter::repeat(activity_id).take(num_laps).collect();
+    #[allow(clippy::type_complexity)]
     let (
     start_time,
     end_time,
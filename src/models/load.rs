@@ -0,0 +1,303 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use super::zones::MAX_SAMPLE_GAP;
+
+/// How the training load of an activity was calculated.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ssr", derive(sqlx::Type))]
+#[cfg_attr(
+    feature = "ssr",
+    sqlx(type_name = "load_model", rename_all = "snake_case")
+)]
+pub enum LoadModel {
+    /// From the heart rate records.
+    Heartrate,
+    /// From the average heart rate of a manually entered activity.
+    AverageHeartrate,
+    /// From the rate of perceived exertion and the duration.
+    SessionRpe,
+    /// Training stress score from the power records.
+    Power,
+    /// Running training stress score from the grade adjusted pace.
+    Pace,
+}
+
+impl LoadModel {
+    pub fn label(&self) -> &'static str {
+        match self {
+            LoadModel::Heartrate => "HR",
+            LoadModel::AverageHeartrate => "Avg. HR",
+            LoadModel::SessionRpe => "RPE",
+            LoadModel::Power => "TSS",
+            LoadModel::Pace => "rTSS",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            LoadModel::Heartrate => "Calculated from the recorded heart rate",
+            LoadModel::AverageHeartrate => "Estimated from the average heart rate",
+            LoadModel::SessionRpe => "Estimated from the perceived exertion and duration",
+            LoadModel::Power => "Training stress score from the recorded power",
+            LoadModel::Pace => "Running training stress score from the grade adjusted pace",
+        }
+    }
+
+    /// Whether the load is calculated from the records, rather than estimated from values
+    /// entered by the user.
+    pub fn from_records(&self) -> bool {
+        matches!(
+            self,
+            LoadModel::Heartrate | LoadModel::Power | LoadModel::Pace
+        )
+    }
+}
+
+/// Power based intensity of an activity, relative to the functional threshold power.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PowerMetrics {
+    pub normalized_power: u16,
+    pub intensity_factor: f64,
+    pub tss: u32,
+}
+
+impl PowerMetrics {
+    /// Power metrics of timestamped power samples.
+    pub fn from_power(samples: &[(DateTime<Local>, f64)], ftp: u32) -> Option<Self> {
+        if ftp == 0 {
+            return None;
+        }
+        let powers = per_second(samples);
+        let normalized_power = normalized_power(&powers)?;
+        let intensity_factor = normalized_power / ftp as f64;
+        let duration = powers.len() as f64;
+        let tss = duration * normalized_power * intensity_factor / (ftp as f64 * 3600.0) * 100.0;
+        Some(Self {
+            normalized_power: normalized_power.round() as u16,
+            intensity_factor,
+            tss: tss.round() as u32,
+        })
+    }
+}
+
+/// Values one second apart of timestamped samples, each sample holding until the next one.
+///
+/// Like for the time in zones, gaps longer than [`MAX_SAMPLE_GAP`] are pauses in recording and
+/// only count for that long, the last sample counts for a second.
+pub fn per_second(samples: &[(DateTime<Local>, f64)]) -> Vec<f64> {
+    let mut values = Vec::new();
+    let mut elapsed = 0.0;
+    for (i, (timestamp, value)) in samples.iter().enumerate() {
+        elapsed += samples.get(i + 1).map_or(1.0, |(next, _)| {
+            ((*next - *timestamp).num_milliseconds() as f64 / 1000.0).clamp(0.0, MAX_SAMPLE_GAP)
+        });
+        // each second takes the value of the sample at its middle
+        while values.len() as f64 + 0.5 <= elapsed {
+            values.push(*value);
+        }
+    }
+    values
+}
+
+/// Samples in the rolling average of normalized values, 30 seconds.
+const NORMALIZED_WINDOW: usize = 30;
+
+/// Normalized value of samples one second apart.
+///
+/// The fourth root of the mean of the fourth power of the 30 second rolling average, so hard
+/// efforts weigh more than their share of the average.
+pub fn normalized(values: &[f64]) -> Option<f64> {
+    if values.len() < NORMALIZED_WINDOW {
+        return None;
+    }
+    let rolling: Vec<f64> = values
+        .windows(NORMALIZED_WINDOW)
+        .map(|window| window.iter().sum::<f64>() / NORMALIZED_WINDOW as f64)
+        .collect();
+    let mean = rolling.iter().map(|v| v.powi(4)).sum::<f64>() / rolling.len() as f64;
+    Some(mean.powf(0.25))
+}
+
+/// Normalized power of power samples one second apart.
+pub fn normalized_power(powers: &[f64]) -> Option<f64> {
+    normalized(powers)
+}
+
+/// Load per minute and RPE point, used until there are enough activities with both heart rate
+/// and RPE to calibrate against.
+pub const DEFAULT_RPE_FACTOR: f64 = 0.25;
+
+/// Activities with both heart rate based load and RPE needed to calibrate the session-RPE model.
+pub const MIN_CALIBRATION_ACTIVITIES: i64 = 3;
+
+/// Session-RPE load, RPE × minutes scaled to match the heart rate based loads.
+pub fn session_rpe_load(duration: f64, rpe: u8, factor: f64) -> u32 {
+    (rpe as f64 * duration / 60.0 * factor).round() as u32
+}
+
+/// Load of an activity from the loads of its sessions, e.g. the legs of a multisport activity.
+///
+/// The loads add up, the model is the one of the session with the most load.
+pub fn combined_load(session_loads: &[Option<(u32, LoadModel)>]) -> Option<(u32, LoadModel)> {
+    let loads = session_loads.iter().flatten();
+    let total = loads.clone().map(|(load, _)| load).sum();
+    loads
+        .max_by_key(|(load, _)| *load)
+        .map(|(_, load_model)| (total, *load_model))
+}
+
+/// Ratio between the heart rate based loads and RPE × minutes of a user's activities.
+#[cfg(feature = "ssr")]
+pub async fn rpe_factor(
+    user_id: i64,
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<f64, sqlx::Error> {
+    let factor = sqlx::query_scalar!(
+        r#"
+        SELECT (SUM(load) / NULLIF(SUM(rpe * duration / 60.0), 0))::float8
+        FROM activities
+        WHERE user_id = $1
+            AND rpe IS NOT NULL
+            AND load_model = 'heartrate'
+        HAVING COUNT(*) >= $2
+        "#,
+        user_id as i32,
+        MIN_CALIBRATION_ACTIVITIES
+    )
+    .fetch_optional(executor)
+    .await?
+    .flatten();
+    Ok(factor.unwrap_or(DEFAULT_RPE_FACTOR))
+}
+
+/// Estimate the load of an activity without heart rate records, from its average heart rate or
+/// else from its perceived exertion.
+///
+/// `duration` is in seconds.
+#[cfg(feature = "ssr")]
+pub async fn estimate_load(
+    start_time: DateTime<Local>,
+    duration: f64,
+    average_heartrate: Option<u16>,
+    rpe: Option<u8>,
+    user_id: i64,
+    executor: &sqlx::PgPool,
+) -> Result<Option<(u32, LoadModel)>, sqlx::Error> {
+    use super::user_preferences::get_user_preferences;
+
+    if let Some(heartrate) = average_heartrate {
+        let preferences = get_user_preferences(user_id, start_time, executor).await;
+        return Ok(Some((
+            preferences.estimate_load(duration, heartrate),
+            LoadModel::AverageHeartrate,
+        )));
+    }
+    match rpe {
+        Some(rpe) => {
+            let factor = rpe_factor(user_id, executor).await?;
+            Ok(Some((
+                session_rpe_load(duration, rpe, factor),
+                LoadModel::SessionRpe,
+            )))
+        }
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multisport_load() {
+        assert_eq!(combined_load(&[]), None);
+        assert_eq!(combined_load(&[None, None]), None);
+        // swim by heart rate, transition without data, bike by power and run by pace
+        assert_eq!(
+            combined_load(&[
+                Some((30, LoadModel::Heartrate)),
+                None,
+                Some((120, LoadModel::Power)),
+                Some((80, LoadModel::Pace)),
+            ]),
+            Some((230, LoadModel::Power))
+        );
+    }
+
+    #[test]
+    fn session_rpe() {
+        assert_eq!(session_rpe_load(3600.0, 5, 1.0), 300);
+        assert_eq!(session_rpe_load(1800.0, 5, DEFAULT_RPE_FACTOR), 38);
+        assert_eq!(session_rpe_load(0.0, 10, DEFAULT_RPE_FACTOR), 0);
+    }
+
+    /// Samples of the values `interval` seconds apart.
+    fn samples(values: &[f64], interval: f64) -> Vec<(DateTime<Local>, f64)> {
+        let start = Local::now();
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let offset = (i as f64 * interval * 1000.0) as i64;
+                (
+                    start + chrono::Duration::try_milliseconds(offset).unwrap(),
+                    *value,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn power() {
+        let steady = samples(&[200.0; 3600], 1.0);
+        assert_eq!(normalized_power(&[200.0; 29]), None);
+        assert!((normalized_power(&[200.0; 3600]).unwrap() - 200.0).abs() < 1e-9);
+        let at_threshold = PowerMetrics::from_power(&steady, 200).unwrap();
+        assert_eq!(at_threshold.normalized_power, 200);
+        assert!((at_threshold.intensity_factor - 1.0).abs() < 1e-9);
+        assert_eq!(at_threshold.tss, 100);
+        assert_eq!(PowerMetrics::from_power(&steady, 250).unwrap().tss, 64);
+        assert_eq!(PowerMetrics::from_power(&steady, 0), None);
+
+        // intervals have a higher normalized than average power
+        let intervals: Vec<f64> = (0..3600)
+            .map(|s| if (s / 300) % 2 == 0 { 300.0 } else { 100.0 })
+            .collect();
+        assert!(normalized_power(&intervals).unwrap() > 200.0);
+    }
+
+    #[test]
+    fn power_with_smart_recording() {
+        // an hour at threshold recorded every 2 seconds
+        let steady = samples(&[200.0; 1800], 2.0);
+        assert_eq!(per_second(&steady).len(), 3599);
+        let at_threshold = PowerMetrics::from_power(&steady, 200).unwrap();
+        assert_eq!(at_threshold.normalized_power, 200);
+        assert_eq!(at_threshold.tss, 100);
+
+        // the rolling average spans 30 seconds, not 30 samples
+        let intervals: Vec<f64> = (0..720)
+            .map(|s| if (s / 60) % 2 == 0 { 300.0 } else { 100.0 })
+            .collect();
+        let every_second: Vec<f64> = (0..3596)
+            .map(|s| if (s / 300) % 2 == 0 { 300.0 } else { 100.0 })
+            .collect();
+        assert_eq!(per_second(&samples(&intervals, 5.0)), every_second);
+        assert_eq!(
+            PowerMetrics::from_power(&samples(&intervals, 5.0), 200),
+            PowerMetrics::from_power(&samples(&every_second, 1.0), 200)
+        );
+
+        // pauses in recording don't count towards the duration
+        let mut paused = samples(&[200.0; 1800], 1.0);
+        let offset =
+            paused.last().unwrap().0 - paused[0].0 + chrono::Duration::try_hours(1).unwrap();
+        let resumed: Vec<_> = paused
+            .iter()
+            .map(|(timestamp, power)| (*timestamp + offset, *power))
+            .collect();
+        paused.extend(resumed);
+        assert_eq!(PowerMetrics::from_power(&paused, 200).unwrap().tss, 100);
+    }
+}
@@ -1,6 +1,16 @@
 pub mod activity;
+pub mod aggregates;
+pub mod banister;
 pub mod base;
+pub mod best_efforts;
+pub mod device;
+pub mod event;
+pub mod fitness;
 pub mod lap;
+pub mod load;
+pub mod pace;
 pub mod record;
 pub mod session;
 pub mod user_preferences;
+pub mod workload;
+pub mod zones;
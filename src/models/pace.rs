@@ -0,0 +1,198 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    load::{normalized, per_second},
+    record::Record,
+};
+
+/// Distance over which the grade of a record is measured, in meters.
+const GRADE_DISTANCE: f64 = 20.0;
+
+/// Steeper grades are treated as this grade, the cost model isn't valid beyond it.
+const MAX_GRADE: f64 = 0.45;
+
+/// Energy cost of running at a grade, in J/kg/m (Minetti et al., 2002).
+fn running_cost(grade: f64) -> f64 {
+    155.4 * grade.powi(5) - 30.4 * grade.powi(4) - 43.3 * grade.powi(3)
+        + 46.3 * grade.powi(2)
+        + 19.5 * grade
+        + 3.6
+}
+
+/// Speed on flat ground that takes the same effort as a speed at a grade.
+pub fn grade_adjusted_speed(speed: f64, grade: f64) -> f64 {
+    let grade = grade.clamp(-MAX_GRADE, MAX_GRADE);
+    speed * running_cost(grade) / running_cost(0.0)
+}
+
+/// Grade adjusted speed of each record with a speed, in m/s, with the record's timestamp.
+///
+/// The grade is taken over the last 20 m, records without distance or altitude count as flat.
+pub fn grade_adjusted_speeds<R: AsRef<Record>>(records: &[R]) -> Vec<(DateTime<Local>, f64)> {
+    let mut start = 0;
+    records
+        .iter()
+        .enumerate()
+        .filter_map(|(i, record)| {
+            let record = record.as_ref();
+            let speed = record.speed?;
+            let grade = match (record.distance, record.altitude) {
+                (Some(distance), Some(altitude)) => {
+                    // move the start up to the last record at least GRADE_DISTANCE behind
+                    while start + 1 < i
+                        && records[start + 1]
+                            .as_ref()
+                            .distance
+                            .is_some_and(|d| distance - d >= GRADE_DISTANCE)
+                    {
+                        start += 1;
+                    }
+                    let from = records[start].as_ref();
+                    match (from.distance, from.altitude) {
+                        (Some(from_distance), Some(from_altitude))
+                            if distance - from_distance >= GRADE_DISTANCE =>
+                        {
+                            (altitude - from_altitude) / (distance - from_distance)
+                        }
+                        _ => 0.0,
+                    }
+                }
+                _ => 0.0,
+            };
+            Some((record.timestamp, grade_adjusted_speed(speed, grade)))
+        })
+        .collect()
+}
+
+/// Pace based intensity of a run, relative to the threshold pace.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PaceMetrics {
+    /// Normalized grade adjusted speed in m/s.
+    pub normalized_graded_speed: f64,
+    pub intensity_factor: f64,
+    /// Running training stress score.
+    pub rtss: u32,
+}
+
+impl PaceMetrics {
+    /// Pace metrics of timestamped grade adjusted speeds, threshold speed in m/s.
+    pub fn from_speeds(samples: &[(DateTime<Local>, f64)], threshold_speed: f64) -> Option<Self> {
+        if threshold_speed <= 0.0 {
+            return None;
+        }
+        let speeds = per_second(samples);
+        let normalized_graded_speed = normalized(&speeds)?;
+        let intensity_factor = normalized_graded_speed / threshold_speed;
+        let hours = speeds.len() as f64 / 3600.0;
+        Some(Self {
+            normalized_graded_speed,
+            intensity_factor,
+            rtss: (hours * intensity_factor.powi(2) * 100.0).round() as u32,
+        })
+    }
+
+    /// Normalized graded pace in seconds per km.
+    pub fn normalized_graded_pace(&self) -> Option<f64> {
+        (self.normalized_graded_speed > 0.0).then(|| 1000.0 / self.normalized_graded_speed)
+    }
+}
+
+/// Format a pace in seconds per km as m:ss.
+pub fn format_pace(seconds: f64) -> String {
+    let seconds = seconds.round() as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Parse a pace given as m:ss into seconds per km.
+pub fn parse_pace(pace: &str) -> Option<i32> {
+    let (minutes, seconds) = pace.trim().split_once(':')?;
+    let minutes: i32 = minutes.parse().ok()?;
+    let seconds: i32 = seconds.parse().ok()?;
+    (minutes >= 0 && (0..60).contains(&seconds) && minutes * 60 + seconds > 0)
+        .then_some(minutes * 60 + seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn record(second: i64, distance: f64, altitude: f64, speed: f64) -> Record {
+        Record {
+            timestamp: Local::now() + Duration::try_seconds(second).unwrap(),
+            heartrate: None,
+            latitude: None,
+            longitude: None,
+            distance: Some(distance),
+            speed: Some(speed),
+            altitude: Some(altitude),
+            cadence: None,
+            power: None,
+            step_length: None,
+            vertical_oscillation: None,
+            ground_contact_time: None,
+            temperature: None,
+        }
+    }
+
+    #[test]
+    fn grade_adjustment() {
+        assert_eq!(grade_adjusted_speed(3.0, 0.0), 3.0);
+        assert!(grade_adjusted_speed(3.0, 0.1) > 3.0);
+        assert!(grade_adjusted_speed(3.0, -0.05) < 3.0);
+        assert_eq!(
+            grade_adjusted_speed(3.0, 1.0),
+            grade_adjusted_speed(3.0, MAX_GRADE)
+        );
+
+        let flat: Vec<_> = (0..60)
+            .map(|s| record(s, s as f64 * 3.0, 100.0, 3.0))
+            .collect();
+        assert!(grade_adjusted_speeds(&flat).iter().all(|&(_, s)| s == 3.0));
+        let uphill: Vec<_> = (0..60)
+            .map(|s| record(s, s as f64 * 3.0, 100.0 + s as f64 * 0.3, 3.0))
+            .collect();
+        let speeds = grade_adjusted_speeds(&uphill);
+        assert_eq!(speeds.len(), 60);
+        assert_eq!(speeds[0], (uphill[0].timestamp, 3.0));
+        assert!((speeds[59].1 - grade_adjusted_speed(3.0, 0.1)).abs() < 1e-9);
+    }
+
+    /// Speeds `interval` seconds apart for an hour.
+    fn speeds(speed: f64, interval: i64) -> Vec<(DateTime<Local>, f64)> {
+        let start = Local::now();
+        (0..3600 / interval)
+            .map(|i| (start + Duration::try_seconds(i * interval).unwrap(), speed))
+            .collect()
+    }
+
+    #[test]
+    fn rtss() {
+        let at_threshold = PaceMetrics::from_speeds(&speeds(4.0, 1), 4.0).unwrap();
+        assert_eq!(at_threshold.rtss, 100);
+        assert_eq!(at_threshold.normalized_graded_pace(), Some(250.0));
+        assert_eq!(
+            PaceMetrics::from_speeds(&speeds(3.0, 1), 4.0).unwrap().rtss,
+            56
+        );
+        assert_eq!(PaceMetrics::from_speeds(&speeds(3.0, 1), 0.0), None);
+
+        // smart recording, records are seconds apart
+        let smart = PaceMetrics::from_speeds(&speeds(4.0, 5), 4.0).unwrap();
+        assert!((smart.normalized_graded_speed - 4.0).abs() < 1e-9);
+        assert_eq!(smart.rtss, 100);
+    }
+
+    #[test]
+    fn pace_format() {
+        assert_eq!(format_pace(270.0), "4:30");
+        assert_eq!(format_pace(305.4), "5:05");
+        assert_eq!(parse_pace("4:30"), Some(270));
+        assert_eq!(parse_pace(" 5:05 "), Some(305));
+        assert_eq!(parse_pace("4:60"), None);
+        assert_eq!(parse_pace("0:00"), None);
+        assert_eq!(parse_pace("430"), None);
+    }
+}
@@ -1,12 +1,11 @@
 use chrono::{DateTime, Local};
-use fitparser::{profile::MesgNum, FitDataRecord, Value};
-#[cfg(feature = "ssr")]
-use itertools::Itertools;
+use fitparser::{profile::MesgNum, FitDataField, FitDataRecord, Value};
+use serde::{Deserialize, Serialize};
 
 use super::base::{DatabaseEntry, ModelError, New};
 
 #[non_exhaustive]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Record {
     pub timestamp: DateTime<Local>,
     pub heartrate: Option<i16>,
@@ -15,11 +14,66 @@ pub struct Record {
     pub distance: Option<f64>,
     pub speed: Option<f64>,
     pub altitude: Option<f64>,
+    /// Cadence in rpm, including the fractional part if the device records it.
+    pub cadence: Option<f64>,
+    pub power: Option<i16>,
+    /// Step length in mm.
+    pub step_length: Option<f64>,
+    /// Vertical oscillation in mm.
+    pub vertical_oscillation: Option<f64>,
+    /// Ground contact time in ms.
+    pub ground_contact_time: Option<f64>,
+    /// Temperature in °C.
+    pub temperature: Option<i16>,
+}
+
+impl Record {
+    /// Fill in values missing from this record with the values of another record of the same
+    /// moment, e.g. recorded by a different device.
+    pub fn fill_missing(&mut self, other: &Record) {
+        self.heartrate = self.heartrate.or(other.heartrate);
+        self.latitude = self.latitude.or(other.latitude);
+        self.longitude = self.longitude.or(other.longitude);
+        self.distance = self.distance.or(other.distance);
+        self.speed = self.speed.or(other.speed);
+        self.altitude = self.altitude.or(other.altitude);
+        self.cadence = self.cadence.or(other.cadence);
+        self.power = self.power.or(other.power);
+        self.step_length = self.step_length.or(other.step_length);
+        self.vertical_oscillation = self.vertical_oscillation.or(other.vertical_oscillation);
+        self.ground_contact_time = self.ground_contact_time.or(other.ground_contact_time);
+        self.temperature = self.temperature.or(other.temperature);
+    }
+}
+
+impl AsRef<Record> for Record {
+    fn as_ref(&self) -> &Record {
+        self
+    }
 }
 
 fn int_to_coord(value: i32) -> f64 {
     value as f64 / (u64::pow(2, 32) as f64 / 360.0)
 }
+
+/// Read a numeric field, scaled fields are decoded as floats while unscaled ones keep their
+/// integer type.
+fn numeric_field(fields: &[FitDataField], name: &str) -> Option<f64> {
+    fields
+        .iter()
+        .find(|&f| f.name() == name)
+        .map(|f| f.clone().into_value())
+        .and_then(|v| match v {
+            Value::Float64(v) => Some(v),
+            Value::Float32(v) => Some(v as f64),
+            Value::UInt8(v) => Some(v as f64),
+            Value::SInt8(v) => Some(v as f64),
+            Value::UInt16(v) => Some(v as f64),
+            Value::SInt16(v) => Some(v as f64),
+            Value::UInt32(v) => Some(v as f64),
+            _ => None,
+        })
+}
 impl TryFrom<FitDataRecord> for DatabaseEntry<New, Record> {
     type Error = ModelError;
 
@@ -93,6 +147,14 @@ impl TryFrom<FitDataRecord> for DatabaseEntry<New, Record> {
             _ => None,
         });
 
+        let cadence = numeric_field(fields, "cadence")
+            .map(|c| c + numeric_field(fields, "fractional_cadence").unwrap_or(0.0));
+        let power = numeric_field(fields, "power").map(|p| p as i16);
+        let step_length = numeric_field(fields, "step_length");
+        let vertical_oscillation = numeric_field(fields, "vertical_oscillation");
+        let ground_contact_time = numeric_field(fields, "stance_time");
+        let temperature = numeric_field(fields, "temperature").map(|t| t as i16);
+
         Ok(DatabaseEntry {
             state: Box::new(Record {
                 timestamp,
@@ -102,6 +164,12 @@ impl TryFrom<FitDataRecord> for DatabaseEntry<New, Record> {
                 altitude,
                 distance,
                 speed,
+                cadence,
+                power,
+                step_length,
+                vertical_oscillation,
+                ground_contact_time,
+                temperature,
             }),
             extra: New,
         })
@@ -115,35 +183,42 @@ pub async fn insert_records(
 ) -> Result<(), ModelError> {
     let num_records = records.len();
     let activity_ids: Vec<i64> = std::iter::repeat(activity_id).take(num_records).collect();
-    let (timestamp, heartrate, distance, speed, altitude, latitude, longitude): (
-        Vec<_>,
-        Vec<_>,
-        Vec<_>,
-        Vec<_>,
-        Vec<_>,
-        Vec<_>,
-        Vec<_>,
-    ) = records
-        .into_iter()
-        .map(|r| {
-            (
-                r.state.timestamp,
-                r.state.heartrate,
-                r.state.distance,
-                r.state.speed,
-                r.state.altitude,
-                r.state.latitude,
-                r.state.longitude,
-            )
-        })
-        .multiunzip();
+    let mut timestamp = Vec::with_capacity(num_records);
+    let mut heartrate = Vec::with_capacity(num_records);
+    let mut distance = Vec::with_capacity(num_records);
+    let mut speed = Vec::with_capacity(num_records);
+    let mut altitude = Vec::with_capacity(num_records);
+    let mut latitude = Vec::with_capacity(num_records);
+    let mut longitude = Vec::with_capacity(num_records);
+    let mut cadence = Vec::with_capacity(num_records);
+    let mut power = Vec::with_capacity(num_records);
+    let mut step_length = Vec::with_capacity(num_records);
+    let mut vertical_oscillation = Vec::with_capacity(num_records);
+    let mut ground_contact_time = Vec::with_capacity(num_records);
+    let mut temperature = Vec::with_capacity(num_records);
+    for record in records {
+        timestamp.push(record.state.timestamp);
+        heartrate.push(record.state.heartrate);
+        distance.push(record.state.distance);
+        speed.push(record.state.speed);
+        altitude.push(record.state.altitude);
+        latitude.push(record.state.latitude);
+        longitude.push(record.state.longitude);
+        cadence.push(record.state.cadence);
+        power.push(record.state.power);
+        step_length.push(record.state.step_length);
+        vertical_oscillation.push(record.state.vertical_oscillation);
+        ground_contact_time.push(record.state.ground_contact_time);
+        temperature.push(record.state.temperature);
+    }
     sqlx::query!(
         r#"
-        INSERT INTO records(activity_id, timestamp, heartrate, distance, speed, altitude, latitude, longitude)
+        INSERT INTO records(activity_id, timestamp, heartrate, distance, speed, altitude, latitude, longitude, cadence, power, step_length, vertical_oscillation, ground_contact_time, temperature)
         SELECT *
-        FROM UNNEST($1::bigint[], $2::timestamptz[], $3::smallint[], $4::float8[], $5::float8[], $6::float8[], $7::float8[], $8::float8[])
+        FROM UNNEST($1::bigint[], $2::timestamptz[], $3::smallint[], $4::float8[], $5::float8[], $6::float8[], $7::float8[], $8::float8[], $9::float8[], $10::smallint[], $11::float8[], $12::float8[], $13::float8[], $14::smallint[])
         "#,
-        &activity_ids[..],&timestamp[..], &heartrate[..] as _, &distance[..] as _, &speed[..] as _, &altitude[..] as _, &latitude[..] as _, &longitude[..] as _).execute(executor).await
+        &activity_ids[..],&timestamp[..], &heartrate[..] as _, &distance[..] as _, &speed[..] as _, &altitude[..] as _, &latitude[..] as _, &longitude[..] as _,
+        &cadence[..] as _, &power[..] as _, &step_length[..] as _, &vertical_oscillation[..] as _, &ground_contact_time[..] as _, &temperature[..] as _).execute(executor).await
         .map_err(|e| ModelError::InsertError(format!("Couldn't insert records: {}", e)))?;
 
     Ok(())
@@ -1,16 +1,11 @@
-
-
 use chrono::{DateTime, Local};
 use fitparser::{profile::MesgNum, FitDataRecord, Value};
-#[cfg(feature = "ssr")]
-use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 
 use super::base::{DatabaseEntry, ModelError, New};
 
-
-
 #[non_exhaustive]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
     pub start_time: DateTime<Local>,
     pub end_time: DateTime<Local>,
@@ -25,6 +20,26 @@ pub struct Session {
     pub descent: Option<i32>,
     pub average_speed: Option<f64>,
     pub max_speed: Option<f64>,
+    /// Training load of the records within the session.
+    #[serde(default)]
+    pub load: Option<u32>,
+}
+
+impl Session {
+    /// Transitions between the sports of a multisport activity.
+    pub fn is_transition(&self) -> bool {
+        self.sport.as_deref() == Some("transition")
+    }
+
+    pub fn contains(&self, timestamp: DateTime<Local>) -> bool {
+        self.start_time <= timestamp && timestamp <= self.end_time
+    }
+}
+
+impl AsRef<Session> for Session {
+    fn as_ref(&self) -> &Session {
+        self
+    }
 }
 
 impl TryFrom<FitDataRecord> for DatabaseEntry<New, Session> {
@@ -180,6 +195,7 @@ impl TryFrom<FitDataRecord> for DatabaseEntry<New, Session> {
                 average_power,
                 average_speed,
                 max_speed,
+                load: None,
             }),
             extra: New,
         })
@@ -193,59 +209,41 @@ pub async fn insert_sessions(
 ) -> Result<(), ModelError> {
     let num_sessions = sessions.len();
     let activity_ids: Vec<i64> = std::iter::repeat(activity_id).take(num_sessions).collect();
-    let (
-    start_time,
-    end_time,
-    sport,
-    distance,
-    calories,
-    average_heartrate,
-    min_heartrate,
-    max_heartrate,
-    average_power,
-    ascent,
-    descent,
-    average_speed,
-    
-    ): (
-        Vec<_>,
-        Vec<_>,
-        Vec<_>,
-        Vec<_>,
-        Vec<_>,
-        Vec<_>,
-        Vec<_>,
-        Vec<_>,
-        Vec<_>,
-        Vec<_>,
-        Vec<_>,
-        Vec<_>,
-    ) = sessions.clone()
-        .into_iter()
-        .map(|r| {
-            (
-                r.state.start_time,
-                r.state.end_time,
-                r.state.sport,
-                r.state.distance,
-                r.state.calories,
-                r.state.average_heartrate,
-                r.state.min_heartrate,
-                r.state.max_heartrate,
-                r.state.average_power,
-                r.state.ascent,
-                r.state.descent,
-                r.state.average_speed,
-            )
-        })
-        .multiunzip();
-    // itertools only supports up to 12 iterators, so we do this one separately
-    let max_speed: Vec<_> = sessions.into_iter().map(|r|r.state.max_speed).collect();
+    let mut start_time = Vec::with_capacity(num_sessions);
+    let mut end_time = Vec::with_capacity(num_sessions);
+    let mut sport = Vec::with_capacity(num_sessions);
+    let mut distance = Vec::with_capacity(num_sessions);
+    let mut calories = Vec::with_capacity(num_sessions);
+    let mut average_heartrate = Vec::with_capacity(num_sessions);
+    let mut min_heartrate = Vec::with_capacity(num_sessions);
+    let mut max_heartrate = Vec::with_capacity(num_sessions);
+    let mut average_power = Vec::with_capacity(num_sessions);
+    let mut ascent = Vec::with_capacity(num_sessions);
+    let mut descent = Vec::with_capacity(num_sessions);
+    let mut average_speed = Vec::with_capacity(num_sessions);
+    let mut max_speed = Vec::with_capacity(num_sessions);
+    let mut load = Vec::with_capacity(num_sessions);
+    for session in sessions {
+        start_time.push(session.state.start_time);
+        end_time.push(session.state.end_time);
+        sport.push(session.state.sport);
+        distance.push(session.state.distance);
+        calories.push(session.state.calories);
+        average_heartrate.push(session.state.average_heartrate);
+        min_heartrate.push(session.state.min_heartrate);
+        max_heartrate.push(session.state.max_heartrate);
+        average_power.push(session.state.average_power);
+        ascent.push(session.state.ascent);
+        descent.push(session.state.descent);
+        average_speed.push(session.state.average_speed);
+        max_speed.push(session.state.max_speed);
+        load.push(session.state.load.map(|l| l as i32));
+    }
     sqlx::query!(
         r#"
-        INSERT INTO sessions(activity_id,start_time,end_time,sport,distance,calories,average_heartrate,min_heartrate,max_heartrate,average_power,ascent,descent,average_speed,max_speed)
+        INSERT INTO sessions(activity_id,start_time,end_time,sport,distance,calories,average_heartrate,min_heartrate,max_heartrate,average_power,ascent,descent,average_speed,max_speed,load)
         SELECT *
-        FROM UNNEST($1::bigint[], $2::timestamptz[],$3::timestamptz[], $4::varchar[], $5::float8[], $6::int[], $7::smallint[], $8::smallint[], $9::smallint[], $10::int[], $11::int[], $12::int[], $13::float8[], $14::float8[])
+        FROM UNNEST($1::bigint[], $2::timestamptz[],$3::timestamptz[], $4::varchar[], $5::float8[], $6::int[], $7::smallint[], $8::smallint[], $9::smallint[], $10::int[], $11::int[], $12::int[], $13::float8[], $14::float8[], $15::int[])
         "#,
         &activity_ids[..],
         &start_time[..] as _,
@@ -261,9 +259,38 @@ pub async fn insert_sessions(
         &descent[..] as _,
         &average_speed[..] as _,
         &max_speed[..] as _,
-        
-    ).execute(executor).await
-        .map_err(|e| ModelError::InsertError(format!("Couldn't insert session: {}", e)))?;
+        &load[..] as _
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| ModelError::InsertError(format!("Couldn't insert session: {}", e)))?;
+
+    Ok(())
+}
+
+/// Update the training load of already stored sessions, identified by their start time.
+#[cfg(feature = "ssr")]
+pub async fn update_session_loads(
+    sessions: &[Session],
+    activity_id: i64,
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<(), ModelError> {
+    let start_time: Vec<_> = sessions.iter().map(|s| s.start_time).collect();
+    let load: Vec<_> = sessions.iter().map(|s| s.load.map(|l| l as i32)).collect();
+    sqlx::query!(
+        r#"
+        UPDATE sessions
+        SET load=loads.load
+        FROM UNNEST($2::timestamptz[], $3::int[]) AS loads(start_time, load)
+        WHERE sessions.activity_id=$1 AND sessions.start_time=loads.start_time
+        "#,
+        activity_id as i32,
+        &start_time[..] as _,
+        &load[..] as _
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| ModelError::InsertError(format!("Couldn't update session load: {}", e)))?;
 
     Ok(())
 }
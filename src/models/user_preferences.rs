@@ -3,6 +3,8 @@ use std::collections::HashMap;
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 
+use super::zones::{Zone, ZoneModel};
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct UserPreferences {
     pub user_id: i64,
@@ -13,6 +15,62 @@ pub struct UserPreferences {
     pub max_heartrate: i32,
     pub tau: f64,
     pub c: f64,
+    /// Functional threshold power in watts.
+    #[serde(default)]
+    pub ftp: Option<i32>,
+    /// Sports whose load comes from power instead of heart rate.
+    #[serde(default)]
+    pub power_load_sports: Vec<String>,
+    /// Threshold pace in seconds per km.
+    #[serde(default)]
+    pub threshold_pace: Option<i32>,
+    /// Sports whose load comes from the grade adjusted pace instead of heart rate.
+    #[serde(default)]
+    pub pace_load_sports: Vec<String>,
+    #[serde(default)]
+    pub zone_model: ZoneModel,
+    #[serde(default)]
+    pub resting_heartrate: Option<i32>,
+    /// Lactate threshold heart rate, the anaerobic threshold is used when not set.
+    #[serde(default)]
+    pub lactate_threshold_heartrate: Option<i32>,
+    /// Time constant of the chronic training load in days.
+    #[serde(default = "default_ctl_days")]
+    pub ctl_days: i32,
+    /// Time constant of the acute training load in days.
+    #[serde(default = "default_atl_days")]
+    pub atl_days: i32,
+    /// Safe band of the acute:chronic workload ratio.
+    #[serde(default = "default_acwr_low")]
+    pub acwr_low: f64,
+    #[serde(default = "default_acwr_high")]
+    pub acwr_high: f64,
+    /// Weekly monotony above which to warn.
+    #[serde(default = "default_monotony_threshold")]
+    pub monotony_threshold: f64,
+    /// Weekly strain above which to warn, no warning if not set.
+    #[serde(default)]
+    pub strain_threshold: Option<f64>,
+}
+
+fn default_ctl_days() -> i32 {
+    42
+}
+
+fn default_atl_days() -> i32 {
+    7
+}
+
+fn default_acwr_low() -> f64 {
+    0.8
+}
+
+fn default_acwr_high() -> f64 {
+    1.3
+}
+
+fn default_monotony_threshold() -> f64 {
+    2.0
 }
 
 impl UserPreferences {
@@ -37,6 +95,48 @@ impl UserPreferences {
             .sum::<f64>()
             .round() as u32
     }
+
+    /// Whether the load of a sport comes from power, which needs an FTP to be set
+    pub fn uses_power(&self, sport: Option<&str>) -> bool {
+        self.ftp.is_some_and(|ftp| ftp > 0)
+            && sport.is_some_and(|sport| self.power_load_sports.iter().any(|s| s == sport))
+    }
+
+    /// Whether the load of a sport comes from pace, which needs a threshold pace to be set
+    pub fn uses_pace(&self, sport: Option<&str>) -> bool {
+        self.threshold_pace.is_some_and(|pace| pace > 0)
+            && sport.is_some_and(|sport| self.pace_load_sports.iter().any(|s| s == sport))
+    }
+
+    /// Threshold pace as a speed in m/s
+    pub fn threshold_speed(&self) -> Option<f64> {
+        self.threshold_pace
+            .filter(|&pace| pace > 0)
+            .map(|pace| 1000.0 / pace as f64)
+    }
+
+    pub fn lactate_threshold_heartrate(&self) -> i32 {
+        self.lactate_threshold_heartrate
+            .filter(|&lthr| lthr > 0)
+            .unwrap_or(self.anaerobic_threshold)
+    }
+
+    /// Heart rate zones of the selected zone model
+    pub fn zones(&self) -> Vec<Zone> {
+        self.zone_model.zones(self)
+    }
+
+    /// Estimate the training load of an activity from its average heartrate
+    ///
+    /// The average heartrate is assumed for the whole duration in seconds, which gives the same
+    /// load as [`Self::calculate_load`] of a record per second at that heartrate.
+    pub fn estimate_load(&self, duration: f64, average_heartrate: u16) -> u32 {
+        if average_heartrate as f64 <= self.max_heartrate as f64 * 0.55 {
+            return 0;
+        }
+        ((self.c * (self.tau * average_heartrate as f64).exp() + 1.0) * duration.round() / 60.0)
+            .round() as u32
+    }
 }
 
 impl Default for UserPreferences {
@@ -50,6 +150,19 @@ impl Default for UserPreferences {
             max_heartrate: 183,
             tau: 0.0809749,
             c: 0.000002370473,
+            ftp: None,
+            power_load_sports: Vec::new(),
+            threshold_pace: None,
+            pace_load_sports: Vec::new(),
+            zone_model: ZoneModel::default(),
+            resting_heartrate: None,
+            lactate_threshold_heartrate: None,
+            ctl_days: default_ctl_days(),
+            atl_days: default_atl_days(),
+            acwr_low: default_acwr_low(),
+            acwr_high: default_acwr_high(),
+            monotony_threshold: default_monotony_threshold(),
+            strain_threshold: None,
         }
     }
 }
@@ -60,24 +173,35 @@ pub async fn get_user_preferences(
     date: DateTime<Local>,
     executor: impl sqlx::PgExecutor<'_>,
 ) -> UserPreferences {
-    let result = sqlx::query_as!(
+    sqlx::query_as!(
         UserPreferences,
         r#"
         SELECT
-            user_id,
+            user_id::int8 as "user_id!",
             start_time as "start_time:DateTime<Local>",
             end_time as "end_time:DateTime<Local>",
             aerobic_threshold,
             anaerobic_threshold,
             max_heartrate,
             tau,
-            c
+            c,
+            ftp,
+            power_load_sports,
+            threshold_pace,
+            pace_load_sports,
+            zone_model as "zone_model: ZoneModel",
+            resting_heartrate,
+            lactate_threshold_heartrate,
+            ctl_days,
+            atl_days,
+            acwr_low,
+            acwr_high,
+            monotony_threshold,
+            strain_threshold
         FROM user_preferences
-        WHERE user_id=$1 
-            and (start_time IS NULL and end_time IS NULL) 
-            OR (start_time IS NULL and $2 < end_time) 
-            OR (start_time <= $2 and end_time IS NULL) 
-            OR (start_time <= $2 and $2 < end_time)
+        WHERE user_id=$1
+            AND (start_time IS NULL OR start_time <= $2)
+            AND (end_time IS NULL OR $2 < end_time)
         LIMIT 1
         "#,
         user_id as i32,
@@ -86,6 +210,49 @@ pub async fn get_user_preferences(
     .fetch_optional(executor)
     .await
     .expect("couldn't query user prefences")
-    .unwrap_or_default();
-    return result;
+    .unwrap_or_default()
+}
+
+/// All preference periods of a user, oldest first.
+#[cfg(feature = "ssr")]
+pub async fn get_user_preferences_history(
+    user_id: i64,
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<UserPreferences>, sqlx::Error> {
+    sqlx::query_as!(
+        UserPreferences,
+        r#"
+        SELECT user_id::int8 as "user_id!", start_time as "start_time:DateTime<Local>",
+            end_time as "end_time:DateTime<Local>", aerobic_threshold, anaerobic_threshold,
+            max_heartrate, tau, c, ftp, power_load_sports, threshold_pace, pace_load_sports,
+            zone_model as "zone_model: ZoneModel", resting_heartrate, lactate_threshold_heartrate, ctl_days, atl_days,
+            acwr_low, acwr_high, monotony_threshold, strain_threshold
+        FROM user_preferences
+        WHERE user_id=$1
+        ORDER BY start_time NULLS FIRST
+        "#,
+        user_id as i32
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UserPreferences;
+
+    #[test]
+    fn estimate_load() {
+        let preferences = UserPreferences::default();
+        assert_eq!(
+            preferences.estimate_load(3600.0, 150),
+            preferences.calculate_load(vec![150; 3600])
+        );
+        assert_eq!(
+            preferences.estimate_load(3600.0, 90),
+            preferences.calculate_load(vec![90; 3600])
+        );
+        assert!(preferences.estimate_load(1800.0, 150) < preferences.estimate_load(3600.0, 150));
+        assert!(preferences.estimate_load(3600.0, 120) < preferences.estimate_load(3600.0, 150));
+    }
 }
@@ -0,0 +1,150 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use super::user_preferences::UserPreferences;
+
+/// Days of the acute load, also the week monotony and strain are calculated over.
+pub const ACUTE_DAYS: usize = 7;
+/// Days of the chronic load.
+pub const CHRONIC_DAYS: usize = 28;
+
+/// Foster's monotony and strain and the acute:chronic workload ratio of a day, each `None`
+/// until there are enough days of load or if it is undefined.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Workload {
+    pub day: DateTime<Local>,
+    pub load: f64,
+    /// Mean daily load of the last week divided by its standard deviation.
+    pub monotony: Option<f64>,
+    /// Total load of the last week multiplied by the monotony.
+    pub strain: Option<f64>,
+    /// Mean daily load of the last week divided by that of the last four weeks.
+    pub acwr: Option<f64>,
+}
+
+/// Workload of each day of consecutive daily loads, the windows ending with that day.
+pub fn workload(loads: &[(DateTime<Local>, f64)]) -> Vec<Workload> {
+    loads
+        .iter()
+        .enumerate()
+        .map(|(i, &(day, load))| {
+            let window = |days: usize| {
+                (i + 1 >= days).then(|| {
+                    loads[i + 1 - days..=i]
+                        .iter()
+                        .map(|(_, load)| *load)
+                        .collect::<Vec<_>>()
+                })
+            };
+            let week = window(ACUTE_DAYS);
+            let (monotony, strain) = week
+                .as_ref()
+                .and_then(|week| {
+                    let total: f64 = week.iter().sum();
+                    let mean = total / week.len() as f64;
+                    // sample standard deviation, like Foster's spreadsheets
+                    let deviation = (week.iter().map(|load| (load - mean).powi(2)).sum::<f64>()
+                        / (week.len() - 1) as f64)
+                        .sqrt();
+                    (deviation > 0.0).then(|| (mean / deviation, total * mean / deviation))
+                })
+                .unzip();
+            let acwr = week.zip(window(CHRONIC_DAYS)).and_then(|(week, month)| {
+                let acute = week.iter().sum::<f64>() / week.len() as f64;
+                let chronic = month.iter().sum::<f64>() / month.len() as f64;
+                (chronic > 0.0).then_some(acute / chronic)
+            });
+            Workload {
+                day,
+                load,
+                monotony,
+                strain,
+                acwr,
+            }
+        })
+        .collect()
+}
+
+/// Warning thresholds of the workload metrics from the user preferences.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WorkloadThresholds {
+    /// Lower bound of the safe band of the ACWR.
+    pub acwr_low: f64,
+    /// Upper bound of the safe band of the ACWR.
+    pub acwr_high: f64,
+    pub monotony: f64,
+    pub strain: Option<f64>,
+}
+
+impl From<&UserPreferences> for WorkloadThresholds {
+    fn from(preferences: &UserPreferences) -> Self {
+        Self {
+            acwr_low: preferences.acwr_low,
+            acwr_high: preferences.acwr_high,
+            monotony: preferences.monotony_threshold,
+            strain: preferences.strain_threshold,
+        }
+    }
+}
+
+impl WorkloadThresholds {
+    /// Whether the ACWR is outside of the safe band.
+    pub fn acwr_warning(&self, acwr: f64) -> bool {
+        acwr < self.acwr_low || acwr > self.acwr_high
+    }
+
+    pub fn monotony_warning(&self, monotony: f64) -> bool {
+        monotony > self.monotony
+    }
+
+    pub fn strain_warning(&self, strain: f64) -> bool {
+        self.strain.is_some_and(|threshold| strain > threshold)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn days(loads: &[f64]) -> Vec<(DateTime<Local>, f64)> {
+        let start = Local::now();
+        loads
+            .iter()
+            .enumerate()
+            .map(|(day, load)| {
+                (
+                    start + chrono::Duration::try_days(day as i64).unwrap(),
+                    *load,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn monotony_and_strain() {
+        let week = workload(&days(&[100.0, 0.0, 100.0, 0.0, 100.0, 0.0, 100.0]));
+        assert_eq!(week[5].monotony, None);
+        let monotony = week[6].monotony.unwrap();
+        // mean 57.14, sample standard deviation 53.45
+        assert!((monotony - 1.069).abs() < 1e-3);
+        assert!((week[6].strain.unwrap() - 400.0 * monotony).abs() < 1e-9);
+        // the same load every day has no variation
+        assert_eq!(workload(&days(&[50.0; 7]))[6].monotony, None);
+    }
+
+    #[test]
+    fn acute_chronic_ratio() {
+        let mut loads = vec![50.0; 21];
+        loads.extend([100.0; 7]);
+        let workload = workload(&days(&loads));
+        assert_eq!(workload[26].acwr, None);
+        // acute 100, chronic (21 * 50 + 7 * 100) / 28 = 62.5
+        assert!((workload[27].acwr.unwrap() - 1.6).abs() < 1e-9);
+
+        let thresholds = WorkloadThresholds::from(&UserPreferences::default());
+        assert!(thresholds.acwr_warning(1.6));
+        assert!(!thresholds.acwr_warning(1.0));
+        assert!(thresholds.acwr_warning(0.5));
+        assert!(!thresholds.strain_warning(10000.0));
+    }
+}
@@ -0,0 +1,323 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use super::user_preferences::UserPreferences;
+
+/// How heart rate zones are derived from the user preferences.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "ssr", derive(sqlx::Type))]
+#[cfg_attr(
+    feature = "ssr",
+    sqlx(type_name = "zone_model", rename_all = "snake_case")
+)]
+pub enum ZoneModel {
+    /// Three zones split at the aerobic and anaerobic threshold.
+    #[default]
+    ThreeZone,
+    /// Five zones in 10% steps of the max heart rate.
+    MaxHeartrate,
+    /// Karvonen, five zones in 10% steps of the heart rate reserve above the resting heart rate.
+    HeartrateReserve,
+    /// Friel, seven zones relative to the lactate threshold heart rate.
+    LactateThreshold,
+}
+
+impl ZoneModel {
+    pub const ALL: [ZoneModel; 4] = [
+        ZoneModel::ThreeZone,
+        ZoneModel::MaxHeartrate,
+        ZoneModel::HeartrateReserve,
+        ZoneModel::LactateThreshold,
+    ];
+
+    /// Value of the model in forms, same as in the database.
+    pub fn value(&self) -> &'static str {
+        match self {
+            ZoneModel::ThreeZone => "three_zone",
+            ZoneModel::MaxHeartrate => "max_heartrate",
+            ZoneModel::HeartrateReserve => "heartrate_reserve",
+            ZoneModel::LactateThreshold => "lactate_threshold",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ZoneModel::ThreeZone => "Aerobic/Anaerobic Threshold",
+            ZoneModel::MaxHeartrate => "% Max Heartrate",
+            ZoneModel::HeartrateReserve => "% Heartrate Reserve (Karvonen)",
+            ZoneModel::LactateThreshold => "% Lactate Threshold (Friel)",
+        }
+    }
+
+    /// Zones of the model with the heart rates of `preferences`, from low to high.
+    pub fn zones(&self, preferences: &UserPreferences) -> Vec<Zone> {
+        let max = preferences.max_heartrate;
+        // like for the load, heart rates below 55% of max are resting and in no zone
+        let floor = percent(max, 55);
+        match self {
+            ZoneModel::ThreeZone => Zone::split(
+                &["Zone 1", "Zone 2", "Zone 3"],
+                &[
+                    floor,
+                    preferences.aerobic_threshold,
+                    preferences.anaerobic_threshold,
+                ],
+                &THREE_ZONE_COLORS,
+            ),
+            ZoneModel::MaxHeartrate => Zone::split(
+                &FIVE_ZONE_NAMES,
+                &[50, 60, 70, 80, 90].map(|p| percent(max, p)),
+                &FIVE_ZONE_COLORS,
+            ),
+            ZoneModel::HeartrateReserve => {
+                // without a resting heart rate this is the same as % max heart rate
+                let rest = preferences.resting_heartrate.unwrap_or(0);
+                Zone::split(
+                    &FIVE_ZONE_NAMES,
+                    &[50, 60, 70, 80, 90].map(|p| rest + percent(max - rest, p)),
+                    &FIVE_ZONE_COLORS,
+                )
+            }
+            ZoneModel::LactateThreshold => {
+                let lthr = preferences.lactate_threshold_heartrate();
+                let mut lows = [85, 90, 95, 100, 103, 106].map(|p| percent(lthr, p));
+                // zone 5c starts above 106%
+                lows[5] = lthr * 106 / 100 + 1;
+                Zone::split(
+                    &[
+                        "Zone 1", "Zone 2", "Zone 3", "Zone 4", "Zone 5a", "Zone 5b", "Zone 5c",
+                    ],
+                    &[[floor.min(lows[0])].as_slice(), &lows].concat(),
+                    &[
+                        "#7dc4e4", "#a6da95", "#eed49f", "#f5a97f", "#ee99a0", "#ed8796", "#c6a0f6",
+                    ],
+                )
+            }
+        }
+    }
+}
+
+/// Percentage of a heart rate, rounded to whole beats.
+fn percent(heartrate: i32, percent: i32) -> i32 {
+    (heartrate as f64 * percent as f64 / 100.0).round() as i32
+}
+
+const THREE_ZONE_COLORS: [&str; 3] = ["#a6da95", "#eed49f", "#ed8796"];
+const FIVE_ZONE_NAMES: [&str; 5] = ["Zone 1", "Zone 2", "Zone 3", "Zone 4", "Zone 5"];
+const FIVE_ZONE_COLORS: [&str; 5] = ["#7dc4e4", "#a6da95", "#eed49f", "#f5a97f", "#ed8796"];
+
+/// Color of heart rates below the lowest zone.
+pub const BELOW_ZONES_COLOR: &str = "#8aadf4";
+
+/// A heart rate zone from `low` up to but not including `high`, the highest zone is open ended.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Zone {
+    pub name: String,
+    pub low: i32,
+    pub high: Option<i32>,
+    pub color: String,
+}
+
+impl Zone {
+    /// Zones between consecutive lower bounds.
+    fn split(names: &[&str], lows: &[i32], colors: &[&str]) -> Vec<Zone> {
+        lows.iter()
+            .enumerate()
+            .map(|(i, &low)| Zone {
+                name: names[i].to_string(),
+                low,
+                high: lows.get(i + 1).copied(),
+                color: colors[i].to_string(),
+            })
+            .collect()
+    }
+
+    pub fn contains(&self, heartrate: i32) -> bool {
+        heartrate >= self.low && self.high.map_or(true, |high| heartrate < high)
+    }
+}
+
+/// Index of the zone a heart rate is in, `None` below the lowest zone.
+pub fn zone_index(zones: &[Zone], heartrate: i32) -> Option<usize> {
+    zones.iter().position(|zone| zone.contains(heartrate))
+}
+
+/// Longest time in seconds a heart rate counts for, longer gaps are pauses in recording.
+pub const MAX_SAMPLE_GAP: f64 = 10.0;
+
+/// Seconds spent in each zone, each heart rate sample counting until the next one.
+///
+/// The samples have to be ordered by time, the last one counts for a second.
+pub fn time_in_zones(samples: &[(DateTime<Local>, i32)], zones: &[Zone]) -> Vec<f64> {
+    let mut seconds = vec![0.0; zones.len()];
+    for (i, (timestamp, heartrate)) in samples.iter().enumerate() {
+        let duration = samples.get(i + 1).map_or(1.0, |(next, _)| {
+            ((*next - *timestamp).num_milliseconds() as f64 / 1000.0).clamp(0.0, MAX_SAMPLE_GAP)
+        });
+        if let Some(zone) = zone_index(zones, *heartrate) {
+            seconds[zone] += duration;
+        }
+    }
+    seconds
+}
+
+/// Store the time in zones of an activity and each of its laps, replacing the stored ones.
+///
+/// The zones are those of the current zone model, with the heart rates of the preferences at the
+/// start of the activity, like in the zone summary. The time in zones has to be recalculated
+/// whenever the zone model changes.
+#[cfg(feature = "ssr")]
+pub async fn update_time_in_zones(
+    activity_id: i64,
+    user_id: i64,
+    conn: &mut sqlx::PgConnection,
+) -> Result<(), sqlx::Error> {
+    use super::user_preferences::get_user_preferences;
+
+    let start_time = sqlx::query_scalar!(
+        r#"SELECT start_time as "start_time: DateTime<Local>" FROM activities WHERE id=$1"#,
+        activity_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    let preferences = get_user_preferences(user_id, start_time, &mut *conn).await;
+    let zone_model = get_user_preferences(user_id, Local::now(), &mut *conn)
+        .await
+        .zone_model;
+    let zones = zone_model.zones(&preferences);
+    let samples: Vec<(DateTime<Local>, i32)> = sqlx::query!(
+        r#"
+        SELECT timestamp, heartrate::int4 as "heartrate!"
+        FROM records
+        WHERE activity_id=$1 AND heartrate IS NOT NULL
+        ORDER BY timestamp
+        "#,
+        activity_id as i32
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|r| (r.timestamp.into(), r.heartrate))
+    .collect();
+    let laps = sqlx::query!(
+        "SELECT id, start_time, end_time FROM laps WHERE activity_id=$1",
+        activity_id as i32
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut lap_ids = Vec::new();
+    let mut zone_indices = Vec::new();
+    let mut seconds = Vec::new();
+    let mut add = |lap_id: Option<i64>, samples: &[(DateTime<Local>, i32)]| {
+        for (zone, time) in time_in_zones(samples, &zones).into_iter().enumerate() {
+            lap_ids.push(lap_id);
+            zone_indices.push(zone as i16);
+            seconds.push(time);
+        }
+    };
+    if !samples.is_empty() {
+        add(None, &samples);
+        for lap in laps {
+            let lap_samples: Vec<_> = samples
+                .iter()
+                .filter(|(timestamp, _)| lap.start_time <= *timestamp && *timestamp < lap.end_time)
+                .copied()
+                .collect();
+            add(Some(lap.id), &lap_samples);
+        }
+    }
+
+    sqlx::query!(
+        "DELETE FROM time_in_zones WHERE activity_id=$1",
+        activity_id as i32
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO time_in_zones (activity_id, zone_model, lap_id, zone, seconds)
+        SELECT $1, $2, *
+        FROM UNNEST($3::bigint[], $4::smallint[], $5::float8[])
+        "#,
+        activity_id as i32,
+        zone_model as ZoneModel,
+        &lap_ids[..] as _,
+        &zone_indices[..],
+        &seconds[..]
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preferences(zone_model: ZoneModel) -> UserPreferences {
+        UserPreferences {
+            max_heartrate: 190,
+            resting_heartrate: Some(50),
+            lactate_threshold_heartrate: Some(170),
+            zone_model,
+            ..Default::default()
+        }
+    }
+
+    fn lows(zones: &[Zone]) -> Vec<i32> {
+        zones.iter().map(|z| z.low).collect()
+    }
+
+    #[test]
+    fn zone_models() {
+        let three = preferences(ZoneModel::ThreeZone).zones();
+        assert_eq!(lows(&three), vec![105, 155, 172]);
+        assert_eq!(three[2].high, None);
+
+        let max = preferences(ZoneModel::MaxHeartrate).zones();
+        assert_eq!(lows(&max), vec![95, 114, 133, 152, 171]);
+        assert_eq!(max[0].high, Some(114));
+
+        let reserve = preferences(ZoneModel::HeartrateReserve).zones();
+        assert_eq!(lows(&reserve), vec![120, 134, 148, 162, 176]);
+        let without_rest = UserPreferences {
+            resting_heartrate: None,
+            ..preferences(ZoneModel::HeartrateReserve)
+        };
+        assert_eq!(lows(&without_rest.zones()), lows(&max));
+
+        let friel = preferences(ZoneModel::LactateThreshold).zones();
+        assert_eq!(lows(&friel), vec![105, 145, 153, 162, 170, 175, 181]);
+        assert_eq!(friel[6].name, "Zone 5c");
+    }
+
+    #[test]
+    fn zone_of_heartrate() {
+        let zones = preferences(ZoneModel::ThreeZone).zones();
+        assert_eq!(zone_index(&zones, 100), None);
+        assert_eq!(zone_index(&zones, 105), Some(0));
+        assert_eq!(zone_index(&zones, 154), Some(0));
+        assert_eq!(zone_index(&zones, 155), Some(1));
+        assert_eq!(zone_index(&zones, 220), Some(2));
+    }
+
+    #[test]
+    fn time_in_zone() {
+        let zones = preferences(ZoneModel::ThreeZone).zones();
+        let start = Local::now();
+        let at = |s: i64| start + chrono::Duration::try_seconds(s).unwrap();
+        let samples = vec![
+            (at(0), 120),
+            (at(1), 120),
+            (at(2), 160),
+            (at(5), 100),
+            (at(6), 170),
+            (at(60), 180),
+        ];
+        // 100 bpm is in no zone, the pause after 170 bpm only counts up to the max gap
+        assert_eq!(time_in_zones(&samples, &zones), vec![2.0, 13.0, 1.0]);
+        assert_eq!(time_in_zones(&[], &zones), vec![0.0, 0.0, 0.0]);
+    }
+}
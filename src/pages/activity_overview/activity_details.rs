@@ -1,14 +1,30 @@
 use std::cmp;
 use std::time::Duration;
 
+use super::format_seconds;
 #[cfg(feature = "ssr")]
 use crate::app::{auth, pool};
-use crate::pages::user::get_preferences;
+#[cfg(feature = "ssr")]
+use crate::models::{user_preferences::get_user_preferences, zones::ZoneModel};
+#[cfg(feature = "ssr")]
+use crate::pages::activity_overview::export::load_devices;
+use crate::{
+    app::FitFileUploaded,
+    models::{
+        device::{optical_heartrate, Device},
+        load::{LoadModel, PowerMetrics},
+        pace::{format_pace, PaceMetrics},
+        zones::Zone,
+    },
+    pages::overview::heartrate_distribution_chart::zone_pieces,
+};
 use bigdecimal::{BigDecimal, ToPrimitive};
 use charming::{
-    component::{Axis, Grid, VisualMap, VisualMapPiece, VisualMapType},
-    element::{AxisType, MarkLine, MarkLineData, MarkLineVariant, Symbol, Tooltip, Trigger},
-    series::Line,
+    component::{Axis, Grid, Legend, VisualMap, VisualMapType},
+    element::{
+        AxisType, ItemStyle, MarkLine, MarkLineData, MarkLineVariant, Symbol, Tooltip, Trigger,
+    },
+    series::{Bar, Line},
     Chart, WasmRenderer,
 };
 use chrono::{DateTime, Local};
@@ -17,11 +33,8 @@ use leptos::{html::Div, *};
 use leptos_leaflet::*;
 use leptos_use::{use_element_size, UseElementSizeReturn};
 use serde::{Deserialize, Serialize};
-#[cfg(feature = "ssr")]
-use sqlx::*;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[cfg_attr(feature = "ssr", derive(sqlx::Type))]
 pub struct Lap {
     pub id: i64,
     pub start_time: DateTime<Local>,
@@ -34,10 +47,12 @@ pub struct Lap {
     pub sport: Option<String>,
     pub ascent: Option<i16>,
     pub descent: Option<i16>,
+    /// Seconds in each heart rate zone.
+    #[serde(default)]
+    pub time_in_zones: Vec<f64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[cfg_attr(feature = "ssr", derive(sqlx::Type))]
 pub struct Record {
     pub timestamp: DateTime<Local>,
     pub heartrate: Option<i16>,
@@ -46,6 +61,85 @@ pub struct Record {
     pub distance: Option<f64>,
     pub speed: Option<f64>,
     pub altitude: Option<f64>,
+    pub cadence: Option<f64>,
+    pub power: Option<i16>,
+    pub step_length: Option<f64>,
+    pub vertical_oscillation: Option<f64>,
+    pub ground_contact_time: Option<f64>,
+    pub temperature: Option<i16>,
+}
+
+/// A part of a multisport activity, transitions included.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub start_time: DateTime<Local>,
+    pub end_time: DateTime<Local>,
+    pub sport: String,
+    pub distance: Option<f64>,
+    pub average_heartrate: Option<i16>,
+    pub average_speed: Option<f64>,
+    pub load: Option<i32>,
+}
+
+impl Session {
+    fn is_transition(&self) -> bool {
+        self.sport == "transition"
+    }
+}
+
+/// Per record metrics that can be shown in the metrics chart, besides heart rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Metric {
+    Cadence,
+    Power,
+    StepLength,
+    VerticalOscillation,
+    GroundContactTime,
+    Temperature,
+}
+
+impl Metric {
+    const ALL: [Metric; 6] = [
+        Metric::Cadence,
+        Metric::Power,
+        Metric::StepLength,
+        Metric::VerticalOscillation,
+        Metric::GroundContactTime,
+        Metric::Temperature,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Metric::Cadence => "Cadence",
+            Metric::Power => "Power",
+            Metric::StepLength => "Step Length",
+            Metric::VerticalOscillation => "Vertical Oscillation",
+            Metric::GroundContactTime => "Ground Contact Time",
+            Metric::Temperature => "Temperature",
+        }
+    }
+
+    fn unit(&self) -> &'static str {
+        match self {
+            Metric::Cadence => "rpm",
+            Metric::Power => "W",
+            Metric::StepLength => "mm",
+            Metric::VerticalOscillation => "mm",
+            Metric::GroundContactTime => "ms",
+            Metric::Temperature => "°C",
+        }
+    }
+
+    fn value(&self, record: &Record) -> Option<f64> {
+        match self {
+            Metric::Cadence => record.cadence,
+            Metric::Power => record.power.map(f64::from),
+            Metric::StepLength => record.step_length,
+            Metric::VerticalOscillation => record.vertical_oscillation,
+            Metric::GroundContactTime => record.ground_contact_time,
+            Metric::Temperature => record.temperature.map(f64::from),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,9 +147,53 @@ pub struct ActivityDetail {
     pub id: i64,
     pub start_time: DateTime<Local>,
     pub duration: BigDecimal,
+    pub moving_time: Option<BigDecimal>,
+    pub elapsed_time: BigDecimal,
     pub sport: String,
+    pub load: Option<i32>,
+    pub load_model: Option<LoadModel>,
+    /// Rate of perceived exertion from 1 to 10.
+    pub rpe: Option<i16>,
+    pub power: Option<PowerMetrics>,
+    pub pace: Option<PaceMetrics>,
     pub laps: Option<Vec<Lap>>,
+    pub sessions: Vec<Session>,
     pub records: Vec<Record>,
+    pub devices: Vec<Device>,
+    /// Heart rate zones of the preferences at the time of the activity.
+    pub zones: Vec<Zone>,
+    /// Seconds in each heart rate zone.
+    pub time_in_zones: Vec<f64>,
+}
+
+impl ActivityDetail {
+    /// Records of a session, or of the whole activity if no session is selected.
+    fn records_in(&self, session: Option<usize>) -> impl Iterator<Item = &Record> {
+        let range = session
+            .and_then(|i| self.sessions.get(i))
+            .map(|s| (s.start_time, s.end_time));
+        self.records.iter().filter(move |r| {
+            range.map_or(true, |(start, end)| {
+                start <= r.timestamp && r.timestamp <= end
+            })
+        })
+    }
+
+    /// Names for the sessions, transitions are numbered T1, T2, ...
+    fn session_names(&self) -> Vec<String> {
+        let mut transitions = 0;
+        self.sessions
+            .iter()
+            .map(|s| {
+                if s.is_transition() {
+                    transitions += 1;
+                    format!("T{}", transitions)
+                } else {
+                    s.sport.clone()
+                }
+            })
+            .collect()
+    }
 }
 
 impl PartialEq for ActivityDetail {
@@ -72,96 +210,308 @@ pub async fn activity_details(id: i64) -> Result<ActivityDetail, ServerFnError>
     }
     let user = auth.current_user.unwrap();
     let pool = pool()?;
-    let activity_detail = query_as!(
-        ActivityDetail,
+    let activity = sqlx::query!(
         r#"
-        SELECT 
-            activities.id, 
-            activities.start_time, 
-            activities.duration,
-            COALESCE(string_agg(sessions.sport,', '),'General') as "sport!",
-            (
-                SELECT
-                    ARRAY_AGG(
-                        (
-                            laps.id, 
-                            laps.start_time, 
-                            laps.end_time, 
-                            laps.distance::float8, 
-                            laps.calories, 
-                            laps.average_heartrate, 
-                            laps.min_heartrate, 
-                            laps.max_heartrate, 
-                            laps.sport,
-                            laps.ascent, 
-                            laps.descent
-                        )
-                    )
-                FROM laps
-                WHERE laps.activity_id = $2::bigint
-            ) as "laps:Vec<Lap>",
-            (
-                SELECT
-                    ARRAY_AGG(
-                        (
-                            records.timestamp,
-                            records.heartrate,
-                            records.latitude,
-                            records.longitude,
-                            records.distance::float8,
-                            records.speed::float8,
-                            records.altitude::float8
-                        )
-                        ORDER BY records.timestamp ASC
-                    ) 
-                FROM records
-                WHERE records.activity_id = $2::bigint
-            ) as "records!:Vec<Record>"
-        FROM activities 
-        JOIN sessions on sessions.activity_id=activities.id
-        WHERE activities.user_id = $1::bigint AND activities.id = $2::bigint
-        GROUP BY activities.id
-        "#,
+            SELECT
+                activities.id,
+                activities.start_time,
+                activities.duration,
+                activities.moving_time,
+                ROUND(EXTRACT(EPOCH FROM activities.end_time - activities.start_time)::numeric, 1) as "elapsed_time!",
+                COALESCE(
+                    string_agg(sessions.sport, ', ' ORDER BY sessions.start_time)
+                        FILTER (WHERE sessions.sport <> 'transition'),
+                    'General'
+                ) as "sport!",
+                activities.load,
+                activities.load_model as "load_model: LoadModel",
+                activities.rpe
+            FROM activities
+            JOIN sessions on sessions.activity_id=activities.id
+            WHERE activities.user_id = $1::bigint AND activities.id = $2::bigint
+            GROUP BY activities.id
+            "#,
         user.id,
         id
     )
     .fetch_one(&pool)
     .await?;
-    Ok(activity_detail)
+    let start_time = activity.start_time.into();
+    let metrics = sqlx::query!(
+        r#"
+        SELECT normalized_power, intensity_factor::float8, tss,
+            normalized_graded_speed::float8, pace_intensity_factor::float8, rtss
+        FROM activities
+        WHERE id = $1::bigint
+        "#,
+        id
+    )
+    .fetch_one(&pool)
+    .await?;
+    let power = match (
+        metrics.normalized_power,
+        metrics.intensity_factor,
+        metrics.tss,
+    ) {
+        (Some(normalized_power), Some(intensity_factor), Some(tss)) => Some(PowerMetrics {
+            normalized_power: normalized_power as u16,
+            intensity_factor,
+            tss: tss as u32,
+        }),
+        _ => None,
+    };
+    let pace = match (
+        metrics.normalized_graded_speed,
+        metrics.pace_intensity_factor,
+        metrics.rtss,
+    ) {
+        (Some(normalized_graded_speed), Some(intensity_factor), Some(rtss)) => Some(PaceMetrics {
+            normalized_graded_speed,
+            intensity_factor,
+            rtss: rtss as u32,
+        }),
+        _ => None,
+    };
+    let mut laps: Vec<Lap> = sqlx::query!(
+        r#"
+        SELECT
+            laps.id,
+            laps.start_time,
+            laps.end_time,
+            laps.distance::float8,
+            laps.calories,
+            laps.average_heartrate,
+            laps.min_heartrate,
+            laps.max_heartrate,
+            laps.sport,
+            laps.ascent,
+            laps.descent
+        FROM laps
+        WHERE laps.activity_id = $1::bigint
+        ORDER BY laps.start_time
+        "#,
+        id
+    )
+    .fetch_all(&pool)
+    .await?
+    .into_iter()
+    .map(|lap| Lap {
+        id: lap.id,
+        start_time: lap.start_time.into(),
+        end_time: lap.end_time.into(),
+        distance: lap.distance,
+        calories: lap.calories,
+        average_heartrate: lap.average_heartrate,
+        min_heartrate: lap.min_heartrate,
+        max_heartrate: lap.max_heartrate,
+        sport: lap.sport,
+        ascent: lap.ascent,
+        descent: lap.descent,
+        time_in_zones: Vec::new(),
+    })
+    .collect();
+    let sessions = sqlx::query_as!(
+        Session,
+        r#"
+        SELECT
+            sessions.start_time,
+            sessions.end_time,
+            sessions.sport,
+            sessions.distance::float8,
+            sessions.average_heartrate,
+            sessions.average_speed::float8,
+            sessions.load
+        FROM sessions
+        WHERE sessions.activity_id = $1::bigint
+        ORDER BY sessions.start_time
+        "#,
+        id
+    )
+    .fetch_all(&pool)
+    .await?;
+    let records = sqlx::query_as!(
+        Record,
+        r#"
+        SELECT
+            records.timestamp,
+            records.heartrate,
+            records.latitude,
+            records.longitude,
+            records.distance::float8,
+            records.speed::float8,
+            records.altitude::float8,
+            records.cadence::float8,
+            records.power,
+            records.step_length::float8,
+            records.vertical_oscillation::float8,
+            records.ground_contact_time::float8,
+            records.temperature
+        FROM records
+        WHERE records.activity_id = $1::bigint
+        ORDER BY records.timestamp ASC
+        "#,
+        id
+    )
+    .fetch_all(&pool)
+    .await?;
+    let zone_times = sqlx::query!(
+        r#"
+        SELECT lap_id, zone_model as "zone_model: ZoneModel", seconds::float8 as "seconds!"
+        FROM time_in_zones
+        WHERE activity_id = $1::bigint
+        ORDER BY lap_id NULLS FIRST, zone
+        "#,
+        id
+    )
+    .fetch_all(&pool)
+    .await?;
+    let mut time_in_zones = Vec::new();
+    for zone_time in &zone_times {
+        match zone_time.lap_id {
+            Some(lap_id) => {
+                if let Some(lap) = laps.iter_mut().find(|lap| lap.id == lap_id) {
+                    lap.time_in_zones.push(zone_time.seconds);
+                }
+            }
+            None => time_in_zones.push(zone_time.seconds),
+        }
+    }
+    // the times are in the zones of the model they were stored with
+    let preferences = get_user_preferences(user.id, start_time, &pool).await;
+    let zone_model = zone_times
+        .first()
+        .map_or(preferences.zone_model, |zone_time| zone_time.zone_model);
+    Ok(ActivityDetail {
+        id: activity.id,
+        start_time,
+        duration: activity.duration,
+        moving_time: activity.moving_time,
+        elapsed_time: activity.elapsed_time,
+        sport: activity.sport,
+        load: activity.load,
+        load_model: activity.load_model,
+        rpe: activity.rpe,
+        power,
+        pace,
+        laps: (!laps.is_empty()).then_some(laps),
+        sessions,
+        records,
+        devices: load_devices(id, &pool)
+            .await
+            .map_err(|e| ServerFnError::new(format!("{:#}", e)))?,
+        zones: zone_model.zones(&preferences),
+        time_in_zones,
+    })
+}
+
+/// Set the perceived exertion of an activity.
+///
+/// Loads that don't come from heart rate records are estimated again, activities with heart
+/// rate based loads calibrate the session-RPE model instead.
+#[server(SetActivityRpe, "/api")]
+pub async fn set_activity_rpe(activity_id: i64, rpe: Option<u8>) -> Result<(), ServerFnError> {
+    use crate::models::{aggregates::update_aggregates, load::estimate_load};
+
+    let pool = pool()?;
+    let auth = auth()?;
+    let user = auth
+        .current_user
+        .ok_or(ServerFnError::new("Not logged in".to_string()))?;
+    if rpe.is_some_and(|rpe| !(1..=10).contains(&rpe)) {
+        return Err(ServerFnError::new(
+            "RPE has to be between 1 and 10".to_string(),
+        ));
+    }
+    let activity = sqlx::query!(
+        r#"
+        SELECT start_time, duration::float8 AS "duration!", avg_heartrate,
+            load_model as "load_model: LoadModel"
+        FROM activities
+        WHERE id=$1 AND user_id=$2
+        "#,
+        activity_id,
+        user.id as i32
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or(ServerFnError::new("Activity doesn't exist".to_string()))?;
+    // loads calculated from the records don't depend on the RPE
+    if activity
+        .load_model
+        .is_some_and(|model| model.from_records())
+    {
+        sqlx::query!(
+            "UPDATE activities SET rpe=$2 WHERE id=$1",
+            activity_id,
+            rpe.map(|v| v as i16)
+        )
+        .execute(&pool)
+        .await?;
+        return Ok(());
+    }
+    // without heart rate records, an average heart rate was entered manually
+    let (load, load_model) = estimate_load(
+        activity.start_time.into(),
+        activity.duration,
+        activity.avg_heartrate.map(|hr| hr as u16),
+        rpe,
+        user.id,
+        &pool,
+    )
+    .await?
+    .unzip();
+    let mut tx = pool.begin().await?;
+    sqlx::query!(
+        "UPDATE activities SET rpe=$2, load=$3, load_model=$4 WHERE id=$1",
+        activity_id,
+        rpe.map(|v| v as i16),
+        load.map(|v| v as i32),
+        load_model as Option<LoadModel>
+    )
+    .execute(&mut *tx)
+    .await?;
+    update_aggregates(activity_id, user.id, &mut tx).await?;
+    tx.commit().await?;
+    Ok(())
 }
 
 #[component]
 pub fn ActivityDetails(activity: RwSignal<Option<i64>>) -> impl IntoView {
     let close = move |_| activity.set(None);
-    let detail = create_resource(activity, |id| async move {
-        if let Some(id) = id {
-            activity_details(id).await.ok()
-        } else {
-            None
+    let uploaded = use_context::<FitFileUploaded>().unwrap();
+    let set_rpe = create_server_action::<SetActivityRpe>();
+    create_effect(move |_| {
+        if set_rpe.version().get() > 0 {
+            uploaded.0.update(|v| *v += 1);
         }
     });
-    let user_prefs = create_resource(move || (), |_| async move { get_preferences().await });
+    let detail = create_resource(
+        move || (activity.get(), set_rpe.version().get()),
+        |(id, _)| async move {
+            if let Some(id) = id {
+                activity_details(id).await.ok()
+            } else {
+                None
+            }
+        },
+    );
+    // the session of a multisport activity the charts are limited to
+    let selected_session = create_rw_signal(None::<usize>);
+    create_effect(move |_| {
+        activity.track();
+        selected_session.set(None);
+    });
     let heartrate_chart_node = create_node_ref::<Div>();
     let UseElementSizeReturn { width, height: _ } = use_element_size(heartrate_chart_node);
     let _chart = create_local_resource(
-        move || (detail.get(), user_prefs.get(), width.get()),
-        move |(detail, user_prefs, width)| async move {
+        move || (detail.get(), width.get(), selected_session.get()),
+        move |(detail, width, session)| async move {
             if width == 0.0 {
                 return;
             }
-            let (aerobic, anaerobic, max_hr) = if let Some(Ok(prefs)) = user_prefs {
-                (
-                    prefs.aerobic_threshold,
-                    prefs.anaerobic_threshold,
-                    prefs.max_heartrate,
-                )
-            } else {
-                (155, 173, 183)
-            };
             if let Some(Some(detail)) = detail {
                 let (timestamps, heartrates) = detail
-                    .records
-                    .iter()
+                    .records_in(session)
                     .filter_map(|r| {
                         r.heartrate.map(|h| {
                             (
@@ -180,49 +530,26 @@ pub fn ActivityDetails(activity: RwSignal<Option<i64>>) -> impl IntoView {
                             .show(false)
                             .type_(VisualMapType::Piecewise)
                             .min(0)
-                            .max(max_hr)
-                            .pieces(vec![
-                                VisualMapPiece::new()
-                                    .gt(0)
-                                    .lte(aerobic as f32 * 0.7)
-                                    .color("#7dc4e4"),
-                                VisualMapPiece::new()
-                                    .gt(aerobic as f32 * 0.7)
-                                    .lte(aerobic)
-                                    .color("#a6da95"),
-                                VisualMapPiece::new()
-                                    .gt(aerobic)
-                                    .lte(anaerobic)
-                                    .color("#eed49f"),
-                                VisualMapPiece::new()
-                                    .gt(anaerobic)
-                                    .lte(max_hr)
-                                    .color("#ed8796"),
-                            ]),
+                            .pieces(zone_pieces(&detail.zones)),
                     )
                     .y_axis(Axis::new().type_(AxisType::Value).min("dataMin"))
                     .series(
                         Line::new().show_symbol(false).data(heartrates).mark_line(
                             MarkLine::new()
                                 .symbol(vec![Symbol::None, Symbol::None])
-                                .data(vec![
-                                    MarkLineVariant::Simple(
-                                        MarkLineData::new().y_axis(aerobic as f32 * 0.7).name(""),
-                                    ),
-                                    MarkLineVariant::Simple(
-                                        MarkLineData::new()
-                                            .y_axis(aerobic)
-                                            .name("Aerobic Threshold"),
-                                    ),
-                                    MarkLineVariant::Simple(
-                                        MarkLineData::new()
-                                            .y_axis(anaerobic)
-                                            .name("Anaerobic Threshold"),
-                                    ),
-                                    MarkLineVariant::Simple(
-                                        MarkLineData::new().y_axis(max_hr).name("Max Heartrate"),
-                                    ),
-                                ]),
+                                .data(
+                                    detail
+                                        .zones
+                                        .iter()
+                                        .map(|zone| {
+                                            MarkLineVariant::Simple(
+                                                MarkLineData::new()
+                                                    .y_axis(zone.low)
+                                                    .name(zone.name.as_str()),
+                                            )
+                                        })
+                                        .collect::<Vec<_>>(),
+                                ),
                         ),
                     );
                 let renderer = WasmRenderer::new(cmp::max(width as u32, 500), 500);
@@ -230,6 +557,128 @@ pub fn ActivityDetails(activity: RwSignal<Option<i64>>) -> impl IntoView {
             }
         },
     );
+    let has_time_in_zones = move || {
+        detail
+            .get()
+            .flatten()
+            .is_some_and(|detail| detail.time_in_zones.iter().any(|&s| s > 0.0))
+    };
+    let zones_chart_node = create_node_ref::<Div>();
+    let UseElementSizeReturn {
+        width: zones_width,
+        height: _,
+    } = use_element_size(zones_chart_node);
+    let _zones_chart = create_local_resource(
+        move || (detail.get(), zones_width.get()),
+        move |(detail, width)| async move {
+            if width == 0.0 {
+                return;
+            }
+            if let Some(Some(detail)) = detail {
+                let laps = detail.laps.unwrap_or_default();
+                // the activity on top, then its laps
+                let mut rows = vec!["Activity".to_string()];
+                rows.extend((1..=laps.len()).map(|lap| format!("Lap {}", lap)));
+                rows.reverse();
+                let mut chart = Chart::new()
+                    .grid(Grid::new().top(30).bottom(20).left(60))
+                    .legend(Legend::new())
+                    .tooltip(Tooltip::new().trigger(Trigger::Axis))
+                    .x_axis(Axis::new().type_(AxisType::Value).name("min"))
+                    .y_axis(Axis::new().type_(AxisType::Category).data(rows.clone()));
+                for (i, zone) in detail.zones.iter().enumerate() {
+                    let minutes = |time_in_zones: &[f64]| {
+                        (time_in_zones.get(i).copied().unwrap_or(0.0) / 6.0).round() / 10.0
+                    };
+                    let mut data: Vec<f64> = laps
+                        .iter()
+                        .rev()
+                        .map(|lap| minutes(&lap.time_in_zones))
+                        .collect();
+                    data.push(minutes(&detail.time_in_zones));
+                    chart = chart.series(
+                        Bar::new()
+                            .name(zone.name.as_str())
+                            .stack("zones")
+                            .item_style(ItemStyle::new().color(zone.color.as_str()))
+                            .data(data),
+                    );
+                }
+                let height = 60 + 25 * rows.len() as u32;
+                let renderer = WasmRenderer::new(cmp::max(width as u32, 500), height);
+                let _rendered = renderer.render("zones_chart", &chart);
+            }
+        },
+    );
+    let available_metrics = move || {
+        detail
+            .get()
+            .flatten()
+            .map(|detail| {
+                Metric::ALL
+                    .into_iter()
+                    .filter(|m| detail.records.iter().any(|r| m.value(r).is_some()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default()
+    };
+    let selected_metric = create_rw_signal(None::<Metric>);
+    // fall back to the first metric with data if nothing (available) is selected
+    let metric = move || {
+        let available = available_metrics();
+        selected_metric
+            .get()
+            .filter(|m| available.contains(m))
+            .or(available.first().copied())
+    };
+    let metrics_chart_node = create_node_ref::<Div>();
+    let UseElementSizeReturn {
+        width: metrics_width,
+        height: _,
+    } = use_element_size(metrics_chart_node);
+    let _metrics_chart = create_local_resource(
+        move || {
+            (
+                detail.get(),
+                metric(),
+                metrics_width.get(),
+                selected_session.get(),
+            )
+        },
+        move |(detail, metric, width, session)| async move {
+            if width == 0.0 {
+                return;
+            }
+            if let (Some(Some(detail)), Some(metric)) = (detail, metric) {
+                let (timestamps, values): (Vec<String>, Vec<f64>) = detail
+                    .records_in(session)
+                    .filter_map(|r| {
+                        metric
+                            .value(r)
+                            .map(|v| (format!("{}", r.timestamp.format("%Y-%m-%d %H:%M:%S")), v))
+                    })
+                    .unzip();
+                let chart = Chart::new()
+                    .grid(Grid::new().top(10).bottom(20))
+                    .tooltip(Tooltip::new().trigger(Trigger::Axis))
+                    .x_axis(Axis::new().type_(AxisType::Category).data(timestamps))
+                    .y_axis(
+                        Axis::new()
+                            .type_(AxisType::Value)
+                            .min("dataMin")
+                            .name(metric.unit()),
+                    )
+                    .series(
+                        Line::new()
+                            .name(metric.label())
+                            .show_symbol(false)
+                            .data(values),
+                    );
+                let renderer = WasmRenderer::new(cmp::max(width as u32, 500), 300);
+                let _rendered = renderer.render("metrics_chart", &chart);
+            }
+        },
+    );
     view! {
         <Show when=move || { activity().is_some() } fallback=|| {}>
 
@@ -244,18 +693,169 @@ pub fn ActivityDetails(activity: RwSignal<Option<i64>>) -> impl IntoView {
                             .map(|detail| match detail {
                                 None => view! { <pre>"Error"</pre> }.into_view(),
                                 Some(detail) => {
+                                    let session_names = detail.session_names();
                                     view! {
                                         <div class="modal-card is-full">
                                             <div class="modal-card-head">
                                                 <div class="modal-card-title">
-                                                    <p class="title is-4">{detail.sport}</p>
+                                                    <p class="title is-4">
+                                                        {detail.sport}
+                                                        {(optical_heartrate(&detail.devices) == Some(true))
+                                                            .then(|| {
+                                                                view! {
+                                                                    <span
+                                                                        class="tag is-warning ml-2"
+                                                                        title="Heart rate was measured at the wrist"
+                                                                    >
+                                                                        "Optical HR"
+                                                                    </span>
+                                                                }
+                                                            })}
+
+                                                    </p>
                                                     <p class="subtitle is-6">
                                                         {detail.start_time.format("%Y-%m-%d").to_string()} ,
-                                                        {format_duration(
-                                                                Duration::new(detail.duration.to_u64().unwrap(), 0),
-                                                            )
-                                                            .to_string()}
+                                                        {format_seconds(
+                                                            detail.moving_time.as_ref().unwrap_or(&detail.duration),
+                                                        )} " moving, "
+                                                        {format_seconds(&detail.elapsed_time)} " elapsed"
                                                     </p>
+                                                    <div class="field is-grouped">
+                                                        <div class="control">
+                                                            <div class="tags has-addons">
+                                                                <span class="tag">"Load"</span>
+                                                                <span
+                                                                    class="tag is-info"
+                                                                    title=detail.load_model.map(|m| m.description())
+                                                                >
+                                                                    {detail
+                                                                        .load
+                                                                        .map(|load| load.to_string())
+                                                                        .unwrap_or("-".to_string())}
+                                                                </span>
+                                                                {detail
+                                                                    .load
+                                                                    .and(detail.load_model)
+                                                                    .map(|model| {
+                                                                        view! { <span class="tag is-light">{model.label()}</span> }
+                                                                    })}
+                                                            </div>
+                                                        </div>
+                                                        {detail
+                                                            .power
+                                                            .map(|power| {
+                                                                view! {
+                                                                    <div class="control">
+                                                                        <div class="tags has-addons">
+                                                                            <span class="tag">"NP"</span>
+                                                                            <span class="tag is-info">
+                                                                                {format!("{} W", power.normalized_power)}
+                                                                            </span>
+                                                                        </div>
+                                                                    </div>
+                                                                    <div class="control">
+                                                                        <div class="tags has-addons">
+                                                                            <span class="tag">"IF"</span>
+                                                                            <span class="tag is-info">
+                                                                                {format!("{:.2}", power.intensity_factor)}
+                                                                            </span>
+                                                                        </div>
+                                                                    </div>
+                                                                    <div class="control">
+                                                                        <div class="tags has-addons">
+                                                                            <span class="tag">"TSS"</span>
+                                                                            <span class="tag is-info">{power.tss}</span>
+                                                                        </div>
+                                                                    </div>
+                                                                }
+                                                            })}
+                                                        {detail
+                                                            .pace
+                                                            .map(|pace| {
+                                                                view! {
+                                                                    <div class="control">
+                                                                        <div class="tags has-addons">
+                                                                            <span class="tag" title="Normalized graded pace">
+                                                                                "NGP"
+                                                                            </span>
+                                                                            <span class="tag is-info">
+                                                                                {pace
+                                                                                    .normalized_graded_pace()
+                                                                                    .map(|p| format!("{} /km", format_pace(p)))}
+                                                                            </span>
+                                                                        </div>
+                                                                    </div>
+                                                                    <div class="control">
+                                                                        <div class="tags has-addons">
+                                                                            <span class="tag">"IF"</span>
+                                                                            <span class="tag is-info">
+                                                                                {format!("{:.2}", pace.intensity_factor)}
+                                                                            </span>
+                                                                        </div>
+                                                                    </div>
+                                                                    <div class="control">
+                                                                        <div class="tags has-addons">
+                                                                            <span class="tag">"rTSS"</span>
+                                                                            <span class="tag is-info">{pace.rtss}</span>
+                                                                        </div>
+                                                                    </div>
+                                                                }
+                                                            })}
+                                                        <div class="control">
+                                                            <div class="select is-small">
+                                                                <select
+                                                                    title="Rate of perceived exertion"
+                                                                    on:change=move |ev| {
+                                                                        set_rpe
+                                                                            .dispatch(SetActivityRpe {
+                                                                                activity_id: detail.id,
+                                                                                rpe: event_target_value(&ev).parse::<u8>().ok(),
+                                                                            })
+                                                                    }
+                                                                >
+                                                                    <option value="" selected=detail.rpe.is_none()>
+                                                                        "RPE"
+                                                                    </option>
+                                                                    {(1..=10)
+                                                                        .map(|rpe| {
+                                                                            view! {
+                                                                                <option
+                                                                                    value=rpe
+                                                                                    selected=detail.rpe == Some(rpe)
+                                                                                >
+                                                                                    "RPE "
+                                                                                    {rpe}
+                                                                                </option>
+                                                                            }
+                                                                        })
+                                                                        .collect_view()}
+                                                                </select>
+                                                            </div>
+                                                        </div>
+                                                    </div>
+                                                </div>
+                                                <div class="buttons are-small mr-4">
+                                                    {["fit", "gpx", "tcx"]
+                                                        .into_iter()
+                                                        .map(|format| {
+                                                            view! {
+                                                                <a
+                                                                    class="button"
+                                                                    href=format!(
+                                                                        "/api/activities/{}/export/{}",
+                                                                        detail.id,
+                                                                        format,
+                                                                    )
+
+                                                                    download=""
+                                                                >
+                                                                    <i class="material-symbols-rounded">download</i>
+                                                                    {format.to_uppercase()}
+                                                                </a>
+                                                            }
+                                                        })
+                                                        .collect_view()}
+
                                                 </div>
                                                 <button
                                                     class="delete"
@@ -264,6 +864,78 @@ pub fn ActivityDetails(activity: RwSignal<Option<i64>>) -> impl IntoView {
                                                 ></button>
                                             </div>
                                             <div class="modal-card-body">
+                                                {(detail.sessions.len() > 1)
+                                                    .then(|| {
+                                                        view! {
+                                                            <div class="tabs is-small">
+                                                                <ul>
+                                                                    <li class:is-active=move || {
+                                                                        selected_session.get().is_none()
+                                                                    }>
+                                                                        <a on:click=move |_| {
+                                                                            selected_session.set(None)
+                                                                        }>"All"</a>
+                                                                    </li>
+                                                                    {session_names
+                                                                        .iter()
+                                                                        .cloned()
+                                                                        .enumerate()
+                                                                        .map(|(i, name)| {
+                                                                            view! {
+                                                                                <li class:is-active=move || {
+                                                                                    selected_session.get() == Some(i)
+                                                                                }>
+                                                                                    <a on:click=move |_| {
+                                                                                        selected_session.set(Some(i))
+                                                                                    }>{name}</a>
+                                                                                </li>
+                                                                            }
+                                                                        })
+                                                                        .collect_view()}
+                                                                </ul>
+                                                            </div>
+                                                            <table class="table is-striped is-hoverable is-fullwidth">
+                                                                <thead>
+                                                                    <tr>
+                                                                        <th>Segment</th>
+                                                                        <th>Time</th>
+                                                                        <th>Distance</th>
+                                                                        <th>Avg. Heartrate</th>
+                                                                        <th>Load</th>
+                                                                    </tr>
+                                                                </thead>
+                                                                <tbody>
+                                                                    {detail
+                                                                        .sessions
+                                                                        .iter()
+                                                                        .zip(session_names.iter())
+                                                                        .map(|(session, name)| {
+                                                                            view! {
+                                                                                <tr>
+                                                                                    <td>{name.clone()}</td>
+                                                                                    <td>
+                                                                                        {format_duration(
+                                                                                                (session.end_time - session.start_time)
+                                                                                                    .to_std()
+                                                                                                    .expect("couldn't convert duration"),
+                                                                                            )
+                                                                                            .to_string()}
+                                                                                    </td>
+                                                                                    <td>
+                                                                                        {session
+                                                                                            .distance
+                                                                                            .map(|d| format!("{:.2} km", d / 1000.0))}
+                                                                                    </td>
+                                                                                    <td>{session.average_heartrate}</td>
+                                                                                    <td>{session.load}</td>
+                                                                                </tr>
+                                                                            }
+                                                                        })
+                                                                        .collect_view()}
+                                                                </tbody>
+                                                            </table>
+                                                        }
+                                                    })}
                                                 <div class="columns">
                                                     <div class="column" node_ref=heartrate_chart_node>
                                                         <div id="heartrate_chart"></div>
@@ -313,6 +985,41 @@ pub fn ActivityDetails(activity: RwSignal<Option<i64>>) -> impl IntoView {
                                                     }
 
                                                 </div>
+                                                <Show when=has_time_in_zones fallback=|| {}>
+                                                    <h6 class="title is-6">Time in Zones</h6>
+                                                    <div class="columns">
+                                                        <div class="column" node_ref=zones_chart_node>
+                                                            <div id="zones_chart"></div>
+                                                        </div>
+                                                    </div>
+                                                </Show>
+                                                <Show
+                                                    when=move || !available_metrics().is_empty()
+                                                    fallback=|| {}
+                                                >
+                                                    <div class="tabs is-small">
+                                                        <ul>
+                                                            <For
+                                                                each=available_metrics
+                                                                key=|m| m.label()
+                                                                let:m
+                                                            >
+                                                                <li class:is-active=move || {
+                                                                    metric() == Some(m)
+                                                                }>
+                                                                    <a on:click=move |_| {
+                                                                        selected_metric.set(Some(m))
+                                                                    }>{m.label()}</a>
+                                                                </li>
+                                                            </For>
+                                                        </ul>
+                                                    </div>
+                                                    <div class="columns">
+                                                        <div class="column" node_ref=metrics_chart_node>
+                                                            <div id="metrics_chart"></div>
+                                                        </div>
+                                                    </div>
+                                                </Show>
                                                 <div class="columns">
                                                     <div class="column is-fullwidth">
                                                         <table class="table is-striped is-hoverable is-fullwidth">
@@ -379,6 +1086,54 @@ pub fn ActivityDetails(activity: RwSignal<Option<i64>>) -> impl IntoView {
                                                         </table>
                                                     </div>
                                                 </div>
+                                                {(!detail.devices.is_empty())
+                                                    .then(|| {
+                                                        view! {
+                                                                <h6 class="title is-6">Devices</h6>
+                                                                <table class="table is-striped is-hoverable is-fullwidth">
+                                                                    <thead>
+                                                                        <tr>
+                                                                            <th>Device</th>
+                                                                            <th>Sensor</th>
+                                                                            <th>Provides</th>
+                                                                            <th>Serial</th>
+                                                                            <th>Software</th>
+                                                                        </tr>
+                                                                    </thead>
+                                                                    <tbody>
+                                                                        {detail
+                                                                            .devices
+                                                                            .iter()
+                                                                            .map(|device| {
+                                                                                let sensor = device.sensor();
+                                                                                view! {
+                                                                                    <tr>
+                                                                                        <td>
+                                                                                            {[device.manufacturer.clone(), device.product.clone()]
+                                                                                                .into_iter()
+                                                                                                .flatten()
+                                                                                                .collect::<Vec<_>>()
+                                                                                                .join(" ")}
+                                                                                            {device
+                                                                                                .is_recording_device()
+                                                                                                .then_some(" (recording device)")}
+                                                                                        </td>
+                                                                                        <td>
+                                                                                            {sensor
+                                                                                                .map(|s| s.label().to_string())
+                                                                                                .or(device.device_type.clone())}
+                                                                                        </td>
+                                                                                        <td>{sensor.map(|s| s.provides())}</td>
+                                                                                        <td>{device.serial_number}</td>
+                                                                                        <td>{device.software_version}</td>
+                                                                                    </tr>
+                                                                                }
+                                                                            })
+                                                                            .collect_view()}
+                                                                    </tbody>
+                                                                </table>
+                                                        }
+                                                    })}
                                             </div>
                                         </div>
                                     }
@@ -0,0 +1,561 @@
+use chrono::{DateTime, Local};
+
+use super::ActivityData;
+
+/// Seconds between the unix epoch and the fit epoch (1989-12-31T00:00:00Z).
+const FIT_EPOCH_OFFSET: i64 = 631_065_600;
+const PROFILE_VERSION: u16 = 2132;
+const CRC_TABLE: [u16; 16] = [
+    0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401, 0xA001, 0x6C00, 0x7800, 0xB401,
+    0x5000, 0x9C01, 0x8801, 0x4400,
+];
+
+/// Sport enum values of the fit profile, by the names fitparser uses for them.
+const SPORTS: [(&str, u8); 20] = [
+    ("generic", 0),
+    ("running", 1),
+    ("cycling", 2),
+    ("transition", 3),
+    ("fitness_equipment", 4),
+    ("swimming", 5),
+    ("basketball", 6),
+    ("soccer", 7),
+    ("tennis", 8),
+    ("american_football", 9),
+    ("training", 10),
+    ("walking", 11),
+    ("cross_country_skiing", 12),
+    ("alpine_skiing", 13),
+    ("snowboarding", 14),
+    ("rowing", 15),
+    ("mountaineering", 16),
+    ("hiking", 17),
+    ("multisport", 18),
+    ("paddling", 19),
+];
+
+/// Timer event types of the fit profile, the timer itself is event 0.
+const TIMER_EVENT_TYPES: [(&str, u8); 5] = [
+    ("start", 0),
+    ("stop", 1),
+    ("stop_all", 4),
+    ("stop_disable", 8),
+    ("stop_disable_all", 9),
+];
+
+#[derive(Debug, Clone, Copy)]
+enum BaseType {
+    Enum,
+    SInt8,
+    UInt8,
+    UInt16,
+    SInt32,
+    UInt32,
+}
+
+impl BaseType {
+    fn size(&self) -> u8 {
+        match self {
+            BaseType::Enum | BaseType::SInt8 | BaseType::UInt8 => 1,
+            BaseType::UInt16 => 2,
+            BaseType::SInt32 | BaseType::UInt32 => 4,
+        }
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            BaseType::Enum => 0x00,
+            BaseType::SInt8 => 0x01,
+            BaseType::UInt8 => 0x02,
+            BaseType::UInt16 => 0x84,
+            BaseType::SInt32 => 0x85,
+            BaseType::UInt32 => 0x86,
+        }
+    }
+
+    /// Write a value, falling back to the invalid value of the type if it is missing or out of range.
+    fn write(&self, value: Option<i64>, buffer: &mut Vec<u8>) {
+        match self {
+            BaseType::Enum | BaseType::UInt8 => buffer.push(
+                value
+                    .filter(|v| (0..0xFF).contains(v))
+                    .map(|v| v as u8)
+                    .unwrap_or(0xFF),
+            ),
+            BaseType::SInt8 => buffer.push(
+                value
+                    .filter(|v| (-0x7F..0x7F).contains(v))
+                    .map(|v| v as i8)
+                    .unwrap_or(0x7F) as u8,
+            ),
+            BaseType::UInt16 => buffer.extend(
+                value
+                    .filter(|v| (0..0xFFFF).contains(v))
+                    .map(|v| v as u16)
+                    .unwrap_or(0xFFFF)
+                    .to_le_bytes(),
+            ),
+            BaseType::SInt32 => buffer.extend(
+                value
+                    .filter(|v| (-0x7FFF_FFFF..0x7FFF_FFFF).contains(v))
+                    .map(|v| v as i32)
+                    .unwrap_or(0x7FFF_FFFF)
+                    .to_le_bytes(),
+            ),
+            BaseType::UInt32 => buffer.extend(
+                value
+                    .filter(|v| (0..0xFFFF_FFFF).contains(v))
+                    .map(|v| v as u32)
+                    .unwrap_or(0xFFFF_FFFF)
+                    .to_le_bytes(),
+            ),
+        }
+    }
+}
+
+/// A message type, with its global message number and (field number, type) pairs.
+struct MessageDefinition {
+    local: u8,
+    global: u16,
+    fields: &'static [(u8, BaseType)],
+}
+
+const FILE_ID: MessageDefinition = MessageDefinition {
+    local: 0,
+    global: 0,
+    fields: &[
+        (0, BaseType::Enum),   // type
+        (1, BaseType::UInt16), // manufacturer
+        (2, BaseType::UInt16), // product
+        (4, BaseType::UInt32), // time_created
+    ],
+};
+const RECORD: MessageDefinition = MessageDefinition {
+    local: 1,
+    global: 20,
+    fields: &[
+        (253, BaseType::UInt32), // timestamp
+        (0, BaseType::SInt32),   // position_lat
+        (1, BaseType::SInt32),   // position_long
+        (3, BaseType::UInt8),    // heart_rate
+        (4, BaseType::UInt8),    // cadence
+        (5, BaseType::UInt32),   // distance
+        (73, BaseType::UInt32),  // enhanced_speed
+        (78, BaseType::UInt32),  // enhanced_altitude
+        (7, BaseType::UInt16),   // power
+        (13, BaseType::SInt8),   // temperature
+        (39, BaseType::UInt16),  // vertical_oscillation
+        (41, BaseType::UInt16),  // stance_time
+        (53, BaseType::UInt8),   // fractional_cadence
+        (85, BaseType::UInt16),  // step_length
+    ],
+};
+const LAP: MessageDefinition = MessageDefinition {
+    local: 2,
+    global: 19,
+    fields: &[
+        (253, BaseType::UInt32), // timestamp
+        (2, BaseType::UInt32),   // start_time
+        (7, BaseType::UInt32),   // total_elapsed_time
+        (8, BaseType::UInt32),   // total_timer_time
+        (9, BaseType::UInt32),   // total_distance
+        (11, BaseType::UInt16),  // total_calories
+        (15, BaseType::UInt8),   // avg_heart_rate
+        (16, BaseType::UInt8),   // max_heart_rate
+        (63, BaseType::UInt8),   // min_heart_rate
+        (19, BaseType::UInt16),  // avg_power
+        (21, BaseType::UInt16),  // total_ascent
+        (22, BaseType::UInt16),  // total_descent
+        (110, BaseType::UInt32), // enhanced_avg_speed
+        (111, BaseType::UInt32), // enhanced_max_speed
+        (25, BaseType::Enum),    // sport
+    ],
+};
+const SESSION: MessageDefinition = MessageDefinition {
+    local: 3,
+    global: 18,
+    fields: &[
+        (253, BaseType::UInt32), // timestamp
+        (2, BaseType::UInt32),   // start_time
+        (7, BaseType::UInt32),   // total_elapsed_time
+        (8, BaseType::UInt32),   // total_timer_time
+        (9, BaseType::UInt32),   // total_distance
+        (11, BaseType::UInt16),  // total_calories
+        (16, BaseType::UInt8),   // avg_heart_rate
+        (17, BaseType::UInt8),   // max_heart_rate
+        (64, BaseType::UInt8),   // min_heart_rate
+        (20, BaseType::UInt16),  // avg_power
+        (22, BaseType::UInt16),  // total_ascent
+        (23, BaseType::UInt16),  // total_descent
+        (124, BaseType::UInt32), // enhanced_avg_speed
+        (125, BaseType::UInt32), // enhanced_max_speed
+        (5, BaseType::Enum),     // sport
+    ],
+};
+const ACTIVITY: MessageDefinition = MessageDefinition {
+    local: 4,
+    global: 34,
+    fields: &[
+        (253, BaseType::UInt32), // timestamp
+        (0, BaseType::UInt32),   // total_timer_time
+        (1, BaseType::UInt16),   // num_sessions
+        (2, BaseType::Enum),     // type
+        (3, BaseType::Enum),     // event
+        (4, BaseType::Enum),     // event_type
+    ],
+};
+const EVENT: MessageDefinition = MessageDefinition {
+    local: 5,
+    global: 21,
+    fields: &[
+        (253, BaseType::UInt32), // timestamp
+        (0, BaseType::Enum),     // event
+        (1, BaseType::Enum),     // event_type
+    ],
+};
+
+struct FitWriter {
+    data: Vec<u8>,
+}
+
+impl FitWriter {
+    fn new() -> Self {
+        FitWriter { data: Vec::new() }
+    }
+
+    fn define(&mut self, definition: &MessageDefinition) {
+        self.data.push(0x40 | definition.local);
+        self.data.push(0); // reserved
+        self.data.push(0); // little endian
+        self.data.extend(definition.global.to_le_bytes());
+        self.data.push(definition.fields.len() as u8);
+        for (number, base_type) in definition.fields {
+            self.data.extend([*number, base_type.size(), base_type.id()]);
+        }
+    }
+
+    /// Write a data message, values have to be in the same order as the fields of the definition.
+    fn write(&mut self, definition: &MessageDefinition, values: &[Option<i64>]) {
+        self.data.push(definition.local);
+        for ((_, base_type), value) in definition.fields.iter().zip(values) {
+            base_type.write(*value, &mut self.data);
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut file = Vec::with_capacity(self.data.len() + 16);
+        file.push(14); // header size
+        file.push(0x20); // protocol version 2.0
+        file.extend(PROFILE_VERSION.to_le_bytes());
+        file.extend((self.data.len() as u32).to_le_bytes());
+        file.extend(b".FIT");
+        let header_crc = crc(&file);
+        file.extend(header_crc.to_le_bytes());
+        file.extend(self.data);
+        let file_crc = crc(&file);
+        file.extend(file_crc.to_le_bytes());
+        file
+    }
+}
+
+fn crc(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, &byte| {
+        let tmp = CRC_TABLE[(crc & 0xF) as usize];
+        let crc = (crc >> 4) & 0x0FFF;
+        let crc = crc ^ tmp ^ CRC_TABLE[(byte & 0xF) as usize];
+        let tmp = CRC_TABLE[(crc & 0xF) as usize];
+        let crc = (crc >> 4) & 0x0FFF;
+        crc ^ tmp ^ CRC_TABLE[((byte >> 4) & 0xF) as usize]
+    })
+}
+
+fn timestamp(time: DateTime<Local>) -> Option<i64> {
+    Some(time.timestamp() - FIT_EPOCH_OFFSET)
+}
+
+fn coordinate(value: Option<f64>) -> Option<i64> {
+    value.map(|v| (v * (u64::pow(2, 32) as f64 / 360.0)).round() as i64)
+}
+
+fn scaled(value: Option<f64>, scale: f64, offset: f64) -> Option<i64> {
+    value.map(|v| ((v + offset) * scale).round() as i64)
+}
+
+fn sport(value: Option<&str>) -> Option<i64> {
+    let value = value?;
+    SPORTS
+        .iter()
+        .find(|(name, _)| *name == value)
+        .map(|(_, id)| *id as i64)
+}
+
+/// Serialize an activity as a fit activity file.
+pub fn write_fit(data: &ActivityData) -> Vec<u8> {
+    let mut writer = FitWriter::new();
+    let activity = &data.activity;
+
+    writer.define(&FILE_ID);
+    writer.write(
+        &FILE_ID,
+        &[
+            Some(4),   // activity file
+            Some(255), // development manufacturer
+            Some(0),
+            timestamp(activity.start_time),
+        ],
+    );
+
+    writer.define(&EVENT);
+    for event in data.events.iter().filter(|e| e.event == "timer") {
+        let event_type = TIMER_EVENT_TYPES
+            .iter()
+            .find(|(name, _)| *name == event.event_type);
+        if let Some((_, event_type)) = event_type {
+            writer.write(
+                &EVENT,
+                &[
+                    timestamp(event.timestamp),
+                    Some(0),
+                    Some(*event_type as i64),
+                ],
+            );
+        }
+    }
+
+    writer.define(&RECORD);
+    for record in data.records.iter() {
+        writer.write(
+            &RECORD,
+            &[
+                timestamp(record.timestamp),
+                coordinate(record.latitude),
+                coordinate(record.longitude),
+                record.heartrate.map(|v| v as i64),
+                record.cadence.map(|v| v.trunc() as i64),
+                scaled(record.distance, 100.0, 0.0),
+                scaled(record.speed, 1000.0, 0.0),
+                scaled(record.altitude, 5.0, 500.0),
+                record.power.map(|v| v as i64),
+                record.temperature.map(|v| v as i64),
+                scaled(record.vertical_oscillation, 10.0, 0.0),
+                scaled(record.ground_contact_time, 10.0, 0.0),
+                scaled(record.cadence.map(|v| v.fract()), 128.0, 0.0),
+                scaled(record.step_length, 10.0, 0.0),
+            ],
+        );
+    }
+
+    writer.define(&LAP);
+    for lap in data.laps.iter() {
+        let elapsed = (lap.end_time - lap.start_time).num_milliseconds() as f64 / 1000.0;
+        writer.write(
+            &LAP,
+            &[
+                timestamp(lap.end_time),
+                timestamp(lap.start_time),
+                scaled(Some(elapsed), 1000.0, 0.0),
+                scaled(Some(elapsed), 1000.0, 0.0),
+                scaled(lap.distance, 100.0, 0.0),
+                lap.calories.map(|v| v as i64),
+                lap.average_heartrate.map(|v| v as i64),
+                lap.max_heartrate.map(|v| v as i64),
+                lap.min_heartrate.map(|v| v as i64),
+                lap.average_power.map(|v| v as i64),
+                lap.ascent.map(|v| v as i64),
+                lap.descent.map(|v| v as i64),
+                scaled(lap.average_speed, 1000.0, 0.0),
+                scaled(lap.max_speed, 1000.0, 0.0),
+                sport(lap.sport.as_deref()),
+            ],
+        );
+    }
+
+    writer.define(&SESSION);
+    for session in data.sessions.iter() {
+        let elapsed = (session.end_time - session.start_time).num_milliseconds() as f64 / 1000.0;
+        writer.write(
+            &SESSION,
+            &[
+                timestamp(session.end_time),
+                timestamp(session.start_time),
+                scaled(Some(elapsed), 1000.0, 0.0),
+                scaled(Some(elapsed), 1000.0, 0.0),
+                scaled(session.distance, 100.0, 0.0),
+                session.calories.map(|v| v as i64),
+                session.average_heartrate.map(|v| v as i64),
+                session.max_heartrate.map(|v| v as i64),
+                session.min_heartrate.map(|v| v as i64),
+                session.average_power.map(|v| v as i64),
+                session.ascent.map(|v| v as i64),
+                session.descent.map(|v| v as i64),
+                scaled(session.average_speed, 1000.0, 0.0),
+                scaled(session.max_speed, 1000.0, 0.0),
+                sport(session.sport.as_deref()),
+            ],
+        );
+    }
+
+    // the activity message is written when the activity ends
+    writer.define(&ACTIVITY);
+    writer.write(
+        &ACTIVITY,
+        &[
+            timestamp(activity.end_time),
+            scaled(Some(activity.duration), 1000.0, 0.0),
+            Some(data.sessions.len() as i64),
+            Some(0),  // manual
+            Some(26), // activity event
+            Some(1),  // stop
+        ],
+    );
+
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Local, TimeZone};
+
+    use super::write_fit;
+    use crate::{
+        models::{
+            activity::Activity,
+            base::{DatabaseEntry, New},
+            event::Event,
+            lap::Lap,
+            record::Record,
+            session::Session,
+        },
+        pages::activity_overview::export::ActivityData,
+    };
+
+    #[test]
+    fn test_fit_round_trip() {
+        let start = Local.with_ymd_and_hms(2023, 5, 1, 6, 0, 0).unwrap();
+        let records: Vec<Record> = (0..3)
+            .map(|i| Record {
+                timestamp: start + Duration::try_seconds(i).unwrap(),
+                heartrate: Some(120 + i as i16),
+                latitude: Some(47.3769 + i as f64 * 0.0001),
+                longitude: Some(8.5417),
+                distance: Some(i as f64 * 3.5),
+                speed: Some(3.5),
+                altitude: Some(408.2),
+                cadence: Some(80.5),
+                power: Some(250 + i as i16),
+                step_length: Some(1150.0),
+                vertical_oscillation: Some(82.5),
+                ground_contact_time: Some(245.0),
+                temperature: Some(21),
+            })
+            .collect();
+        let end = start + Duration::try_seconds(2).unwrap();
+        let session = Session {
+            start_time: start,
+            end_time: end,
+            sport: Some("running".to_string()),
+            distance: Some(7.0),
+            calories: Some(1),
+            average_heartrate: Some(121),
+            min_heartrate: Some(120),
+            max_heartrate: Some(122),
+            average_power: None,
+            ascent: Some(0),
+            descent: Some(0),
+            average_speed: Some(3.5),
+            max_speed: Some(3.5),
+            load: None,
+        };
+        let lap = Lap {
+            start_time: start,
+            end_time: end,
+            sport: Some("running".to_string()),
+            distance: Some(7.0),
+            calories: Some(1),
+            average_heartrate: Some(121),
+            min_heartrate: Some(120),
+            max_heartrate: Some(122),
+            average_power: None,
+            ascent: Some(0),
+            descent: Some(0),
+            average_speed: Some(3.5),
+            max_speed: Some(3.5),
+        };
+        let data = ActivityData {
+            activity: Activity {
+                user_id: None,
+                start_time: start,
+                end_time: end,
+                duration: 2.0,
+                moving_time: None,
+                load: None,
+                avg_heartrate: None,
+                file_hash: None,
+                rpe: None,
+                load_model: None,
+                power: None,
+                pace: None,
+            },
+            sessions: vec![session],
+            laps: vec![lap],
+            records: records.clone(),
+            events: vec![
+                Event::new(start, "timer", "start"),
+                Event::new(end, "timer", "stop_all"),
+            ],
+            devices: Vec::new(),
+        };
+
+        let file = write_fit(&data);
+        let messages = fitparser::from_bytes(&file).unwrap();
+        let mut parsed_records = Vec::new();
+        let mut parsed_activity = None;
+        let mut parsed_sessions = Vec::new();
+        let mut parsed_events = Vec::new();
+        for message in messages {
+            match message.kind() {
+                fitparser::profile::MesgNum::Record => {
+                    parsed_records.push(DatabaseEntry::<New, Record>::try_from(message).unwrap())
+                }
+                fitparser::profile::MesgNum::Session => {
+                    parsed_sessions.push(DatabaseEntry::<New, Session>::try_from(message).unwrap())
+                }
+                fitparser::profile::MesgNum::Event => {
+                    parsed_events.push(DatabaseEntry::<New, Event>::try_from(message).unwrap())
+                }
+                fitparser::profile::MesgNum::Activity => {
+                    parsed_activity =
+                        Some(DatabaseEntry::<New, Activity>::try_from(message).unwrap())
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(parsed_records.len(), records.len());
+        for (parsed, original) in parsed_records.iter().zip(records.iter()) {
+            let parsed = &parsed.state;
+            assert_eq!(parsed.timestamp, original.timestamp);
+            assert_eq!(parsed.heartrate, original.heartrate);
+            assert!((parsed.latitude.unwrap() - original.latitude.unwrap()).abs() < 0.000001);
+            assert!((parsed.longitude.unwrap() - original.longitude.unwrap()).abs() < 0.000001);
+            assert!((parsed.distance.unwrap() - original.distance.unwrap()).abs() < 0.01);
+            assert!((parsed.speed.unwrap() - original.speed.unwrap()).abs() < 0.001);
+            assert!((parsed.altitude.unwrap() - original.altitude.unwrap()).abs() < 0.2);
+            assert_eq!(parsed.cadence, original.cadence);
+            assert_eq!(parsed.power, original.power);
+            assert_eq!(parsed.step_length, original.step_length);
+            assert_eq!(parsed.vertical_oscillation, original.vertical_oscillation);
+            assert_eq!(parsed.ground_contact_time, original.ground_contact_time);
+            assert_eq!(parsed.temperature, original.temperature);
+        }
+        let parsed_activity = parsed_activity.unwrap();
+        assert_eq!(parsed_activity.state.start_time, start);
+        assert_eq!(parsed_activity.state.end_time, end);
+        assert_eq!(parsed_activity.state.duration, 2.0);
+        assert_eq!(parsed_sessions.len(), 1);
+        assert_eq!(parsed_sessions[0].state.sport.as_deref(), Some("running"));
+        assert_eq!(parsed_events.len(), 2);
+        assert!(parsed_events[0].state.starts_timer());
+        assert_eq!(parsed_events[1].state.timestamp, end);
+        assert_eq!(parsed_events[1].state.event_type, "stop_all");
+    }
+}
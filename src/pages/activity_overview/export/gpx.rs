@@ -0,0 +1,90 @@
+use std::fmt::Write;
+
+use super::{xml_escape, xml_time, ActivityData};
+
+/// Serialize an activity as a gpx 1.1 track, with heart rate and cadence in the Garmin
+/// TrackPointExtension.
+pub fn write_gpx(data: &ActivityData) -> String {
+    let mut gpx = String::new();
+    gpx.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    gpx.push('\n');
+    gpx.push_str(
+        r#"<gpx version="1.1" creator="Toedi" xmlns="http://www.topografix.com/GPX/1/1" xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1">"#,
+    );
+    gpx.push('\n');
+    let _ = writeln!(
+        gpx,
+        " <metadata><time>{}</time></metadata>",
+        xml_time(data.activity.start_time)
+    );
+    gpx.push_str(" <trk>\n");
+    if let Some(sport) = data.sport() {
+        let _ = writeln!(gpx, "  <type>{}</type>", xml_escape(sport));
+    }
+    gpx.push_str("  <trkseg>\n");
+    for record in data.records.iter() {
+        let (Some(latitude), Some(longitude)) = (record.latitude, record.longitude) else {
+            // gpx track points require a position
+            continue;
+        };
+        let _ = writeln!(gpx, r#"   <trkpt lat="{}" lon="{}">"#, latitude, longitude);
+        if let Some(altitude) = record.altitude {
+            let _ = writeln!(gpx, "    <ele>{}</ele>", altitude);
+        }
+        let _ = writeln!(gpx, "    <time>{}</time>", xml_time(record.timestamp));
+        if record.heartrate.is_some() || record.cadence.is_some() || record.temperature.is_some() {
+            gpx.push_str("    <extensions><gpxtpx:TrackPointExtension>");
+            if let Some(temperature) = record.temperature {
+                let _ = write!(gpx, "<gpxtpx:atemp>{}</gpxtpx:atemp>", temperature);
+            }
+            if let Some(heartrate) = record.heartrate {
+                let _ = write!(gpx, "<gpxtpx:hr>{}</gpxtpx:hr>", heartrate);
+            }
+            if let Some(cadence) = record.cadence {
+                let _ = write!(gpx, "<gpxtpx:cad>{}</gpxtpx:cad>", cadence.round());
+            }
+            gpx.push_str("</gpxtpx:TrackPointExtension></extensions>\n");
+        }
+        gpx.push_str("   </trkpt>\n");
+    }
+    gpx.push_str("  </trkseg>\n </trk>\n</gpx>\n");
+    gpx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_gpx;
+    use crate::pages::{activity_overview::export::tests::activity_data, fit_upload::parse_file};
+
+    #[test]
+    fn test_gpx_round_trip() {
+        let data = activity_data("running");
+        let parsed = parse_file(write_gpx(&data).as_bytes()).unwrap();
+        assert_eq!(parsed.activity.state.start_time, data.activity.start_time);
+        assert_eq!(parsed.activity.state.end_time, data.activity.end_time);
+        assert_eq!(parsed.sessions[0].state.sport.as_deref(), Some("running"));
+        assert_eq!(parsed.records.len(), data.records.len());
+        for (i, (parsed, original)) in parsed.records.iter().zip(data.records.iter()).enumerate() {
+            let parsed = &parsed.state;
+            assert_eq!(parsed.timestamp, original.timestamp);
+            assert_eq!(parsed.latitude, original.latitude);
+            assert_eq!(parsed.longitude, original.longitude);
+            assert_eq!(parsed.heartrate, original.heartrate);
+            assert_eq!(parsed.altitude, original.altitude);
+            assert_eq!(parsed.cadence, original.cadence);
+            assert_eq!(parsed.temperature, original.temperature);
+            // gpx has no distance and speed, they are calculated from the positions again
+            assert!((parsed.distance.unwrap() - original.distance.unwrap()).abs() < 0.01);
+            if i > 0 {
+                assert!((parsed.speed.unwrap() - original.speed.unwrap()).abs() < 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gpx_escapes_sport() {
+        let data = activity_data("<run> & \"jump\"");
+        let parsed = parse_file(write_gpx(&data).as_bytes()).unwrap();
+        assert_eq!(parsed.sessions[0].state.sport.as_deref(), Some("generic"));
+    }
+}
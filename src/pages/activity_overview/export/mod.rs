@@ -0,0 +1,418 @@
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use strum::{Display, EnumString};
+
+use crate::{
+    authentication::AuthSession,
+    models::{
+        activity::Activity,
+        device::Device,
+        event::Event,
+        lap::Lap,
+        load::{LoadModel, PowerMetrics},
+        pace::PaceMetrics,
+        record::Record,
+        session::Session,
+    },
+    state::AppState,
+};
+
+pub mod fit;
+pub mod gpx;
+pub mod tcx;
+
+/// File formats an activity can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum ExportFormat {
+    Fit,
+    Gpx,
+    Tcx,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Fit => "application/vnd.ant.fit",
+            ExportFormat::Gpx => "application/gpx+xml",
+            ExportFormat::Tcx => "application/vnd.garmin.tcx+xml",
+        }
+    }
+}
+
+/// A stored activity with all its entries, as needed for exporting.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityData {
+    pub activity: Activity,
+    pub sessions: Vec<Session>,
+    pub laps: Vec<Lap>,
+    pub records: Vec<Record>,
+    #[serde(default)]
+    pub events: Vec<Event>,
+    #[serde(default)]
+    pub devices: Vec<Device>,
+}
+
+impl ActivityData {
+    /// The sport of the first session, used for formats that only support one sport per activity.
+    pub fn sport(&self) -> Option<&str> {
+        self.sessions.first().and_then(|s| s.sport.as_deref())
+    }
+
+    pub fn export(&self, format: ExportFormat) -> Vec<u8> {
+        match format {
+            ExportFormat::Fit => fit::write_fit(self),
+            ExportFormat::Gpx => gpx::write_gpx(self).into_bytes(),
+            ExportFormat::Tcx => tcx::write_tcx(self).into_bytes(),
+        }
+    }
+}
+
+pub async fn export_activity(
+    State(state): State<AppState>,
+    auth: AuthSession,
+    Path((activity_id, format)): Path<(i64, String)>,
+) -> Response {
+    let user = if let Some(user) = auth.current_user {
+        user
+    } else {
+        return (StatusCode::FORBIDDEN, "Not logged in".to_string()).into_response();
+    };
+    let format = match format.parse::<ExportFormat>() {
+        Ok(format) => format,
+        Err(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Unsupported export format: {}", format),
+            )
+                .into_response()
+        }
+    };
+    let data = match load_activity_data(activity_id, user.id, &state.pool).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Activity not found").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("{}", e)).into_response(),
+    };
+    let file_name = format!(
+        "{}_{}.{}",
+        data.activity.start_time.format("%Y-%m-%d_%H-%M"),
+        data.sport().unwrap_or("activity"),
+        format
+    );
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+        ],
+        data.export(format),
+    )
+        .into_response()
+}
+
+/// Load an activity of a user with all its entries.
+pub async fn load_activity_data(
+    activity_id: i64,
+    user_id: i64,
+    pool: &PgPool,
+) -> Result<Option<ActivityData>> {
+    let activity = sqlx::query!(
+        r#"
+        SELECT start_time, end_time, duration::float8 AS "duration!", moving_time::float8,
+            load, avg_heartrate, file_hash, rpe, load_model as "load_model: LoadModel",
+            normalized_power, intensity_factor::float8, tss, normalized_graded_speed::float8,
+            pace_intensity_factor::float8, rtss
+        FROM activities
+        WHERE id=$1 AND user_id=$2
+        "#,
+        activity_id,
+        user_id as _
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Couldn't load activity")?;
+    let activity = match activity {
+        Some(row) => Activity {
+            user_id: Some(user_id),
+            start_time: row.start_time.into(),
+            end_time: row.end_time.into(),
+            duration: row.duration,
+            moving_time: row.moving_time,
+            load: row.load.map(|l| l as u32),
+            avg_heartrate: row.avg_heartrate.map(|hr| hr as u16),
+            file_hash: row.file_hash,
+            rpe: row.rpe.map(|v| v as u8),
+            load_model: row.load_model,
+            power: row.normalized_power.map(|normalized_power| PowerMetrics {
+                normalized_power: normalized_power as u16,
+                intensity_factor: row.intensity_factor.unwrap_or(0.0),
+                tss: row.tss.unwrap_or(0) as u32,
+            }),
+            pace: row
+                .normalized_graded_speed
+                .map(|normalized_graded_speed| PaceMetrics {
+                    normalized_graded_speed,
+                    intensity_factor: row.pace_intensity_factor.unwrap_or(0.0),
+                    rtss: row.rtss.unwrap_or(0) as u32,
+                }),
+        },
+        None => return Ok(None),
+    };
+    let sessions = sqlx::query!(
+        r#"
+        SELECT start_time, end_time, sport, distance::float8, calories::int4, average_heartrate,
+            min_heartrate, max_heartrate, average_power::int4, ascent::int4, descent::int4,
+            average_speed::float8, max_speed::float8, load
+        FROM sessions
+        WHERE activity_id=$1
+        ORDER BY start_time
+        "#,
+        activity_id as i32
+    )
+    .fetch_all(pool)
+    .await
+    .context("Couldn't load sessions")?
+    .into_iter()
+    .map(|row| Session {
+        start_time: row.start_time.into(),
+        end_time: row.end_time.into(),
+        sport: Some(row.sport),
+        distance: row.distance,
+        calories: row.calories,
+        average_heartrate: row.average_heartrate,
+        min_heartrate: row.min_heartrate,
+        max_heartrate: row.max_heartrate,
+        average_power: row.average_power,
+        ascent: row.ascent,
+        descent: row.descent,
+        average_speed: row.average_speed,
+        max_speed: row.max_speed,
+        load: row.load.map(|l| l as u32),
+    })
+    .collect();
+    let laps = sqlx::query!(
+        r#"
+        SELECT start_time, end_time, sport, distance::float8, calories::int4, average_heartrate,
+            min_heartrate, max_heartrate, average_power::int4, ascent::int4, descent::int4,
+            average_speed::float8, max_speed::float8
+        FROM laps
+        WHERE activity_id=$1
+        ORDER BY start_time
+        "#,
+        activity_id as i32
+    )
+    .fetch_all(pool)
+    .await
+    .context("Couldn't load laps")?
+    .into_iter()
+    .map(|row| Lap {
+        start_time: row.start_time.into(),
+        end_time: row.end_time.into(),
+        sport: row.sport,
+        distance: row.distance,
+        calories: row.calories,
+        average_heartrate: row.average_heartrate,
+        min_heartrate: row.min_heartrate,
+        max_heartrate: row.max_heartrate,
+        average_power: row.average_power,
+        ascent: row.ascent,
+        descent: row.descent,
+        average_speed: row.average_speed,
+        max_speed: row.max_speed,
+    })
+    .collect();
+    let records = sqlx::query!(
+        r#"
+        SELECT timestamp, heartrate, latitude, longitude, distance::float8, speed::float8,
+            altitude::float8, cadence::float8, power, step_length::float8,
+            vertical_oscillation::float8, ground_contact_time::float8, temperature
+        FROM records
+        WHERE activity_id=$1
+        ORDER BY timestamp
+        "#,
+        activity_id as i32
+    )
+    .fetch_all(pool)
+    .await
+    .context("Couldn't load records")?
+    .into_iter()
+    .map(|row| Record {
+        timestamp: row.timestamp.into(),
+        heartrate: row.heartrate,
+        latitude: row.latitude,
+        longitude: row.longitude,
+        distance: row.distance,
+        speed: row.speed,
+        altitude: row.altitude,
+        cadence: row.cadence,
+        power: row.power,
+        step_length: row.step_length,
+        vertical_oscillation: row.vertical_oscillation,
+        ground_contact_time: row.ground_contact_time,
+        temperature: row.temperature,
+    })
+    .collect();
+    let events = sqlx::query!(
+        r#"
+        SELECT date_recorded, event, event_type
+        FROM events
+        WHERE activity_id=$1
+        ORDER BY date_recorded
+        "#,
+        activity_id as i32
+    )
+    .fetch_all(pool)
+    .await
+    .context("Couldn't load events")?
+    .into_iter()
+    .map(|row| Event {
+        timestamp: row.date_recorded.into(),
+        event: row.event,
+        event_type: row.event_type,
+    })
+    .collect();
+    let devices = load_devices(activity_id, pool).await?;
+    Ok(Some(ActivityData {
+        activity,
+        sessions,
+        laps,
+        records,
+        events,
+        devices,
+    }))
+}
+
+/// The devices an activity was recorded with, the recording device first.
+pub async fn load_devices(activity_id: i64, pool: &PgPool) -> Result<Vec<Device>> {
+    sqlx::query_as!(
+        Device,
+        r#"
+        SELECT device_index, device_type, source_type, manufacturer, product, serial_number,
+            software_version::float8
+        FROM devices
+        WHERE activity_id=$1
+        ORDER BY device_index NULLS LAST, id
+        "#,
+        activity_id as i32
+    )
+    .fetch_all(pool)
+    .await
+    .context("Couldn't load devices")
+}
+
+/// Format a timestamp the way gpx and tcx expect it.
+fn xml_time(time: DateTime<Local>) -> String {
+    time.with_timezone(&Utc)
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string()
+}
+
+/// Escape text for xml element content and attribute values.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use chrono::{Duration, Local, TimeZone};
+
+    use super::ActivityData;
+    use crate::models::{activity::Activity, lap::Lap, record::Record, session::Session};
+
+    /// An activity of one session and lap with a record every 3 seconds, moving north by 0.0001
+    /// degrees latitude in between, as a round trip test case.
+    pub fn activity_data(sport: &str) -> ActivityData {
+        let start = Local.with_ymd_and_hms(2023, 5, 1, 6, 0, 0).unwrap();
+        // 0.0001 degrees latitude with the earth radius of the distance calculation
+        let step = 0.0001f64.to_radians() * 6_371_000.0;
+        let records: Vec<Record> = (0..4)
+            .map(|i| Record {
+                timestamp: start + Duration::try_seconds(i * 3).unwrap(),
+                heartrate: Some(120 + i as i16),
+                latitude: Some(47.3769 + i as f64 * 0.0001),
+                longitude: Some(8.5417),
+                distance: Some(i as f64 * step),
+                speed: Some(step / 3.0),
+                altitude: Some(408.2 + i as f64),
+                cadence: Some(80.0),
+                power: Some(250 + i as i16),
+                step_length: None,
+                vertical_oscillation: None,
+                ground_contact_time: None,
+                temperature: Some(21),
+            })
+            .collect();
+        let end = records.last().unwrap().timestamp;
+        ActivityData {
+            activity: Activity {
+                user_id: None,
+                start_time: start,
+                end_time: end,
+                duration: 9.0,
+                moving_time: None,
+                load: None,
+                avg_heartrate: None,
+                file_hash: None,
+                rpe: None,
+                load_model: None,
+                power: None,
+                pace: None,
+            },
+            sessions: vec![Session {
+                start_time: start,
+                end_time: end,
+                sport: Some(sport.to_string()),
+                distance: Some(3.0 * step),
+                calories: Some(1),
+                average_heartrate: Some(121),
+                min_heartrate: Some(120),
+                max_heartrate: Some(123),
+                average_power: None,
+                ascent: Some(3),
+                descent: Some(0),
+                average_speed: Some(step / 3.0),
+                max_speed: Some(step / 3.0),
+                load: None,
+            }],
+            laps: vec![Lap {
+                start_time: start,
+                end_time: end,
+                sport: Some(sport.to_string()),
+                distance: Some(3.0 * step),
+                calories: Some(1),
+                average_heartrate: Some(121),
+                min_heartrate: Some(120),
+                max_heartrate: Some(123),
+                average_power: None,
+                ascent: Some(3),
+                descent: Some(0),
+                average_speed: Some(step / 3.0),
+                max_speed: Some(step / 3.0),
+            }],
+            records,
+            events: Vec::new(),
+            devices: Vec::new(),
+        }
+    }
+}
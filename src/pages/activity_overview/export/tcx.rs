@@ -0,0 +1,162 @@
+use std::fmt::Write;
+
+use super::{xml_time, ActivityData};
+
+fn tcx_sport(sport: Option<&str>) -> &'static str {
+    match sport {
+        Some("running") => "Running",
+        Some("cycling") => "Biking",
+        _ => "Other",
+    }
+}
+
+/// Serialize an activity as a tcx file, with one `<Lap>` per stored lap.
+///
+/// Activities without laps are exported as a single lap spanning the whole activity.
+pub fn write_tcx(data: &ActivityData) -> String {
+    let mut tcx = String::new();
+    tcx.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    tcx.push('\n');
+    tcx.push_str(
+        r#"<TrainingCenterDatabase xmlns="http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2" xmlns:ns3="http://www.garmin.com/xmlschemas/ActivityExtension/v2">"#,
+    );
+    tcx.push_str("\n <Activities>\n");
+    let _ = writeln!(tcx, r#"  <Activity Sport="{}">"#, tcx_sport(data.sport()));
+    let _ = writeln!(tcx, "   <Id>{}</Id>", xml_time(data.activity.start_time));
+
+    let mut laps: Vec<_> = data
+        .laps
+        .iter()
+        .map(|l| {
+            (
+                l.start_time,
+                l.end_time,
+                l.distance,
+                l.calories,
+                l.average_heartrate,
+                l.max_heartrate,
+                l.max_speed,
+            )
+        })
+        .collect();
+    if laps.is_empty() {
+        laps.push((
+            data.activity.start_time,
+            data.activity.end_time,
+            None,
+            None,
+            None,
+            None,
+            None,
+        ));
+    }
+    let last_lap = laps.len() - 1;
+    for (index, (start, end, distance, calories, average_hr, max_hr, max_speed)) in
+        laps.into_iter().enumerate()
+    {
+        let _ = writeln!(tcx, r#"   <Lap StartTime="{}">"#, xml_time(start));
+        let _ = writeln!(
+            tcx,
+            "    <TotalTimeSeconds>{}</TotalTimeSeconds>",
+            (end - start).num_milliseconds() as f64 / 1000.0
+        );
+        if let Some(distance) = distance {
+            let _ = writeln!(tcx, "    <DistanceMeters>{}</DistanceMeters>", distance);
+        }
+        if let Some(max_speed) = max_speed {
+            let _ = writeln!(tcx, "    <MaximumSpeed>{}</MaximumSpeed>", max_speed);
+        }
+        let _ = writeln!(tcx, "    <Calories>{}</Calories>", calories.unwrap_or(0));
+        if let Some(average_hr) = average_hr {
+            let _ = writeln!(
+                tcx,
+                "    <AverageHeartRateBpm><Value>{}</Value></AverageHeartRateBpm>",
+                average_hr
+            );
+        }
+        if let Some(max_hr) = max_hr {
+            let _ = writeln!(
+                tcx,
+                "    <MaximumHeartRateBpm><Value>{}</Value></MaximumHeartRateBpm>",
+                max_hr
+            );
+        }
+        tcx.push_str("    <Intensity>Active</Intensity>\n");
+        tcx.push_str("    <TriggerMethod>Manual</TriggerMethod>\n");
+        tcx.push_str("    <Track>\n");
+        // records on a lap boundary belong to the later lap, the last lap includes its end
+        for record in data.records.iter().filter(|r| {
+            r.timestamp >= start && (r.timestamp < end || (index == last_lap && r.timestamp == end))
+        }) {
+            tcx.push_str("     <Trackpoint>\n");
+            let _ = writeln!(tcx, "      <Time>{}</Time>", xml_time(record.timestamp));
+            if let (Some(latitude), Some(longitude)) = (record.latitude, record.longitude) {
+                let _ = writeln!(
+                    tcx,
+                    "      <Position><LatitudeDegrees>{}</LatitudeDegrees><LongitudeDegrees>{}</LongitudeDegrees></Position>",
+                    latitude, longitude
+                );
+            }
+            if let Some(altitude) = record.altitude {
+                let _ = writeln!(tcx, "      <AltitudeMeters>{}</AltitudeMeters>", altitude);
+            }
+            if let Some(distance) = record.distance {
+                let _ = writeln!(tcx, "      <DistanceMeters>{}</DistanceMeters>", distance);
+            }
+            if let Some(heartrate) = record.heartrate {
+                let _ = writeln!(
+                    tcx,
+                    "      <HeartRateBpm><Value>{}</Value></HeartRateBpm>",
+                    heartrate
+                );
+            }
+            if let Some(cadence) = record.cadence {
+                let _ = writeln!(tcx, "      <Cadence>{}</Cadence>", cadence.round());
+            }
+            if record.speed.is_some() || record.power.is_some() {
+                tcx.push_str("      <Extensions><ns3:TPX>");
+                if let Some(speed) = record.speed {
+                    let _ = write!(tcx, "<ns3:Speed>{}</ns3:Speed>", speed);
+                }
+                if let Some(power) = record.power {
+                    let _ = write!(tcx, "<ns3:Watts>{}</ns3:Watts>", power);
+                }
+                tcx.push_str("</ns3:TPX></Extensions>\n");
+            }
+            tcx.push_str("     </Trackpoint>\n");
+        }
+        tcx.push_str("    </Track>\n   </Lap>\n");
+    }
+    tcx.push_str("  </Activity>\n </Activities>\n</TrainingCenterDatabase>\n");
+    tcx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_tcx;
+    use crate::pages::{activity_overview::export::tests::activity_data, fit_upload::parse_file};
+
+    #[test]
+    fn test_tcx_round_trip() {
+        let data = activity_data("cycling");
+        let parsed = parse_file(write_tcx(&data).as_bytes()).unwrap();
+        assert_eq!(parsed.activity.state.start_time, data.activity.start_time);
+        assert_eq!(parsed.activity.state.end_time, data.activity.end_time);
+        assert_eq!(parsed.sessions[0].state.sport.as_deref(), Some("cycling"));
+        assert_eq!(parsed.laps.len(), 1);
+        assert_eq!(parsed.laps[0].state.distance, data.laps[0].distance);
+        assert_eq!(parsed.records.len(), data.records.len());
+        for (parsed, original) in parsed.records.iter().zip(data.records.iter()) {
+            let parsed = &parsed.state;
+            assert_eq!(parsed.timestamp, original.timestamp);
+            assert_eq!(parsed.latitude, original.latitude);
+            assert_eq!(parsed.longitude, original.longitude);
+            assert_eq!(parsed.heartrate, original.heartrate);
+            assert_eq!(parsed.distance, original.distance);
+            assert_eq!(parsed.speed, original.speed);
+            assert_eq!(parsed.altitude, original.altitude);
+            assert_eq!(parsed.cadence, original.cadence);
+            assert_eq!(parsed.power, original.power);
+        }
+    }
+}
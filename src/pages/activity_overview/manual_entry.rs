@@ -0,0 +1,333 @@
+use crate::app::FitFileUploaded;
+#[cfg(feature = "ssr")]
+use crate::{
+    app::{auth, pool},
+    models::{
+        activity::{insert_activity, Activity},
+        aggregates::update_aggregates,
+        base::{DatabaseEntry, New},
+        load::estimate_load,
+        session::{insert_sessions, Session},
+    },
+};
+#[cfg(feature = "ssr")]
+use chrono::Duration;
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use leptos::{ev::SubmitEvent, *};
+
+/// Longest activity that can be entered manually, in minutes.
+const MAX_DURATION: u32 = 24 * 60;
+
+/// Sports that can be entered manually, by their fit name.
+const SPORTS: [(&str, &str); 9] = [
+    ("training", "Strength Training"),
+    ("rock_climbing", "Climbing"),
+    ("swimming", "Swimming"),
+    ("running", "Running"),
+    ("cycling", "Cycling"),
+    ("walking", "Walking"),
+    ("hiking", "Hiking"),
+    ("rowing", "Rowing"),
+    ("generic", "Other"),
+];
+
+/// Add an activity that wasn't recorded, estimating its load from the average heart rate or
+/// the perceived exertion.
+///
+/// The load only applies to the activity, sessions only get a load from heart rate records.
+///
+/// `duration` is in minutes.
+#[server(CreateManualActivity, "/api")]
+pub async fn create_manual_activity(
+    sport: String,
+    start_time: DateTime<Local>,
+    duration: u32,
+    average_heartrate: Option<u16>,
+    rpe: Option<u8>,
+) -> Result<(), ServerFnError> {
+    let pool = pool()?;
+    let auth = auth()?;
+    let user = auth
+        .current_user
+        .ok_or(ServerFnError::new("Not logged in".to_string()))?;
+    if duration == 0 {
+        return Err(ServerFnError::new(
+            "Duration has to be positive".to_string(),
+        ));
+    }
+    if duration > MAX_DURATION {
+        return Err(ServerFnError::new(format!(
+            "Duration can be at most {} minutes",
+            MAX_DURATION
+        )));
+    }
+    if !SPORTS.iter().any(|(name, _)| *name == sport) {
+        return Err(ServerFnError::new("Unknown sport".to_string()));
+    }
+    if rpe.is_some_and(|rpe| !(1..=10).contains(&rpe)) {
+        return Err(ServerFnError::new(
+            "RPE has to be between 1 and 10".to_string(),
+        ));
+    }
+    if average_heartrate.is_some_and(|hr| !(30..=250).contains(&hr)) {
+        return Err(ServerFnError::new(
+            "Average heart rate has to be between 30 and 250".to_string(),
+        ));
+    }
+    let duration = duration as f64 * 60.0;
+    let (load, load_model) =
+        estimate_load(start_time, duration, average_heartrate, rpe, user.id, &pool)
+            .await
+            .map_err(|e| ServerFnError::new(format!("Couldn't estimate load:{}", e)))?
+            .unzip();
+    let end_time = start_time + Duration::try_seconds(duration as i64).unwrap();
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| ServerFnError::new(format!("Couldn't start transaction:{}", e)))?;
+    let activity = insert_activity(
+        DatabaseEntry {
+            state: Box::new(Activity {
+                user_id: Some(user.id),
+                start_time,
+                end_time,
+                duration,
+                moving_time: Some(duration),
+                load,
+                avg_heartrate: average_heartrate,
+                file_hash: None,
+                rpe,
+                load_model,
+                power: None,
+                pace: None,
+            }),
+            extra: New,
+        },
+        user.id,
+        &mut *tx,
+    )
+    .await
+    .map_err(|e| ServerFnError::new(format!("Couldn't add activity:{}", e)))?;
+    insert_sessions(
+        vec![DatabaseEntry {
+            state: Box::new(Session {
+                start_time,
+                end_time,
+                sport: Some(sport),
+                distance: None,
+                calories: None,
+                average_heartrate: average_heartrate.map(|hr| hr as i16),
+                min_heartrate: None,
+                max_heartrate: None,
+                average_power: None,
+                ascent: None,
+                descent: None,
+                average_speed: None,
+                max_speed: None,
+                load: None,
+            }),
+            extra: New,
+        }],
+        activity.extra.activity_id,
+        &mut *tx,
+    )
+    .await
+    .map_err(|e| ServerFnError::new(format!("Couldn't add activity:{}", e)))?;
+    update_aggregates(activity.extra.activity_id, user.id, &mut tx)
+        .await
+        .map_err(|e| ServerFnError::new(format!("Couldn't update aggregates:{}", e)))?;
+    tx.commit()
+        .await
+        .map_err(|e| ServerFnError::new(format!("Couldn't commit changes:{}", e)))?;
+    Ok(())
+}
+
+#[component]
+pub fn ManualActivityForm(show: RwSignal<bool>) -> impl IntoView {
+    let uploaded = use_context::<FitFileUploaded>().unwrap();
+    let create_activity = create_server_action::<CreateManualActivity>();
+    let sport = create_rw_signal(SPORTS[0].0.to_string());
+    let start_time = create_rw_signal(Local::now().format("%Y-%m-%dT%H:%M").to_string());
+    let duration = create_rw_signal(60u32);
+    let average_heartrate = create_rw_signal(None::<u16>);
+    let rpe = create_rw_signal(None::<u8>);
+    let error = create_rw_signal(None::<String>);
+    let close = move |_| {
+        show.set(false);
+        error.set(None);
+    };
+    let on_submit = move |ev: SubmitEvent| {
+        ev.prevent_default();
+        let start = NaiveDateTime::parse_from_str(&start_time.get(), "%Y-%m-%dT%H:%M")
+            .ok()
+            .and_then(|start| Local.from_local_datetime(&start).single());
+        match start {
+            Some(start) => {
+                error.set(None);
+                create_activity.dispatch(CreateManualActivity {
+                    sport: sport.get(),
+                    start_time: start,
+                    duration: duration.get(),
+                    average_heartrate: average_heartrate.get(),
+                    rpe: rpe.get(),
+                });
+            }
+            None => error.set(Some("Invalid start time".to_string())),
+        }
+    };
+    create_effect(move |_| match create_activity.value().get() {
+        Some(Ok(())) => {
+            uploaded.0.update(|v| *v += 1);
+            show.set(false);
+        }
+        Some(Err(e)) => error.set(Some(e.to_string())),
+        None => {}
+    });
+    view! {
+        <Show when=move || { show() } fallback=|| {}>
+            <form on:submit=on_submit>
+                <div class="modal is-active">
+                    <div class="modal-background" on:click=close></div>
+                    <div class="modal-card">
+                        <div class="modal-card-head">
+                            <p class="modal-card-title">"Add Activity"</p>
+                            <button
+                                type="button"
+                                class="delete"
+                                aria-label="close"
+                                on:click=close
+                            ></button>
+                        </div>
+                        <div class="modal-card-body">
+                            <div class="field">
+                                <label class="label">Sport</label>
+                                <div class="control">
+                                    <div class="select">
+                                        <select on:change=move |ev| {
+                                            sport.set(event_target_value(&ev))
+                                        }>
+                                            {SPORTS
+                                                .into_iter()
+                                                .map(|(value, label)| {
+                                                    view! {
+                                                        <option
+                                                            value=value
+                                                            selected=move || sport.get() == value
+                                                        >
+                                                            {label}
+                                                        </option>
+                                                    }
+                                                })
+                                                .collect_view()}
+                                        </select>
+                                    </div>
+                                </div>
+                            </div>
+                            <div class="columns">
+                                <div class="column">
+                                    <div class="field">
+                                        <label class="label">Start</label>
+                                        <div class="control">
+                                            <input
+                                                class="input"
+                                                type="datetime-local"
+                                                required
+                                                prop:value=start_time
+                                                on:input=move |ev| {
+                                                    start_time.set(event_target_value(&ev))
+                                                }
+                                            />
+                                        </div>
+                                    </div>
+                                </div>
+                                <div class="column">
+                                    <div class="field">
+                                        <label class="label">Duration (minutes)</label>
+                                        <div class="control">
+                                            <input
+                                                class="input"
+                                                type="number"
+                                                min="1"
+                                                max=MAX_DURATION
+                                                required
+                                                prop:value=duration
+                                                on:input=move |ev| {
+                                                    if let Ok(value) = event_target_value(&ev)
+                                                        .parse::<u32>()
+                                                    {
+                                                        duration.set(value);
+                                                    }
+                                                }
+                                            />
+                                        </div>
+                                    </div>
+                                </div>
+                            </div>
+                            <div class="columns">
+                                <div class="column">
+                                    <div class="field">
+                                        <label class="label">Avg. Heartrate</label>
+                                        <div class="control">
+                                            <input
+                                                class="input"
+                                                type="number"
+                                                min="30"
+                                                max="250"
+                                                placeholder="optional"
+                                                on:input=move |ev| {
+                                                    average_heartrate
+                                                        .set(event_target_value(&ev).parse::<u16>().ok())
+                                                }
+                                            />
+                                        </div>
+                                    </div>
+                                </div>
+                                <div class="column">
+                                    <div class="field">
+                                        <label class="label">RPE (1-10)</label>
+                                        <div class="control">
+                                            <input
+                                                class="input"
+                                                type="number"
+                                                min="1"
+                                                max="10"
+                                                placeholder="optional"
+                                                on:input=move |ev| {
+                                                    rpe.set(event_target_value(&ev).parse::<u8>().ok())
+                                                }
+                                            />
+                                        </div>
+                                    </div>
+                                </div>
+                            </div>
+                            <p class="help">
+                                "The load is estimated from the average heart rate, or from the perceived exertion if there is none."
+                            </p>
+                            {move || {
+                                error
+                                    .get()
+                                    .map(|e| {
+                                        view! { <p class="help is-danger">{e}</p> }
+                                    })
+                            }}
+
+                        </div>
+                        <div class="modal-card-foot">
+                            <button
+                                type="submit"
+                                class="button is-primary"
+                                class:is-loading=create_activity.pending()
+                            >
+                                "Add"
+                            </button>
+                            <button type="button" class="button" on:click=close>
+                                "Cancel"
+                            </button>
+                        </div>
+                    </div>
+                </div>
+            </form>
+        </Show>
+    }
+}
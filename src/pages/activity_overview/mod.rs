@@ -2,25 +2,54 @@ use std::time::Duration;
 
 #[cfg(feature = "ssr")]
 use crate::app::{auth, pool};
-use crate::{app::FitFileUploaded, error_template::ErrorTemplate};
+#[cfg(feature = "ssr")]
+use crate::models::aggregates::{aggregated_days, update_daily_aggregates};
+#[cfg(feature = "ssr")]
+use crate::pages::fit_upload::originals::{original_path, remove_original};
+use crate::{app::FitFileUploaded, error_template::ErrorTemplate, models::load::LoadModel};
 use activity_details::ActivityDetails;
 use bigdecimal::{BigDecimal, ToPrimitive};
 use chrono::{DateTime, Local};
 use humantime::format_duration;
 use leptos::*;
+use leptos_router::use_query_map;
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "ssr")]
 use sqlx::*;
 
 pub mod activity_details;
+#[cfg(feature = "ssr")]
+pub mod export;
+pub mod manual_entry;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ActivityListEntry {
     pub id: i64,
     pub start_time: DateTime<Local>,
     pub duration: BigDecimal,
+    pub moving_time: Option<BigDecimal>,
+    pub elapsed_time: BigDecimal,
     pub load: Option<i32>,
+    pub load_model: Option<LoadModel>,
     pub sport: String,
+    /// Whether the heart rate was measured at the wrist, like [`optical_heartrate`] of the
+    /// activity's devices, `None` if unknown.
+    ///
+    /// [`optical_heartrate`]: crate::models::device::optical_heartrate
+    pub optical_heartrate: Option<bool>,
+}
+
+impl ActivityListEntry {
+    /// Moving time, falling back to the timer time for activities imported before it was
+    /// calculated.
+    pub fn moving_time(&self) -> &BigDecimal {
+        self.moving_time.as_ref().unwrap_or(&self.duration)
+    }
+}
+
+/// Format a duration in seconds, without the fractional part.
+pub fn format_seconds(seconds: &BigDecimal) -> String {
+    format_duration(Duration::new(seconds.to_u64().unwrap_or(0), 0)).to_string()
 }
 
 #[server(ActivityList, "/api")]
@@ -38,8 +67,25 @@ pub async fn get_activity_list() -> Result<Vec<ActivityListEntry>, ServerFnError
             activities.id, 
             activities.start_time, 
             activities.duration,
+            activities.moving_time,
+            ROUND(EXTRACT(EPOCH FROM activities.end_time - activities.start_time)::numeric, 1) as "elapsed_time!",
             activities.load,
-            COALESCE(string_agg(sessions.sport,', '),'General') as "sport!" 
+            activities.load_model as "load_model: LoadModel",
+            COALESCE(
+                string_agg(sessions.sport, ', ' ORDER BY sessions.start_time)
+                    FILTER (WHERE sessions.sport <> 'transition'),
+                'General'
+            ) as "sport!",
+            (
+                SELECT CASE
+                    WHEN bool_or(device_type = 'heart_rate' AND source_type IS DISTINCT FROM 'local')
+                        THEN false
+                    WHEN bool_or(device_type = 'whr' OR (device_type = 'heart_rate' AND source_type = 'local'))
+                        THEN true
+                END
+                FROM devices
+                WHERE devices.activity_id = activities.id
+            ) as "optical_heartrate?"
         FROM activities 
         JOIN sessions on sessions.activity_id=activities.id
         WHERE activities.user_id = $1::bigint
@@ -58,6 +104,14 @@ pub async fn delete_activity(activity_id: i64) -> Result<(), ServerFnError> {
     let user = auth
         .current_user
         .ok_or(ServerFnError::new("Not logged in".to_string()))?;
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| ServerFnError::new(format!("Couldn't start transaction:{}", e)))?;
+    let path = original_path(activity_id, user.id, &mut *tx)
+        .await
+        .map_err(|e| ServerFnError::new(format!("{:#}", e)))?;
+    let days = aggregated_days(activity_id, &mut *tx).await?;
     sqlx::query!(
         r#"
         DELETE FROM activities
@@ -66,9 +120,25 @@ pub async fn delete_activity(activity_id: i64) -> Result<(), ServerFnError> {
         user.id as _,
         activity_id
     )
-    .execute(&pool)
+    .execute(&mut *tx)
     .await
     .map_err(|e| ServerFnError::new(format!("Couldn't delete activity:{}", e)))?;
+    update_daily_aggregates(user.id, &days, &mut tx)
+        .await
+        .map_err(|e| ServerFnError::new(format!("Couldn't update aggregates:{}", e)))?;
+    tx.commit()
+        .await
+        .map_err(|e| ServerFnError::new(format!("Couldn't commit changes:{}", e)))?;
+    // only once the activity is gone, a file left behind is better than a missing one
+    if let Some(path) = path {
+        if let Err(e) = remove_original(&path).await {
+            leptos::logging::warn!(
+                "Couldn't remove original file of activity {}: {:#}",
+                activity_id,
+                e
+            );
+        }
+    }
 
     Ok(())
 }
@@ -77,10 +147,28 @@ pub async fn delete_activity(activity_id: i64) -> Result<(), ServerFnError> {
 pub fn ActivityList() -> impl IntoView {
     let uploaded = use_context::<FitFileUploaded>().unwrap();
     let activities = create_resource(move || (uploaded.0.get()), move |_| get_activity_list());
-    let show_activity = create_rw_signal(None);
+    // links to an activity, e.g. from the records, open its details
+    let query = use_query_map();
+    let show_activity = create_rw_signal(
+        query.with_untracked(|query| query.get("activity").and_then(|id| id.parse().ok())),
+    );
     let delete_activity = create_server_action::<DeleteActivity>();
+    // wrist heart rate isn't trusted for the load
+    let hide_optical = create_rw_signal(false);
     view! {
         <div class="container">
+            <div class="field">
+                <div class="control">
+                    <label class="checkbox">
+                        <input
+                            type="checkbox"
+                            prop:checked=hide_optical
+                            on:change=move |_| hide_optical.update(|v| *v = !*v)
+                        />
+                        " Hide activities with wrist heart rate"
+                    </label>
+                </div>
+            </div>
             <Transition fallback=move || view! { <p>"Loading..."</p> }>
                 <ErrorBoundary fallback=|errors| {
                     view! { <ErrorTemplate errors=errors/> }
@@ -105,7 +193,7 @@ pub fn ActivityList() -> impl IntoView {
                                                         <h6 class="title is-6">Date</h6>
                                                     </div>
                                                     <div class="column is-one-fifth">
-                                                        <h6 class="title is-6">Duration</h6>
+                                                        <h6 class="title is-6">Moving / Elapsed</h6>
                                                     </div>
                                                     <div class="column is-one-fifth">
                                                         <h6 class="title is-6">Load</h6>
@@ -115,7 +203,15 @@ pub fn ActivityList() -> impl IntoView {
                                             <div class="column is-1"></div>
                                         </div>
                                         <For
-                                            each=move || activities.clone()
+                                            each=move || {
+                                                activities
+                                                    .iter()
+                                                    .filter(|activity| {
+                                                        !hide_optical() || activity.optical_heartrate != Some(true)
+                                                    })
+                                                    .cloned()
+                                                    .collect::<Vec<_>>()
+                                            }
                                             key=|e| e.id
                                             let:activity
                                         >
@@ -128,19 +224,45 @@ pub fn ActivityList() -> impl IntoView {
                                                                 href="#!"
                                                                 on:click=move |_| show_activity.set(Some(activity.id))
                                                             >
-                                                                {activity.sport}
+                                                                {activity.sport.clone()}
                                                             </a>
+                                                            {(activity.optical_heartrate == Some(true))
+                                                                .then(|| {
+                                                                    view! {
+                                                                        <span
+                                                                            class="tag is-warning ml-2"
+                                                                            title="Heart rate was measured at the wrist"
+                                                                        >
+                                                                            "Optical HR"
+                                                                        </span>
+                                                                    }
+                                                                })}
                                                         </div>
                                                         <div class="column is-one-fifth">
                                                             {activity.start_time.format("%Y-%m-%d").to_string()}
                                                         </div>
                                                         <div class="column is-one-fifth">
-                                                            {format_duration(
-                                                                    Duration::new(activity.duration.to_u64().unwrap(), 0),
-                                                                )
-                                                                .to_string()}
+                                                            {format_seconds(activity.moving_time())}
+                                                            <span class="has-text-grey">
+                                                                " / " {format_seconds(&activity.elapsed_time)}
+                                                            </span>
+                                                        </div>
+                                                        <div class="column is-one-fifth">
+                                                            {activity.load}
+                                                            {activity
+                                                                .load
+                                                                .and(activity.load_model)
+                                                                .map(|model| {
+                                                                    view! {
+                                                                        <span
+                                                                            class="tag is-light ml-2"
+                                                                            title=model.description()
+                                                                        >
+                                                                            {model.label()}
+                                                                        </span>
+                                                                    }
+                                                                })}
                                                         </div>
-                                                        <div class="column is-one-fifth">{activity.load}</div>
                                                     </div>
                                                 </div>
                                                 <div class="column is-1">
@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use super::{activity_load, load_summary, set_session_loads, ParsedActivity};
+use crate::{
+    models::{
+        activity::{update_activity_summary, Activity},
+        aggregates::update_aggregates,
+        base::{DatabaseEntry, New},
+        device::insert_devices,
+        event::{insert_events, moving_time},
+        lap::insert_laps,
+        record::{insert_records, Record},
+        session::{insert_sessions, update_session_loads},
+    },
+    pages::activity_overview::export::load_activity_data,
+};
+
+/// An already stored activity matching an upload.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Duplicate {
+    /// The exact same file was uploaded before.
+    SameFile(i64),
+    /// A different file covering the same time was uploaded, e.g. from a second device.
+    Overlapping(i64),
+}
+
+impl Duplicate {
+    pub fn activity_id(&self) -> i64 {
+        match self {
+            Duplicate::SameFile(id) | Duplicate::Overlapping(id) => *id,
+        }
+    }
+}
+
+pub fn file_hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Find a stored activity of the user that is the same file or overlaps in time with `activity`.
+pub async fn find_duplicate(
+    activity: &Activity,
+    user_id: i64,
+    conn: &mut sqlx::PgConnection,
+) -> Result<Option<Duplicate>> {
+    if let Some(hash) = &activity.file_hash {
+        let same_file = sqlx::query_scalar!(
+            r#"
+            SELECT id
+            FROM activities
+            WHERE user_id=$1 AND file_hash=$2
+            LIMIT 1
+            "#,
+            user_id as i32,
+            hash
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .context("Couldn't check for duplicate files")?;
+        if let Some(id) = same_file {
+            return Ok(Some(Duplicate::SameFile(id)));
+        }
+    }
+    let overlapping = sqlx::query_scalar!(
+        r#"
+        SELECT id
+        FROM activities
+        WHERE user_id=$1 AND start_time < $3 AND end_time > $2
+        ORDER BY start_time
+        LIMIT 1
+        "#,
+        user_id as i32,
+        activity.start_time,
+        activity.end_time
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .context("Couldn't check for overlapping activities")?;
+    Ok(overlapping.map(Duplicate::Overlapping))
+}
+
+/// Merge an uploaded activity into an already stored one.
+///
+/// Records at the same timestamp are combined, keeping stored values and filling in the ones
+/// missing, records only present in the upload are added. Sessions, laps and events are only
+/// taken from the upload if the stored activity has none, devices of the upload are added if
+/// they aren't stored yet. Heart rate, load and moving time are recalculated afterwards.
+///
+/// The activity is marked as merged, as its original file alone doesn't have all its entries
+/// anymore.
+pub async fn merge_activity(
+    parsed: ParsedActivity,
+    activity_id: i64,
+    user_id: i64,
+    executor: &PgPool,
+) -> Result<()> {
+    let stored = load_activity_data(activity_id, user_id, executor)
+        .await?
+        .context("Activity to merge into doesn't exist")?;
+    let mut records: BTreeMap<_, Record> = stored
+        .records
+        .into_iter()
+        .map(|r| (r.timestamp, r))
+        .collect();
+    for record in parsed.records {
+        records
+            .entry(record.state.timestamp)
+            .and_modify(|r| r.fill_missing(&record.state))
+            .or_insert(*record.state);
+    }
+    let records: Vec<DatabaseEntry<New, Record>> = records
+        .into_values()
+        .map(|r| DatabaseEntry {
+            state: Box::new(r),
+            extra: New,
+        })
+        .collect();
+
+    let start_time = stored
+        .activity
+        .start_time
+        .min(parsed.activity.state.start_time);
+    let end_time = stored.activity.end_time.max(parsed.activity.state.end_time);
+    let duration = stored.activity.duration.max(parsed.activity.state.duration);
+    let (events, new_events) = if stored.events.is_empty() {
+        let events = parsed.events.iter().map(|e| (*e.state).clone()).collect();
+        (events, parsed.events)
+    } else {
+        (stored.events, Vec::new())
+    };
+    let mut new_sessions = parsed.sessions;
+    let summary = if stored.sessions.is_empty() {
+        load_summary(
+            &records,
+            &events,
+            &new_sessions,
+            user_id,
+            start_time,
+            executor,
+        )
+        .await
+    } else {
+        load_summary(
+            &records,
+            &events,
+            &stored.sessions,
+            user_id,
+            start_time,
+            executor,
+        )
+        .await
+    };
+    let moving_time = moving_time(&records, &events);
+    let (load, load_model) = activity_load(
+        &summary,
+        start_time,
+        duration,
+        stored.activity.rpe,
+        user_id,
+        executor,
+    )
+    .await?
+    .unzip();
+    let activity = Activity {
+        start_time,
+        end_time,
+        duration,
+        moving_time,
+        load,
+        load_model,
+        avg_heartrate: summary.avg_heartrate,
+        power: summary.power,
+        pace: summary.pace,
+        ..stored.activity
+    };
+    let new_devices: Vec<_> = parsed
+        .devices
+        .into_iter()
+        .filter(|d| !stored.devices.iter().any(|s| s.same_device(&d.state)))
+        .collect();
+
+    let mut tx = executor.begin().await?;
+    sqlx::query!(
+        "DELETE FROM records WHERE activity_id=$1",
+        activity_id as i32
+    )
+    .execute(&mut *tx)
+    .await
+    .context("Couldn't replace records")?;
+    insert_records(records, activity_id, &mut *tx).await?;
+    if stored.sessions.is_empty() {
+        set_session_loads(&mut new_sessions, &summary);
+        insert_sessions(new_sessions, activity_id, &mut *tx).await?;
+    } else {
+        let mut sessions = stored.sessions;
+        for (session, load) in sessions.iter_mut().zip(&summary.session_loads) {
+            session.load = *load;
+        }
+        update_session_loads(&sessions, activity_id, &mut *tx).await?;
+    }
+    if stored.laps.is_empty() {
+        insert_laps(parsed.laps, activity_id, &mut *tx).await?;
+    }
+    insert_events(new_events, activity_id, &mut *tx).await?;
+    insert_devices(new_devices, activity_id, &mut *tx).await?;
+    update_activity_summary(activity_id, &activity, &mut *tx)
+        .await
+        .context("Couldn't update merged activity")?;
+    sqlx::query!("UPDATE activities SET merged=true WHERE id=$1", activity_id)
+        .execute(&mut *tx)
+        .await
+        .context("Couldn't mark activity as merged")?;
+    update_aggregates(activity_id, user_id, &mut tx)
+        .await
+        .context("Couldn't update aggregates")?;
+    tx.commit().await.context("Transaction failed, try again")?;
+    Ok(())
+}
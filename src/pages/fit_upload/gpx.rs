@@ -0,0 +1,243 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use roxmltree::{Document, Node};
+
+use super::{
+    haversine_distance,
+    xml::{child_text, descendant_text, LocalName},
+    NoActivityFound, ParsedActivity, RecordSummary,
+};
+use crate::models::{
+    activity::Activity,
+    base::{DatabaseEntry, New},
+    lap::Lap,
+    record::Record,
+    session::Session,
+};
+
+/// Parse a gpx file into an activity.
+///
+/// Each track segment becomes a lap, the whole track a session. Heart rate and cadence are read
+/// from the Garmin TrackPointExtension if present. Distance and speed aren't part of gpx, so they
+/// are calculated from the positions.
+pub fn parse_gpx_file(data: &[u8]) -> Result<ParsedActivity> {
+    let text = std::str::from_utf8(data).context("gpx file is not valid utf-8")?;
+    let document = Document::parse(text).context("Failed to read gpx file")?;
+    let track = document
+        .root_element()
+        .children()
+        .find(|n| n.has_tag_name_local("trk"))
+        .ok_or(NoActivityFound("No track found in gpx file"))?;
+    let sport = track
+        .children()
+        .find(|n| n.has_tag_name_local("type"))
+        .and_then(|n| n.text())
+        .map(normalize_sport)
+        .unwrap_or_else(|| "generic".to_string());
+
+    let mut records: Vec<DatabaseEntry<New, Record>> = Vec::new();
+    let mut laps: Vec<DatabaseEntry<New, Lap>> = Vec::new();
+    let mut distance = 0.0;
+    for segment in track.children().filter(|n| n.has_tag_name_local("trkseg")) {
+        let first_record = records.len();
+        for point in segment.children().filter(|n| n.has_tag_name_local("trkpt")) {
+            let mut record = parse_trackpoint(point)?;
+            if let Some(previous) = records.last() {
+                if let (Some(lat1), Some(lon1), Some(lat2), Some(lon2)) = (
+                    previous.state.latitude,
+                    previous.state.longitude,
+                    record.state.latitude,
+                    record.state.longitude,
+                ) {
+                    let delta = haversine_distance((lat1, lon1), (lat2, lon2));
+                    distance += delta;
+                    let seconds = (record.state.timestamp - previous.state.timestamp)
+                        .num_milliseconds() as f64
+                        / 1000.0;
+                    if seconds > 0.0 {
+                        record.state.speed = Some(delta / seconds);
+                    }
+                }
+            }
+            record.state.distance = Some(distance);
+            records.push(record);
+        }
+        if records.len() > first_record {
+            laps.push(summary_lap(&records[first_record..], &sport));
+        }
+    }
+    if records.is_empty() {
+        return Err(NoActivityFound("No track points found in gpx file").into());
+    }
+
+    let start_time = records.first().unwrap().state.timestamp;
+    let end_time = records.last().unwrap().state.timestamp;
+    let summary = RecordSummary::from_records(&records);
+    let session = DatabaseEntry {
+        state: Box::new(Session {
+            start_time,
+            end_time,
+            sport: Some(sport),
+            distance: summary.distance,
+            calories: None,
+            average_heartrate: summary.average_heartrate,
+            min_heartrate: summary.min_heartrate,
+            max_heartrate: summary.max_heartrate,
+            average_power: None,
+            ascent: summary.ascent,
+            descent: summary.descent,
+            average_speed: summary.average_speed,
+            max_speed: summary.max_speed,
+            load: None,
+        }),
+        extra: New,
+    };
+    let activity = DatabaseEntry {
+        state: Box::new(Activity {
+            user_id: None,
+            start_time,
+            end_time,
+            duration: (end_time - start_time).num_seconds() as f64,
+            moving_time: None,
+            load: None,
+            avg_heartrate: None,
+            file_hash: None,
+            rpe: None,
+            load_model: None,
+            power: None,
+            pace: None,
+        }),
+        extra: New,
+    };
+    Ok(ParsedActivity {
+        activity,
+        sessions: vec![session],
+        laps,
+        records,
+        events: Vec::new(),
+        devices: Vec::new(),
+    })
+}
+
+fn parse_trackpoint(point: Node) -> Result<DatabaseEntry<New, Record>> {
+    let timestamp = child_text(point, "time").context("track point without time")?;
+    let timestamp = DateTime::parse_from_rfc3339(timestamp.trim())
+        .context("track point time is not a date")?
+        .with_timezone(&Local);
+    let latitude = point.attribute("lat").and_then(|v| v.parse::<f64>().ok());
+    let longitude = point.attribute("lon").and_then(|v| v.parse::<f64>().ok());
+    let altitude = child_text(point, "ele").and_then(|v| v.trim().parse::<f64>().ok());
+    let extensions = point
+        .children()
+        .find(|n| n.has_tag_name_local("extensions"));
+    let heartrate = extensions
+        .and_then(|e| descendant_text(e, &["hr", "heartrate"]))
+        .and_then(|v| v.trim().parse::<i16>().ok());
+    let cadence = extensions
+        .and_then(|e| descendant_text(e, &["cad", "cadence"]))
+        .and_then(|v| v.trim().parse::<f64>().ok());
+    let power = extensions
+        .and_then(|e| descendant_text(e, &["power", "PowerInWatts"]))
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .map(|v| v.round() as i16);
+    let temperature = extensions
+        .and_then(|e| descendant_text(e, &["atemp", "temp"]))
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .map(|v| v.round() as i16);
+    Ok(DatabaseEntry {
+        state: Box::new(Record {
+            timestamp,
+            heartrate,
+            latitude,
+            longitude,
+            distance: None,
+            speed: None,
+            altitude,
+            cadence,
+            power,
+            step_length: None,
+            vertical_oscillation: None,
+            ground_contact_time: None,
+            temperature,
+        }),
+        extra: New,
+    })
+}
+
+fn summary_lap(records: &[DatabaseEntry<New, Record>], sport: &str) -> DatabaseEntry<New, Lap> {
+    let summary = RecordSummary::from_records(records);
+    DatabaseEntry {
+        state: Box::new(Lap {
+            start_time: records.first().unwrap().state.timestamp,
+            end_time: records.last().unwrap().state.timestamp,
+            sport: Some(sport.to_string()),
+            distance: summary.distance,
+            calories: None,
+            average_heartrate: summary.average_heartrate,
+            min_heartrate: summary.min_heartrate,
+            max_heartrate: summary.max_heartrate,
+            average_power: None,
+            ascent: summary.ascent,
+            descent: summary.descent,
+            average_speed: summary.average_speed,
+            max_speed: summary.max_speed,
+        }),
+        extra: New,
+    }
+}
+
+/// Map the activity type of a gpx track to the sport names used in fit files.
+///
+/// Strava exports use numeric activity types, other tools use free text.
+fn normalize_sport(value: &str) -> String {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "ride" | "cycling" | "biking" | "road_biking" | "mountain_biking" => "cycling",
+        "9" | "run" | "running" | "trail_running" => "running",
+        "10" | "walk" | "walking" => "walking",
+        "4" | "hike" | "hiking" => "hiking",
+        "16" | "swim" | "swimming" => "swimming",
+        "rowing" => "rowing",
+        _ => "generic",
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_gpx_file;
+
+    const GPX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx creator="StravaGPX" version="1.1" xmlns="http://www.topografix.com/GPX/1/1" xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1">
+ <trk>
+  <name>Morning Run</name>
+  <type>9</type>
+  <trkseg>
+   <trkpt lat="47.3769" lon="8.5417">
+    <ele>408.0</ele>
+    <time>2023-05-01T06:00:00Z</time>
+    <extensions><gpxtpx:TrackPointExtension><gpxtpx:hr>120</gpxtpx:hr><gpxtpx:cad>80</gpxtpx:cad></gpxtpx:TrackPointExtension></extensions>
+   </trkpt>
+   <trkpt lat="47.3779" lon="8.5417">
+    <ele>410.0</ele>
+    <time>2023-05-01T06:00:30Z</time>
+    <extensions><gpxtpx:TrackPointExtension><gpxtpx:hr>140</gpxtpx:hr><gpxtpx:cad>84</gpxtpx:cad></gpxtpx:TrackPointExtension></extensions>
+   </trkpt>
+  </trkseg>
+ </trk>
+</gpx>"#;
+
+    #[test]
+    fn test_parse_gpx() {
+        let parsed = parse_gpx_file(GPX.as_bytes()).unwrap();
+        assert_eq!(parsed.records.len(), 2);
+        assert_eq!(parsed.laps.len(), 1);
+        assert_eq!(parsed.sessions[0].state.sport.as_deref(), Some("running"));
+        assert_eq!(parsed.activity.state.duration, 30.0);
+        let last = &parsed.records[1].state;
+        assert_eq!(last.heartrate, Some(140));
+        assert_eq!(last.cadence, Some(84.0));
+        // 0.001 degrees latitude are roughly 111m
+        assert!((last.distance.unwrap() - 111.2).abs() < 0.5);
+        assert!((last.speed.unwrap() - 3.7).abs() < 0.1);
+    }
+}
@@ -0,0 +1,198 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use sqlx::{types::Json, PgPool};
+
+use super::{process_file, DuplicateStrategy, UploadOutcome, UploadStatus};
+
+/// How often idle workers look for new jobs.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// How often a job is tried before its failure is reported.
+const MAX_ATTEMPTS: i32 = 3;
+/// Finished jobs are kept this long so the upload report can still be fetched.
+const KEEP_FINISHED_DAYS: i32 = 7;
+
+/// Queue an uploaded file for ingestion, returning the job id.
+pub async fn enqueue_file(
+    file_name: &str,
+    data: &[u8],
+    user_id: i64,
+    strategy: DuplicateStrategy,
+    executor: &PgPool,
+) -> Result<i64> {
+    sqlx::query_scalar!(
+        r#"
+        INSERT INTO ingestion_jobs (user_id, file_name, data, duplicates)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id
+        "#,
+        user_id as i32,
+        file_name,
+        data,
+        strategy.to_string()
+    )
+    .fetch_one(executor)
+    .await
+    .context("Couldn't queue file")
+}
+
+/// The current state of a user's ingestion jobs.
+pub async fn job_outcomes(
+    job_ids: &[i64],
+    user_id: i64,
+    executor: &PgPool,
+) -> Result<Vec<UploadOutcome>> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, file_name, status::text AS "status!", result as "result: Json<UploadStatus>"
+        FROM ingestion_jobs
+        WHERE user_id=$1 AND id = ANY($2)
+        ORDER BY id
+        "#,
+        user_id as i32,
+        job_ids
+    )
+    .fetch_all(executor)
+    .await
+    .context("Couldn't load upload progress")?;
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let status = match row.status.as_str() {
+                "queued" => UploadStatus::Queued,
+                "running" => UploadStatus::Processing,
+                _ => row
+                    .result
+                    .map(|r| r.0)
+                    .unwrap_or_else(|| UploadStatus::Failed {
+                        error: "Job finished without a result".to_string(),
+                    }),
+            };
+            UploadOutcome {
+                file_name: row.file_name,
+                job_id: Some(row.id),
+                status,
+            }
+        })
+        .collect())
+}
+
+/// Start the ingestion workers.
+///
+/// Jobs that were running when the server stopped are queued again, as their transaction never
+/// got committed.
+pub async fn start_workers(pool: PgPool, count: usize) -> Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE ingestion_jobs
+        SET status='queued', updated_at=now()
+        WHERE status='running'
+        "#
+    )
+    .execute(&pool)
+    .await
+    .context("Couldn't requeue interrupted jobs")?;
+    sqlx::query!(
+        r#"
+        DELETE FROM ingestion_jobs
+        WHERE status='done' AND updated_at < now() - make_interval(days => $1)
+        "#,
+        KEEP_FINISHED_DAYS
+    )
+    .execute(&pool)
+    .await
+    .context("Couldn't clean up finished jobs")?;
+    for _ in 0..count {
+        let pool = pool.clone();
+        tokio::spawn(async move {
+            loop {
+                match run_next_job(&pool).await {
+                    Ok(true) => {}
+                    Ok(false) => tokio::time::sleep(POLL_INTERVAL).await,
+                    Err(e) => {
+                        leptos::logging::error!("Ingestion worker failed: {:#}", e);
+                        tokio::time::sleep(POLL_INTERVAL).await;
+                    }
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Claim and run the next queued job, returns false if there was nothing to do.
+async fn run_next_job(pool: &PgPool) -> Result<bool> {
+    let job = sqlx::query!(
+        r#"
+        UPDATE ingestion_jobs
+        SET status='running', attempts=attempts + 1, updated_at=now()
+        WHERE id = (
+            SELECT id
+            FROM ingestion_jobs
+            WHERE status='queued' AND run_after <= now()
+            ORDER BY id
+            FOR UPDATE SKIP LOCKED
+            LIMIT 1
+        )
+        RETURNING id, user_id::int8 AS "user_id!", file_name, data, duplicates, attempts
+        "#
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Couldn't claim job")?;
+    let job = match job {
+        Some(job) => job,
+        None => return Ok(false),
+    };
+    let job_id = job.id;
+    let attempts = job.attempts;
+    let strategy = job
+        .duplicates
+        .parse::<DuplicateStrategy>()
+        .unwrap_or_default();
+
+    let status = match job.data {
+        Some(data) => {
+            process_file(
+                &job.file_name,
+                Bytes::from(data),
+                job.user_id,
+                strategy,
+                pool.clone(),
+            )
+            .await
+        }
+        None => UploadStatus::Failed {
+            error: "Uploaded file is missing".to_string(),
+        },
+    };
+    if matches!(status, UploadStatus::Failed { .. }) && attempts < MAX_ATTEMPTS {
+        sqlx::query!(
+            r#"
+            UPDATE ingestion_jobs
+            SET status='queued', run_after=now() + make_interval(secs => $2), updated_at=now()
+            WHERE id=$1
+            "#,
+            job_id,
+            30.0 * attempts as f64
+        )
+        .execute(pool)
+        .await
+        .context("Couldn't requeue job")?;
+    } else {
+        sqlx::query!(
+            r#"
+            UPDATE ingestion_jobs
+            SET status='done', result=$2, data=NULL, updated_at=now()
+            WHERE id=$1
+            "#,
+            job_id,
+            Json(&status) as _
+        )
+        .execute(pool)
+        .await
+        .context("Couldn't finish job")?;
+    }
+    Ok(true)
+}
@@ -1,4 +1,6 @@
-use std::time::Duration;
+#[cfg(feature = "ssr")]
+use std::cmp;
+use std::{rc::Rc, time::Duration};
 
 #[cfg(feature = "ssr")]
 use anyhow::{bail, Context, Result};
@@ -6,25 +8,43 @@ use anyhow::{bail, Context, Result};
 use axum::{
     extract::{Multipart, State},
     http::StatusCode,
-    response::IntoResponse,
+    response::{IntoResponse, Response},
+    Json,
 };
 #[cfg(feature = "ssr")]
 use bytes::Bytes;
+#[cfg(feature = "ssr")]
+use chrono::{DateTime, Local};
+#[cfg(feature = "ssr")]
+use itertools::Itertools;
 use leptos::ev::SubmitEvent;
+use leptos::leptos_dom::helpers::TimeoutHandle;
 use leptos::*;
 use leptos_router::*;
+use serde::{Deserialize, Serialize};
 #[cfg(feature = "ssr")]
 use sqlx::PgPool;
+use strum::{Display, EnumString};
+#[cfg(feature = "ssr")]
+use thiserror::Error;
+use wasm_bindgen_futures::JsFuture;
 
 use crate::app::FitFileUploaded;
+#[cfg(feature = "ssr")]
+use crate::app::{auth, pool};
 
 #[cfg(feature = "ssr")]
 use crate::authentication::User;
 #[cfg(feature = "ssr")]
 use crate::models::{
     activity::{insert_activity, Activity},
+    aggregates::update_aggregates,
     base::{DatabaseEntry, New},
+    device::{combine_devices, insert_devices, Device},
+    event::{insert_events, moving_mask, moving_time, Event},
     lap::{insert_laps, Lap},
+    load::{combined_load, estimate_load, LoadModel, PowerMetrics},
+    pace::{grade_adjusted_speeds, PaceMetrics},
     record::{insert_records, Record},
     session::{insert_sessions, Session},
 };
@@ -33,36 +53,422 @@ use crate::state::AppState;
 #[cfg(feature = "ssr")]
 use axum_session_auth::{AuthSession, SessionPgPool};
 
+#[cfg(feature = "ssr")]
+use self::duplicates::Duplicate;
+
+#[cfg(feature = "ssr")]
+pub mod duplicates;
+#[cfg(feature = "ssr")]
+mod gpx;
+#[cfg(feature = "ssr")]
+pub mod jobs;
+#[cfg(feature = "ssr")]
+pub mod originals;
+#[cfg(feature = "ssr")]
+mod reprocess;
+#[cfg(feature = "ssr")]
+mod tcx;
+#[cfg(feature = "ssr")]
+pub mod watch;
+#[cfg(feature = "ssr")]
+mod xml;
+
 #[cfg(feature = "ssr")]
 pub async fn upload_fit_file(
     State(state): State<AppState>,
     auth: AuthSession<User, i64, SessionPgPool, PgPool>,
     mut multipart: Multipart,
-) -> impl IntoResponse {
+) -> Response {
     let user = if let Some(user) = auth.current_user {
         user
     } else {
-        return (StatusCode::FORBIDDEN, "Not logged in".to_string());
+        return (StatusCode::FORBIDDEN, "Not logged in".to_string()).into_response();
     };
-    while let Some(field) = multipart.next_field().await.unwrap() {
-        let data = field.bytes().await.unwrap();
-        let result = process_fit_file(data, user.id, state.pool.clone()).await;
-        if let Err(x) = result {
-            return (StatusCode::BAD_REQUEST, format!("{}", x));
+    let mut strategy = DuplicateStrategy::default();
+    let mut files = Vec::new();
+    let mut outcomes = Vec::new();
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => {
+                // the rest of the upload can't be read, but the files received so far are fine
+                outcomes.push(UploadOutcome {
+                    file_name: String::new(),
+                    job_id: None,
+                    status: UploadStatus::Failed {
+                        error: format!("Upload was interrupted: {}", e),
+                    },
+                });
+                break;
+            }
+        };
+        if field.name() == Some("duplicates") {
+            let value = field.text().await.unwrap_or_default();
+            strategy = match value.parse::<DuplicateStrategy>() {
+                Ok(strategy) => strategy,
+                Err(_) => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        format!("Unknown duplicate handling: {}", value),
+                    )
+                        .into_response()
+                }
+            };
+            continue;
         }
+        let file_name = field.file_name().unwrap_or("").to_string();
+        files.push((file_name, field.bytes().await));
+    }
+    let mut results = Vec::with_capacity(files.len());
+    for (file_name, data) in files {
+        let queued = match data {
+            Ok(data) => jobs::enqueue_file(&file_name, &data, user.id, strategy, &state.pool).await,
+            Err(e) => Err(anyhow::anyhow!("Couldn't read file: {}", e)),
+        };
+        results.push(match queued {
+            Ok(job_id) => UploadOutcome {
+                file_name,
+                job_id: Some(job_id),
+                status: UploadStatus::Queued,
+            },
+            Err(e) => UploadOutcome {
+                file_name,
+                job_id: None,
+                status: UploadStatus::Failed {
+                    error: format!("{:#}", e),
+                },
+            },
+        });
+    }
+    results.append(&mut outcomes);
+    (StatusCode::ACCEPTED, Json(results)).into_response()
+}
+
+/// Parse the original files of all activities again and recalculate heart rate and load.
+///
+/// This runs in the background, the number of activities being reprocessed is returned, along
+/// with how many of them are merged from several files and only recalculated.
+#[server]
+pub async fn reprocess_activities() -> Result<(usize, usize), ServerFnError> {
+    let pool = pool()?;
+    let auth = auth()?;
+    let user = auth
+        .current_user
+        .ok_or(ServerFnError::new("Not logged in".to_string()))?;
+    let count = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "activities!", COUNT(*) FILTER (WHERE merged) AS "merged!"
+        FROM activities
+        WHERE user_id=$1
+        "#,
+        user.id as i32
+    )
+    .fetch_one(&pool)
+    .await
+    .map_err(|e| ServerFnError::new(format!("Couldn't count activities: {}", e)))?;
+    tokio::spawn(async move {
+        match reprocess::reprocess_all(user.id, &pool).await {
+            Ok((reprocessed, failed)) => {
+                leptos::logging::log!("Reprocessed {} activities, {} failed", reprocessed, failed)
+            }
+            Err(e) => leptos::logging::error!("Couldn't reprocess activities: {:#}", e),
+        }
+    });
+    Ok((count.activities as usize, count.merged as usize))
+}
+
+/// Progress of queued uploads.
+#[server]
+pub async fn upload_progress(job_ids: Vec<i64>) -> Result<Vec<UploadOutcome>, ServerFnError> {
+    let pool = pool()?;
+    let auth = auth()?;
+    let user = auth
+        .current_user
+        .ok_or(ServerFnError::new("Not logged in".to_string()))?;
+    jobs::job_outcomes(&job_ids, user.id, &pool)
+        .await
+        .map_err(|e| ServerFnError::new(format!("{:#}", e)))
+}
+
+/// How to handle an uploaded activity that is already stored.
+#[derive(Debug, Clone, Copy, PartialEq, Default, EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum DuplicateStrategy {
+    /// Don't store the activity and report it as rejected.
+    Reject,
+    /// Don't store the activity.
+    #[default]
+    Skip,
+    /// Merge the records of the upload into the stored activity.
+    Merge,
+}
+
+/// What happened to an uploaded file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum UploadStatus {
+    /// The file is waiting to be processed.
+    Queued,
+    Processing,
+    Imported {
+        activity_id: i64,
+    },
+    Merged {
+        activity_id: i64,
+    },
+    Skipped {
+        duplicate_of: i64,
+    },
+    Rejected {
+        duplicate_of: i64,
+    },
+    /// The file could be read but contains no activity.
+    NoActivity {
+        error: String,
+    },
+    /// The file isn't a valid activity file.
+    ParseError {
+        error: String,
+    },
+    /// The activity couldn't be stored.
+    Failed {
+        error: String,
+    },
+}
+
+impl UploadStatus {
+    pub fn is_pending(&self) -> bool {
+        matches!(self, UploadStatus::Queued | UploadStatus::Processing)
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            UploadStatus::Rejected { .. }
+                | UploadStatus::NoActivity { .. }
+                | UploadStatus::ParseError { .. }
+                | UploadStatus::Failed { .. }
+        )
     }
-    (StatusCode::ACCEPTED, "".to_string())
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadOutcome {
+    pub file_name: String,
+    /// The ingestion job processing the file, if it could be queued.
+    pub job_id: Option<i64>,
+    #[serde(flatten)]
+    pub status: UploadStatus,
+}
+
+/// Parsing succeeded, but there is nothing to import in the file.
 #[cfg(feature = "ssr")]
-async fn process_fit_file<'a>(data: Bytes, user_id: i64, executor: PgPool) -> Result<()> {
-    use crate::models::user_preferences::get_user_preferences;
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct NoActivityFound(pub &'static str);
+
+/// The kinds of activity files we know how to import.
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileType {
+    Fit,
+    Gpx,
+    Tcx,
+}
+
+#[cfg(feature = "ssr")]
+impl FileType {
+    /// Detect the file type from the file contents.
+    ///
+    /// Fit files carry a ".FIT" signature in their header, the xml based formats are recognized
+    /// by their root element.
+    pub fn detect(data: &[u8]) -> Option<FileType> {
+        if data.len() >= 12 && &data[8..12] == b".FIT" {
+            return Some(FileType::Fit);
+        }
+        let head = String::from_utf8_lossy(&data[..cmp::min(data.len(), 1024)]);
+        if head.contains("<gpx") {
+            return Some(FileType::Gpx);
+        }
+        if head.contains("<TrainingCenterDatabase") {
+            return Some(FileType::Tcx);
+        }
+        None
+    }
+}
+
+/// An activity with all its entries, parsed from a file and ready to be stored.
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone)]
+pub struct ParsedActivity {
+    pub activity: DatabaseEntry<New, Activity>,
+    pub sessions: Vec<DatabaseEntry<New, Session>>,
+    pub laps: Vec<DatabaseEntry<New, Lap>>,
+    pub records: Vec<DatabaseEntry<New, Record>>,
+    pub events: Vec<DatabaseEntry<New, Event>>,
+    pub devices: Vec<DatabaseEntry<New, Device>>,
+}
+
+/// Summary values over a range of records, used for file formats that don't contain
+/// precomputed lap and session summaries.
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RecordSummary {
+    pub distance: Option<f64>,
+    pub average_heartrate: Option<i16>,
+    pub min_heartrate: Option<i16>,
+    pub max_heartrate: Option<i16>,
+    pub ascent: Option<i32>,
+    pub descent: Option<i32>,
+    pub average_speed: Option<f64>,
+    pub max_speed: Option<f64>,
+}
+
+#[cfg(feature = "ssr")]
+impl RecordSummary {
+    pub fn from_records(records: &[DatabaseEntry<New, Record>]) -> Self {
+        let heartrates: Vec<i16> = records.iter().filter_map(|r| r.state.heartrate).collect();
+        let average_heartrate = if heartrates.is_empty() {
+            None
+        } else {
+            Some(
+                (heartrates.iter().map(|&hr| hr as i64).sum::<i64>() / heartrates.len() as i64)
+                    as i16,
+            )
+        };
+        let distances: Vec<f64> = records.iter().filter_map(|r| r.state.distance).collect();
+        let distance = match (distances.first(), distances.last()) {
+            (Some(first), Some(last)) => Some(last - first),
+            _ => None,
+        };
+        let (ascent, descent) = records
+            .iter()
+            .filter_map(|r| r.state.altitude)
+            .tuple_windows()
+            .fold(
+                (None, None),
+                |(up, down): (Option<f64>, Option<f64>), (a, b)| {
+                    if b > a {
+                        (Some(up.unwrap_or(0.0) + b - a), down.or(Some(0.0)))
+                    } else {
+                        (up.or(Some(0.0)), Some(down.unwrap_or(0.0) + a - b))
+                    }
+                },
+            );
+        // these formats have no timer events, so only standing still is left out
+        let duration = moving_time::<_, Event>(records, &[]).unwrap_or(0.0);
+        RecordSummary {
+            distance,
+            average_heartrate,
+            min_heartrate: heartrates.iter().min().copied(),
+            max_heartrate: heartrates.iter().max().copied(),
+            ascent: ascent.map(|a| a.round() as i32),
+            descent: descent.map(|d| d.round() as i32),
+            average_speed: distance.filter(|_| duration > 0.0).map(|d| d / duration),
+            max_speed: records
+                .iter()
+                .filter_map(|r| r.state.speed)
+                .reduce(f64::max),
+        }
+    }
+}
+
+/// Great circle distance between two coordinates in meters.
+#[cfg(feature = "ssr")]
+pub(crate) fn haversine_distance(from: (f64, f64), to: (f64, f64)) -> f64 {
+    const EARTH_RADIUS: f64 = 6_371_000.0;
+    let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+    let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+    let a = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+#[cfg(feature = "ssr")]
+pub(crate) async fn process_file(
+    file_name: &str,
+    data: Bytes,
+    user_id: i64,
+    strategy: DuplicateStrategy,
+    executor: PgPool,
+) -> UploadStatus {
+    let parsed = match parse_file(&data) {
+        Ok(parsed) => parsed,
+        Err(e) if e.is::<NoActivityFound>() => {
+            return UploadStatus::NoActivity {
+                error: format!("{:#}", e),
+            }
+        }
+        Err(e) => {
+            return UploadStatus::ParseError {
+                error: format!("{:#}", e),
+            }
+        }
+    };
+    match import_activity(parsed, file_name, &data, user_id, strategy, executor).await {
+        Ok(status) => status,
+        Err(e) => UploadStatus::Failed {
+            error: format!("{:#}", e),
+        },
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub(crate) fn parse_file(data: &[u8]) -> Result<ParsedActivity> {
+    let mut parsed = match FileType::detect(data) {
+        Some(FileType::Fit) => parse_fit_file(data)?,
+        Some(FileType::Gpx) => gpx::parse_gpx_file(data)?,
+        Some(FileType::Tcx) => tcx::parse_tcx_file(data)?,
+        None => bail!("Unknown file type, only fit, gpx and tcx files are supported"),
+    };
+    parsed.activity.state.file_hash = Some(duplicates::file_hash(data));
+    Ok(parsed)
+}
+
+#[cfg(feature = "ssr")]
+async fn import_activity(
+    parsed: ParsedActivity,
+    file_name: &str,
+    data: &[u8],
+    user_id: i64,
+    strategy: DuplicateStrategy,
+    executor: PgPool,
+) -> Result<UploadStatus> {
+    let duplicate = duplicates::find_duplicate(
+        &parsed.activity.state,
+        user_id,
+        &mut *executor.acquire().await?,
+    )
+    .await?;
+    match (duplicate, strategy) {
+        (None, _) => Ok(UploadStatus::Imported {
+            activity_id: store_activity(parsed, file_name, data, user_id, executor).await?,
+        }),
+        (Some(duplicate), DuplicateStrategy::Reject) => Ok(UploadStatus::Rejected {
+            duplicate_of: duplicate.activity_id(),
+        }),
+        // merging the same file again wouldn't change anything
+        (Some(duplicate @ Duplicate::SameFile(_)), _)
+        | (Some(duplicate), DuplicateStrategy::Skip) => Ok(UploadStatus::Skipped {
+            duplicate_of: duplicate.activity_id(),
+        }),
+        (Some(Duplicate::Overlapping(activity_id)), DuplicateStrategy::Merge) => {
+            duplicates::merge_activity(parsed, activity_id, user_id, &executor).await?;
+            Ok(UploadStatus::Merged { activity_id })
+        }
+    }
+}
 
+#[cfg(feature = "ssr")]
+fn parse_fit_file(data: &[u8]) -> Result<ParsedActivity> {
     let mut records: Vec<DatabaseEntry<New, Record>> = Vec::new();
     let mut sessions: Vec<DatabaseEntry<New, Session>> = Vec::new();
     let mut laps: Vec<DatabaseEntry<New, Lap>> = Vec::new();
+    let mut events: Vec<DatabaseEntry<New, Event>> = Vec::new();
+    let mut devices: Vec<DatabaseEntry<New, Device>> = Vec::new();
     let mut activity: Option<DatabaseEntry<New, Activity>> = None;
-    for data in fitparser::from_bytes(&data).context("Failed to read fit file")? {
+    for data in fitparser::from_bytes(data).context("Failed to read fit file")? {
         match data.kind() {
             fitparser::profile::MesgNum::Record => {
                 DatabaseEntry::<New, Record>::try_from(data)
@@ -70,7 +476,10 @@ async fn process_fit_file<'a>(data: Bytes, user_id: i64, executor: PgPool) -> Re
                     .context("Couldn't parse record")?;
             }
             fitparser::profile::MesgNum::Event => {
-                leptos::logging::log!("Event: {:?}", data);
+                // some devices write events without a type, they aren't needed for the timer
+                if let Ok(event) = DatabaseEntry::<New, Event>::try_from(data) {
+                    events.push(event);
+                }
             }
             fitparser::profile::MesgNum::Session => {
                 DatabaseEntry::<New, Session>::try_from(data)
@@ -88,70 +497,306 @@ async fn process_fit_file<'a>(data: Bytes, user_id: i64, executor: PgPool) -> Re
                 }
                 activity = Some(
                     DatabaseEntry::<New, Activity>::try_from(data)
-                        .expect("no activity entry found"),
+                        .context("Couldn't parse activity")?,
                 );
             }
             fitparser::profile::MesgNum::DeviceInfo => {
-                leptos::logging::log!("Device Info: {:?}", data);
+                DatabaseEntry::<New, Device>::try_from(data)
+                    .map(|device| devices.push(device))
+                    .context("Couldn't parse device info")?;
             }
             _ => {
                 leptos::logging::log!("Unknown: {:?}", data.kind())
             }
         }
     }
-    if let Some(mut activity) = activity {
-        let hr_measurements: Vec<_> = records
-            .iter()
-            .filter_map(|r| r.state.heartrate.and_then(|hr| Some(hr as u32)))
-            .collect();
-        if hr_measurements.len() > 0 {
-            activity.state.avg_heartrate =
-                Some((hr_measurements.iter().sum::<u32>() / hr_measurements.len() as u32) as u16);
-            // calculate training load
-            let preferences =
-                get_user_preferences(user_id, activity.state.start_time, &executor).await;
-            activity.state.load = Some(preferences.calculate_load(hr_measurements));
+    match activity {
+        Some(mut activity) => {
+            // the activity message is written when the activity ends, the sessions tell when
+            // it actually started
+            let start_time = sessions.iter().map(|s| s.state.start_time).min();
+            let end_time = sessions.iter().map(|s| s.state.end_time).max();
+            if let (Some(start_time), Some(end_time)) = (start_time, end_time) {
+                activity.state.start_time = start_time;
+                activity.state.end_time = end_time;
+            }
+            Ok(ParsedActivity {
+                activity,
+                sessions,
+                laps,
+                records,
+                events,
+                devices: combine_devices(devices),
+            })
         }
+        None => Err(NoActivityFound("No activity found in fit file, may be corrupt").into()),
+    }
+}
 
-        let mut tx = executor.begin().await?;
-        let result = insert_activity(activity, user_id, &mut *tx).await;
-        if let Err(x) = result {
-            bail!("activity wasn't inserted: {}", x);
-        };
-        let activity = result.unwrap();
-        let result = insert_records(records, activity.extra.activity_id, &mut *tx).await;
-        if let Err(x) = result {
-            bail!("couldn't insert records: {}", x);
-        }
-        let result = insert_sessions(sessions, activity.extra.activity_id, &mut *tx).await;
-        if let Err(x) = result {
-            bail!("couldn't insert sessions: {}", x);
-        }
-        let result = insert_laps(laps, activity.extra.activity_id, &mut *tx).await;
-        if let Err(x) = result {
-            bail!("couldn't insert laps: {}", x);
-        }
-        let tx_result = tx.commit().await;
-        if let Err(x) = tx_result {
-            bail!("Transaction failed, try again: {}", x);
+/// Heart rate, power and pace based summary values of an activity.
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LoadSummary {
+    pub avg_heartrate: Option<u16>,
+    pub load: Option<u32>,
+    pub load_model: Option<LoadModel>,
+    pub power: Option<PowerMetrics>,
+    pub pace: Option<PaceMetrics>,
+    /// Load of each session, in the order the sessions were passed in.
+    pub session_loads: Vec<Option<u32>>,
+}
+
+/// Average heart rate, power and pace metrics and training load of an activity's records, as well
+/// as the load of each of its sessions.
+///
+/// Only records while moving count, so long stops don't lower the average or add load. Sports
+/// the user chose power or pace for get their TSS or rTSS as load, as long as there is power or
+/// speed data, the others their heart rate based load. The activity's load is the sum of its
+/// sessions' loads.
+#[cfg(feature = "ssr")]
+pub(crate) async fn load_summary<E: AsRef<Event>, S: AsRef<Session>>(
+    records: &[DatabaseEntry<New, Record>],
+    events: &[E],
+    sessions: &[S],
+    user_id: i64,
+    start_time: DateTime<Local>,
+    executor: &PgPool,
+) -> LoadSummary {
+    use crate::models::user_preferences::get_user_preferences;
+
+    let preferences = get_user_preferences(user_id, start_time, executor).await;
+    let moving_records: Vec<&Record> = records
+        .iter()
+        .zip(moving_mask(records, events))
+        .filter(|(_, moving)| *moving)
+        .map(|(r, _)| r.as_ref())
+        .collect();
+    let heartrates = |records: &[&Record]| -> Vec<u32> {
+        records
+            .iter()
+            .filter_map(|r| r.heartrate.map(|hr| hr as u32))
+            .collect()
+    };
+    let heartrate_load = |records: &[&Record]| -> Option<u32> {
+        let hr_measurements = heartrates(records);
+        (!hr_measurements.is_empty()).then(|| preferences.calculate_load(hr_measurements))
+    };
+    let power_metrics = |records: &[&Record]| -> Option<PowerMetrics> {
+        let powers: Vec<_> = records
+            .iter()
+            .filter_map(|r| r.power.map(|p| (r.timestamp, p as f64)))
+            .collect();
+        preferences
+            .ftp
+            .and_then(|ftp| PowerMetrics::from_power(&powers, ftp as u32))
+    };
+    let pace_metrics = |records: &[&Record]| -> Option<PaceMetrics> {
+        preferences.threshold_speed().and_then(|threshold_speed| {
+            PaceMetrics::from_speeds(&grade_adjusted_speeds(records), threshold_speed)
+        })
+    };
+    let metrics = |records: &[&Record], sport: Option<&str>| {
+        let power = power_metrics(records);
+        let pace = pace_metrics(records);
+        let load = match (power, pace) {
+            (Some(power), _) if preferences.uses_power(sport) => {
+                Some((power.tss, LoadModel::Power))
+            }
+            (_, Some(pace)) if preferences.uses_pace(sport) => Some((pace.rtss, LoadModel::Pace)),
+            _ => heartrate_load(records).map(|load| (load, LoadModel::Heartrate)),
         };
-    } else {
-        bail!("No activity found in fit file, may be corrupt");
+        (power, pace, load)
     };
-    Ok(())
+
+    let hr_measurements = heartrates(&moving_records);
+    let avg_heartrate = (!hr_measurements.is_empty())
+        .then(|| (hr_measurements.iter().sum::<u32>() / hr_measurements.len() as u32) as u16);
+    let sport = sessions
+        .iter()
+        .map(|s| s.as_ref())
+        .find(|s| !s.is_transition())
+        .and_then(|s| s.sport.as_deref());
+    let (power, pace, load) = metrics(&moving_records, sport);
+    let session_loads: Vec<_> = sessions
+        .iter()
+        .map(|session| {
+            let session = session.as_ref();
+            let session_records: Vec<&Record> = moving_records
+                .iter()
+                .filter(|r| session.contains(r.timestamp))
+                .copied()
+                .collect();
+            let (_, _, load) = metrics(&session_records, session.sport.as_deref());
+            load
+        })
+        .collect();
+    // each session is loaded by its own sport's model, so a multisport activity adds them up
+    let (load, load_model) = combined_load(&session_loads).or(load).unzip();
+    LoadSummary {
+        avg_heartrate,
+        load,
+        load_model,
+        power,
+        pace,
+        session_loads: session_loads
+            .into_iter()
+            .map(|load| load.map(|(load, _)| load))
+            .collect(),
+    }
+}
+
+/// Training load of an activity and the model it was calculated with.
+///
+/// Without heart rate, power or pace based load, it falls back to the session-RPE model if the
+/// activity has a perceived exertion.
+#[cfg(feature = "ssr")]
+pub(crate) async fn activity_load(
+    summary: &LoadSummary,
+    start_time: DateTime<Local>,
+    duration: f64,
+    rpe: Option<u8>,
+    user_id: i64,
+    executor: &PgPool,
+) -> Result<Option<(u32, LoadModel)>> {
+    if let (Some(load), Some(load_model)) = (summary.load, summary.load_model) {
+        return Ok(Some((load, load_model)));
+    }
+    estimate_load(start_time, duration, None, rpe, user_id, executor)
+        .await
+        .context("Couldn't estimate load")
+}
+
+/// Set the load of sessions that are about to be stored.
+#[cfg(feature = "ssr")]
+pub(crate) fn set_session_loads(
+    sessions: &mut [DatabaseEntry<New, Session>],
+    summary: &LoadSummary,
+) {
+    for (session, load) in sessions.iter_mut().zip(&summary.session_loads) {
+        session.state.load = *load;
+    }
+}
+
+#[cfg(feature = "ssr")]
+async fn store_activity(
+    parsed: ParsedActivity,
+    file_name: &str,
+    data: &[u8],
+    user_id: i64,
+    executor: PgPool,
+) -> Result<i64> {
+    let ParsedActivity {
+        mut activity,
+        mut sessions,
+        laps,
+        records,
+        events,
+        devices,
+    } = parsed;
+    let summary = load_summary(
+        &records,
+        &events,
+        &sessions,
+        user_id,
+        activity.state.start_time,
+        &executor,
+    )
+    .await;
+    set_session_loads(&mut sessions, &summary);
+    let (load, load_model) = activity_load(
+        &summary,
+        activity.state.start_time,
+        activity.state.duration,
+        activity.state.rpe,
+        user_id,
+        &executor,
+    )
+    .await?
+    .unzip();
+    activity.state.avg_heartrate = summary.avg_heartrate;
+    activity.state.load = load;
+    activity.state.load_model = load_model;
+    activity.state.power = summary.power;
+    activity.state.pace = summary.pace;
+    activity.state.moving_time = moving_time(&records, &events);
+
+    let mut tx = executor.begin().await?;
+    let activity = insert_activity(activity, user_id, &mut *tx)
+        .await
+        .context("activity wasn't inserted")?;
+    insert_records(records, activity.extra.activity_id, &mut *tx)
+        .await
+        .context("couldn't insert records")?;
+    insert_sessions(sessions, activity.extra.activity_id, &mut *tx)
+        .await
+        .context("couldn't insert sessions")?;
+    insert_laps(laps, activity.extra.activity_id, &mut *tx)
+        .await
+        .context("couldn't insert laps")?;
+    insert_events(events, activity.extra.activity_id, &mut *tx)
+        .await
+        .context("couldn't insert events")?;
+    insert_devices(devices, activity.extra.activity_id, &mut *tx)
+        .await
+        .context("couldn't insert devices")?;
+    update_aggregates(activity.extra.activity_id, user_id, &mut tx)
+        .await
+        .context("couldn't update aggregates")?;
+    let path = originals::save_original(
+        activity.extra.activity_id,
+        user_id,
+        file_name,
+        data,
+        &mut tx,
+    )
+    .await?;
+    if let Err(e) = tx.commit().await {
+        if let Some(path) = path {
+            originals::discard_original(&path).await;
+        }
+        return Err(e).context("Transaction failed, try again");
+    }
+    Ok(activity.extra.activity_id)
 }
 
 #[component]
 pub fn FitUploadForm(show: ReadSignal<bool>, show_set: WriteSignal<bool>) -> impl IntoView {
     let uploaded = use_context::<FitFileUploaded>().unwrap();
+    let uploading = create_rw_signal(false);
+    let report = create_rw_signal(Option::<Result<Vec<UploadOutcome>, String>>::None);
+    let poll_timer = store_value(Option::<TimeoutHandle>::None);
+    on_cleanup(move || {
+        if let Some(timer) = poll_timer.try_get_value().flatten() {
+            timer.clear();
+        }
+    });
     let on_submit = move |_ev: SubmitEvent| {
-        set_timeout(
-            move || uploaded.0.update(|v| *v += 1),
-            Duration::from_secs(2),
-        );
+        uploading.set(true);
+        report.set(None);
+    };
+    let on_response: Rc<dyn Fn(&web_sys::Response)> = Rc::new(move |response| {
+        let status = response.status();
+        let body = response.text();
+        spawn_local(async move {
+            let text = match body {
+                Ok(body) => JsFuture::from(body).await.ok().and_then(|t| t.as_string()),
+                Err(_) => None,
+            };
+            let result = match (status, text) {
+                (200 | 202, Some(text)) => serde_json::from_str::<Vec<UploadOutcome>>(&text)
+                    .map_err(|e| format!("Couldn't read upload report: {}", e)),
+                (_, Some(text)) if !text.is_empty() => Err(text),
+                _ => Err(format!("Upload failed with status {}", status)),
+            };
+            report.set(Some(result));
+            uploading.set(false);
+            poll_progress(report, uploaded, poll_timer);
+        });
+    });
+    let close = move |_| {
         show_set(false);
+        report.set(None);
     };
-    let close = move |_| show_set(false);
     leptos::view! {
         <Show when=move || { show() } fallback=|| { }>
             <Form
@@ -159,6 +804,7 @@ pub fn FitUploadForm(show: ReadSignal<bool>, show_set: WriteSignal<bool>) -> imp
                 method="POST"
                 enctype="multipart/form-data".to_string()
                 on:submit=on_submit
+                on_response=on_response.clone()
             >
                 <div
                     class="modal is-active"
@@ -166,10 +812,22 @@ pub fn FitUploadForm(show: ReadSignal<bool>, show_set: WriteSignal<bool>) -> imp
                 <div class="modal-background" on:click=close></div>
                 <div class="modal-card">
                     <div class="modal-card-head">
-                            <p class="modal-card-title">"Upload Fit File"</p>
-                            <button class="delete" aria-label="close" on:click=close></button>
+                            <p class="modal-card-title">"Upload Activity Files"</p>
+                            <button type="button" class="delete" aria-label="close" on:click=close></button>
                     </div>
                         <div class="modal-card-body">
+                            <div class="field">
+                                <label class="label">Already uploaded activities</label>
+                                <div class="control">
+                                    <div class="select">
+                                        <select name="duplicates">
+                                            <option value="skip" selected>Skip</option>
+                                            <option value="merge">Merge</option>
+                                            <option value="reject">Reject</option>
+                                        </select>
+                                    </div>
+                                </div>
+                            </div>
                             <div class="file">
                                 <label class="file-label">
                                     <input class="file-input" type="file" name="fit_file" multiple />
@@ -178,18 +836,23 @@ pub fn FitUploadForm(show: ReadSignal<bool>, show_set: WriteSignal<bool>) -> imp
                                             <i class="fas fa-upload"></i>
                                         </span>
                                         <span class="file-label">
-                                            Choose Fit Files...
+                                            Choose Fit, Gpx or Tcx Files...
                                         </span>
                                     </span>
                                 </label>
                             </div>
+                            <UploadReport report=report/>
                         </div>
                         <div class="modal-card-foot">
-                            <button type="submit" class="button is-success">
+                            <button
+                                type="submit"
+                                class="button is-success"
+                                class:is-loading=uploading
+                            >
                                 <i class="material-symbols-rounded right">upload</i>
                                 Upload
                             </button>
-                            <button class="button" on:click=close>Cancel</button>
+                            <button type="button" class="button" on:click=close>Close</button>
                         </div>
                     </div>
                 </div>
@@ -197,3 +860,140 @@ pub fn FitUploadForm(show: ReadSignal<bool>, show_set: WriteSignal<bool>) -> imp
         </Show>
     }
 }
+
+/// Refresh the report until all queued files are processed.
+///
+/// Stops with the error if the progress can't be loaded, and once the form is gone.
+fn poll_progress(
+    report: RwSignal<Option<Result<Vec<UploadOutcome>, String>>>,
+    uploaded: FitFileUploaded,
+    timer: StoredValue<Option<TimeoutHandle>>,
+) {
+    let Some(job_ids) = report.try_with_untracked(|report| match report {
+        Some(Ok(outcomes)) => outcomes
+            .iter()
+            .filter(|o| o.status.is_pending())
+            .filter_map(|o| o.job_id)
+            .collect::<Vec<i64>>(),
+        _ => Vec::new(),
+    }) else {
+        return;
+    };
+    if job_ids.is_empty() {
+        uploaded.0.update(|v| *v += 1);
+        return;
+    }
+    let handle = set_timeout_with_handle(
+        move || {
+            spawn_local(async move {
+                match upload_progress(job_ids).await {
+                    Ok(progress) => {
+                        let updated = report.try_update(|report| {
+                            if let Some(Ok(outcomes)) = report {
+                                for outcome in outcomes.iter_mut() {
+                                    if let Some(update) =
+                                        progress.iter().find(|p| p.job_id == outcome.job_id)
+                                    {
+                                        *outcome = update.clone();
+                                    }
+                                }
+                            }
+                        });
+                        if updated.is_some() {
+                            poll_progress(report, uploaded, timer);
+                        }
+                    }
+                    Err(e) => {
+                        report.try_set(Some(Err(format!(
+                            "Couldn't load the upload progress: {}",
+                            e
+                        ))));
+                        uploaded.0.update(|v| *v += 1);
+                    }
+                }
+            })
+        },
+        Duration::from_secs(1),
+    );
+    timer.try_set_value(handle.ok());
+}
+
+#[component]
+fn UploadReport(report: RwSignal<Option<Result<Vec<UploadOutcome>, String>>>) -> impl IntoView {
+    move || match report() {
+        None => ().into_view(),
+        Some(Err(error)) => view! {
+            <div class="notification is-danger mt-4">{error}</div>
+        }
+        .into_view(),
+        Some(Ok(outcomes)) => {
+            let failed = outcomes.iter().filter(|o| o.status.is_error()).count();
+            let pending = outcomes.iter().filter(|o| o.status.is_pending()).count();
+            let total = outcomes.len();
+            let finished = total - pending;
+            view! {
+                <Show when=move || { pending > 0 } fallback=|| {}>
+                    <progress
+                        class="progress is-primary mt-4"
+                        value=finished
+                        max=total
+                    ></progress>
+                </Show>
+                <p class="mt-4">
+                    {format!("{} of {} files processed without errors", finished - failed, total)}
+                </p>
+                <table class="table is-fullwidth is-narrow">
+                    <thead>
+                        <tr>
+                            <th>File</th>
+                            <th>Status</th>
+                            <th>Details</th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        {outcomes
+                            .into_iter()
+                            .map(|outcome| {
+                                let (tag, label, details) = match outcome.status {
+                                    UploadStatus::Queued => ("is-light", "Queued", String::new()),
+                                    UploadStatus::Processing => {
+                                        ("is-info is-light", "Processing", String::new())
+                                    }
+                                    UploadStatus::Imported { activity_id } => {
+                                        ("is-success", "Imported", format!("Activity {}", activity_id))
+                                    }
+                                    UploadStatus::Merged { activity_id } => {
+                                        ("is-info", "Merged", format!("Into activity {}", activity_id))
+                                    }
+                                    UploadStatus::Skipped { duplicate_of } => {
+                                        ("is-light", "Duplicate", format!("Same as activity {}", duplicate_of))
+                                    }
+                                    UploadStatus::Rejected { duplicate_of } => {
+                                        ("is-warning", "Rejected", format!("Same as activity {}", duplicate_of))
+                                    }
+                                    UploadStatus::NoActivity { error } => {
+                                        ("is-warning", "No activity", error)
+                                    }
+                                    UploadStatus::ParseError { error } => {
+                                        ("is-danger", "Parse error", error)
+                                    }
+                                    UploadStatus::Failed { error } => ("is-danger", "Failed", error),
+                                };
+                                view! {
+                                    <tr>
+                                        <td>{outcome.file_name}</td>
+                                        <td>
+                                            <span class=format!("tag {}", tag)>{label}</span>
+                                        </td>
+                                        <td>{details}</td>
+                                    </tr>
+                                }
+                            })
+                            .collect_view()}
+                    </tbody>
+                </table>
+            }
+            .into_view()
+        }
+    }
+}
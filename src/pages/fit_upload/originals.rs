@@ -0,0 +1,140 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::config::Config;
+
+/// Keep the original file of an activity, so it can be parsed again later.
+///
+/// Files are written to the configured file store if there is one, otherwise they are kept in
+/// the database. Returns the path of a file written to the store, which has to be removed with
+/// [`discard_original`] if the transaction fails.
+pub async fn save_original(
+    activity_id: i64,
+    user_id: i64,
+    file_name: &str,
+    data: &[u8],
+    tx: &mut Transaction<'_, Postgres>,
+) -> Result<Option<String>> {
+    let path = match &Config::global().file_store {
+        Some(store) => Some(
+            write_to_store(store, activity_id, user_id, file_name, data)
+                .await?
+                .to_string_lossy()
+                .to_string(),
+        ),
+        None => None,
+    };
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO activity_files (activity_id, file_name, data, path)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        activity_id as i32,
+        file_name,
+        if path.is_none() { Some(data) } else { None },
+        path.as_deref()
+    )
+    .execute(&mut **tx)
+    .await
+    .context("Couldn't store original file");
+    if let Err(e) = inserted {
+        if let Some(path) = &path {
+            discard_original(path).await;
+        }
+        return Err(e);
+    }
+    Ok(path)
+}
+
+/// Remove a file written to the store for a transaction that failed, nothing refers to it.
+pub async fn discard_original(path: &str) {
+    if let Err(e) = remove_original(path).await {
+        leptos::logging::warn!("Couldn't remove orphaned original file: {:#}", e);
+    }
+}
+
+async fn write_to_store(
+    store: &Path,
+    activity_id: i64,
+    user_id: i64,
+    file_name: &str,
+    data: &[u8],
+) -> Result<PathBuf> {
+    let directory = store.join(user_id.to_string());
+    tokio::fs::create_dir_all(&directory)
+        .await
+        .context("Couldn't create file store directory")?;
+    let mut path = directory.join(activity_id.to_string());
+    if let Some(extension) = Path::new(file_name).extension() {
+        path.set_extension(extension);
+    }
+    tokio::fs::write(&path, data)
+        .await
+        .with_context(|| format!("Couldn't write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Load the original file of an activity, if it was kept.
+pub async fn load_original(activity_id: i64, executor: &PgPool) -> Result<Option<Vec<u8>>> {
+    let row = sqlx::query!(
+        r#"
+        SELECT data, path
+        FROM activity_files
+        WHERE activity_id=$1
+        "#,
+        activity_id as i32
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Couldn't load original file")?;
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    match (row.data, row.path) {
+        (Some(data), _) => Ok(Some(data)),
+        (None, Some(path)) => Ok(Some(
+            tokio::fs::read(&path)
+                .await
+                .with_context(|| format!("Couldn't read {}", path))?,
+        )),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Path of the original file of an activity, if it is kept in the file store.
+///
+/// Files kept in the database are removed together with the activity, the ones in the file store
+/// have to be removed with [`remove_original`] once the activity is deleted.
+pub async fn original_path(
+    activity_id: i64,
+    user_id: i64,
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<Option<String>> {
+    let path = sqlx::query_scalar!(
+        r#"
+        SELECT f.path
+        FROM activity_files f
+        INNER JOIN activities a ON a.id=f.activity_id
+        WHERE a.id=$1 AND a.user_id=$2
+        "#,
+        activity_id,
+        user_id as _
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Couldn't look up original file")?;
+    Ok(path.flatten())
+}
+
+/// Remove an original file from the file store.
+pub async fn remove_original(path: &str) -> Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Couldn't remove {}", path))
+        }
+        _ => Ok(()),
+    }
+}
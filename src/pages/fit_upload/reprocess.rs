@@ -0,0 +1,199 @@
+use anyhow::{Context, Result};
+use sqlx::PgPool;
+
+use super::{
+    activity_load, load_summary, originals::load_original, parse_file, set_session_loads,
+    LoadSummary, ParsedActivity,
+};
+use crate::{
+    models::{
+        activity::{update_activity_summary, Activity},
+        aggregates::update_aggregates,
+        base::{DatabaseEntry, New},
+        device::insert_devices,
+        event::{insert_events, moving_time},
+        lap::insert_laps,
+        load::{estimate_load, LoadModel},
+        record::insert_records,
+        session::{insert_sessions, update_session_loads},
+    },
+    pages::activity_overview::export::load_activity_data,
+};
+
+/// Reprocess all activities of a user, returning how many succeeded and failed.
+pub async fn reprocess_all(user_id: i64, executor: &PgPool) -> Result<(usize, usize)> {
+    let activity_ids = sqlx::query_scalar!(
+        r#"
+        SELECT id
+        FROM activities
+        WHERE user_id=$1
+        ORDER BY start_time
+        "#,
+        user_id as i32
+    )
+    .fetch_all(executor)
+    .await
+    .context("Couldn't load activities")?;
+    let mut reprocessed = 0;
+    let mut failed = 0;
+    for activity_id in activity_ids {
+        match reprocess_activity(activity_id, user_id, executor).await {
+            Ok(()) => reprocessed += 1,
+            Err(e) => {
+                leptos::logging::warn!("Couldn't reprocess activity {}: {:#}", activity_id, e);
+                failed += 1;
+            }
+        }
+    }
+    Ok((reprocessed, failed))
+}
+
+/// Parse the original file of an activity again and replace its entries.
+///
+/// Activities without an original file keep their entries, only heart rate, load and moving time
+/// are recalculated for them. The same goes for activities other files were merged into, their
+/// original file doesn't have the merged entries. Manually entered activities only get their
+/// estimated load updated.
+pub async fn reprocess_activity(activity_id: i64, user_id: i64, executor: &PgPool) -> Result<()> {
+    let stored = load_activity_data(activity_id, user_id, executor)
+        .await?
+        .context("Activity doesn't exist")?;
+    let merged = sqlx::query_scalar!("SELECT merged FROM activities WHERE id=$1", activity_id)
+        .fetch_one(executor)
+        .await
+        .context("Couldn't load activity")?;
+    let original = match load_original(activity_id, executor).await? {
+        Some(data) if !merged => Some(parse_file(&data)?),
+        _ => None,
+    };
+    let manual = original.is_none() && stored.records.is_empty();
+    let rpe = stored.activity.rpe;
+
+    let mut tx = executor.begin().await?;
+    let (start_time, end_time, duration, summary, moving_time) = match original {
+        Some(parsed) => {
+            let ParsedActivity {
+                activity,
+                mut sessions,
+                laps,
+                records,
+                events,
+                devices,
+            } = parsed;
+            let summary = load_summary(
+                &records,
+                &events,
+                &sessions,
+                user_id,
+                activity.state.start_time,
+                executor,
+            )
+            .await;
+            set_session_loads(&mut sessions, &summary);
+            let moving_time = moving_time(&records, &events);
+            sqlx::query!(
+                r#"
+                WITH records AS (DELETE FROM records WHERE activity_id=$1),
+                    sessions AS (DELETE FROM sessions WHERE activity_id=$1),
+                    laps AS (DELETE FROM laps WHERE activity_id=$1),
+                    events AS (DELETE FROM events WHERE activity_id=$1)
+                DELETE FROM devices WHERE activity_id=$1
+                "#,
+                activity_id as i32
+            )
+            .execute(&mut *tx)
+            .await
+            .context("Couldn't remove old entries")?;
+            insert_records(records, activity_id, &mut *tx).await?;
+            insert_sessions(sessions, activity_id, &mut *tx).await?;
+            insert_laps(laps, activity_id, &mut *tx).await?;
+            insert_events(events, activity_id, &mut *tx).await?;
+            insert_devices(devices, activity_id, &mut *tx).await?;
+            (
+                activity.state.start_time,
+                activity.state.end_time,
+                activity.state.duration,
+                summary,
+                moving_time,
+            )
+        }
+        None if manual => {
+            // manually entered, keep the entered average heart rate
+            (
+                stored.activity.start_time,
+                stored.activity.end_time,
+                stored.activity.duration,
+                LoadSummary {
+                    avg_heartrate: stored.activity.avg_heartrate,
+                    ..Default::default()
+                },
+                stored.activity.moving_time,
+            )
+        }
+        None => {
+            let records: Vec<_> = stored
+                .records
+                .into_iter()
+                .map(|r| DatabaseEntry {
+                    state: Box::new(r),
+                    extra: New,
+                })
+                .collect();
+            let summary = load_summary(
+                &records,
+                &stored.events,
+                &stored.sessions,
+                user_id,
+                stored.activity.start_time,
+                executor,
+            )
+            .await;
+            let mut sessions = stored.sessions;
+            for (session, load) in sessions.iter_mut().zip(&summary.session_loads) {
+                session.load = *load;
+            }
+            update_session_loads(&sessions, activity_id, &mut *tx).await?;
+            (
+                stored.activity.start_time,
+                stored.activity.end_time,
+                stored.activity.duration,
+                summary,
+                moving_time(&records, &stored.events),
+            )
+        }
+    };
+    let load = if manual {
+        estimate_load(
+            start_time,
+            duration,
+            summary.avg_heartrate,
+            rpe,
+            user_id,
+            executor,
+        )
+        .await?
+    } else {
+        activity_load(&summary, start_time, duration, rpe, user_id, executor).await?
+    };
+    let (load, load_model): (Option<u32>, Option<LoadModel>) = load.unzip();
+    let activity = Activity {
+        start_time,
+        end_time,
+        duration,
+        moving_time,
+        load,
+        load_model,
+        avg_heartrate: summary.avg_heartrate,
+        power: summary.power,
+        pace: summary.pace,
+        ..stored.activity
+    };
+    update_activity_summary(activity_id, &activity, &mut *tx)
+        .await
+        .context("Couldn't update activity")?;
+    update_aggregates(activity_id, user_id, &mut tx)
+        .await
+        .context("Couldn't update aggregates")?;
+    tx.commit().await.context("Transaction failed, try again")?;
+    Ok(())
+}
@@ -0,0 +1,292 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Local};
+use roxmltree::{Document, Node};
+
+use super::{
+    xml::{child, child_text, descendant_text, LocalName},
+    NoActivityFound, ParsedActivity, RecordSummary,
+};
+use crate::models::{
+    activity::Activity,
+    base::{DatabaseEntry, New},
+    lap::Lap,
+    record::Record,
+    session::Session,
+};
+
+/// Parse a tcx (Training Center XML) file into an activity.
+///
+/// Tcx files contain explicit laps with their own summaries, which are used as is. Values missing
+/// from the lap summary are calculated from the lap's trackpoints.
+pub fn parse_tcx_file(data: &[u8]) -> Result<ParsedActivity> {
+    let text = std::str::from_utf8(data).context("tcx file is not valid utf-8")?;
+    let document = Document::parse(text).context("Failed to read tcx file")?;
+    let tcx_activity = document
+        .root_element()
+        .descendants()
+        .find(|n| n.has_tag_name_local("Activity"))
+        .ok_or(NoActivityFound("No activity found in tcx file"))?;
+    let sport = normalize_sport(tcx_activity.attribute("Sport").unwrap_or("Other"));
+
+    let mut records: Vec<DatabaseEntry<New, Record>> = Vec::new();
+    let mut laps: Vec<DatabaseEntry<New, Lap>> = Vec::new();
+    let mut timer_time = 0.0;
+    for tcx_lap in tcx_activity
+        .children()
+        .filter(|n| n.has_tag_name_local("Lap"))
+    {
+        let first_record = records.len();
+        for point in tcx_lap
+            .descendants()
+            .filter(|n| n.has_tag_name_local("Trackpoint"))
+        {
+            records.push(parse_trackpoint(point)?);
+        }
+        let (lap, lap_time) = parse_lap(tcx_lap, &records[first_record..], &sport)?;
+        timer_time += lap_time;
+        laps.push(lap);
+    }
+    if laps.is_empty() {
+        return Err(NoActivityFound("No laps found in tcx file").into());
+    }
+
+    let start_time = laps.first().unwrap().state.start_time;
+    let end_time = laps.last().unwrap().state.end_time;
+    let summary = RecordSummary::from_records(&records);
+    let session = DatabaseEntry {
+        state: Box::new(Session {
+            start_time,
+            end_time,
+            sport: Some(sport),
+            distance: laps
+                .iter()
+                .filter_map(|l| l.state.distance)
+                .reduce(|acc, d| acc + d)
+                .or(summary.distance),
+            calories: laps
+                .iter()
+                .filter_map(|l| l.state.calories)
+                .reduce(|acc, c| acc + c),
+            average_heartrate: summary.average_heartrate,
+            min_heartrate: summary.min_heartrate,
+            max_heartrate: laps
+                .iter()
+                .filter_map(|l| l.state.max_heartrate)
+                .max()
+                .or(summary.max_heartrate),
+            average_power: None,
+            ascent: summary.ascent,
+            descent: summary.descent,
+            average_speed: summary.average_speed,
+            max_speed: laps
+                .iter()
+                .filter_map(|l| l.state.max_speed)
+                .reduce(f64::max)
+                .or(summary.max_speed),
+            load: None,
+        }),
+        extra: New,
+    };
+    let activity = DatabaseEntry {
+        state: Box::new(Activity {
+            user_id: None,
+            start_time,
+            end_time,
+            duration: timer_time,
+            moving_time: None,
+            load: None,
+            avg_heartrate: None,
+            file_hash: None,
+            rpe: None,
+            load_model: None,
+            power: None,
+            pace: None,
+        }),
+        extra: New,
+    };
+    Ok(ParsedActivity {
+        activity,
+        sessions: vec![session],
+        laps,
+        records,
+        events: Vec::new(),
+        devices: Vec::new(),
+    })
+}
+
+fn parse_lap(
+    lap: Node,
+    records: &[DatabaseEntry<New, Record>],
+    sport: &str,
+) -> Result<(DatabaseEntry<New, Lap>, f64)> {
+    let start_time = lap
+        .attribute("StartTime")
+        .context("lap without start time")?;
+    let start_time = parse_time(start_time)?;
+    let total_time = child_text(lap, "TotalTimeSeconds")
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .unwrap_or(0.0);
+    let end_time = records
+        .last()
+        .map(|r| r.state.timestamp)
+        .filter(|&t| t > start_time)
+        .unwrap_or_else(|| {
+            start_time + Duration::try_milliseconds((total_time * 1000.0) as i64).unwrap()
+        });
+    let summary = RecordSummary::from_records(records);
+    let distance = child_text(lap, "DistanceMeters").and_then(|v| v.trim().parse::<f64>().ok());
+    Ok((
+        DatabaseEntry {
+            state: Box::new(Lap {
+                start_time,
+                end_time,
+                sport: Some(sport.to_string()),
+                distance: distance.or(summary.distance),
+                calories: child_text(lap, "Calories").and_then(|v| v.trim().parse::<i32>().ok()),
+                average_heartrate: bpm(lap, "AverageHeartRateBpm").or(summary.average_heartrate),
+                min_heartrate: summary.min_heartrate,
+                max_heartrate: bpm(lap, "MaximumHeartRateBpm").or(summary.max_heartrate),
+                average_power: child(lap, "Extensions")
+                    .and_then(|e| descendant_text(e, &["AvgWatts"]))
+                    .and_then(|v| v.trim().parse::<f64>().ok())
+                    .map(|v| v.round() as i32),
+                ascent: summary.ascent,
+                descent: summary.descent,
+                average_speed: distance
+                    .filter(|_| total_time > 0.0)
+                    .map(|d| d / total_time)
+                    .or(summary.average_speed),
+                max_speed: child_text(lap, "MaximumSpeed")
+                    .and_then(|v| v.trim().parse::<f64>().ok())
+                    .or(summary.max_speed),
+            }),
+            extra: New,
+        },
+        total_time,
+    ))
+}
+
+fn parse_trackpoint(point: Node) -> Result<DatabaseEntry<New, Record>> {
+    let timestamp = child_text(point, "Time").context("trackpoint without time")?;
+    let timestamp = parse_time(timestamp)?;
+    let position = child(point, "Position");
+    let latitude = position
+        .and_then(|p| child_text(p, "LatitudeDegrees"))
+        .and_then(|v| v.trim().parse::<f64>().ok());
+    let longitude = position
+        .and_then(|p| child_text(p, "LongitudeDegrees"))
+        .and_then(|v| v.trim().parse::<f64>().ok());
+    let extensions = child(point, "Extensions");
+    Ok(DatabaseEntry {
+        state: Box::new(Record {
+            timestamp,
+            heartrate: bpm(point, "HeartRateBpm"),
+            latitude,
+            longitude,
+            distance: child_text(point, "DistanceMeters")
+                .and_then(|v| v.trim().parse::<f64>().ok()),
+            speed: extensions
+                .and_then(|e| descendant_text(e, &["Speed"]))
+                .and_then(|v| v.trim().parse::<f64>().ok()),
+            altitude: child_text(point, "AltitudeMeters")
+                .and_then(|v| v.trim().parse::<f64>().ok()),
+            cadence: child_text(point, "Cadence")
+                .or_else(|| extensions.and_then(|e| descendant_text(e, &["RunCadence"])))
+                .and_then(|v| v.trim().parse::<f64>().ok()),
+            power: extensions
+                .and_then(|e| descendant_text(e, &["Watts"]))
+                .and_then(|v| v.trim().parse::<f64>().ok())
+                .map(|v| v.round() as i16),
+            step_length: None,
+            vertical_oscillation: None,
+            ground_contact_time: None,
+            temperature: None,
+        }),
+        extra: New,
+    })
+}
+
+/// Heart rate values are wrapped in a `<Value>` element in tcx.
+fn bpm(node: Node, name: &str) -> Option<i16> {
+    child(node, name)
+        .and_then(|hr| child_text(hr, "Value"))
+        .and_then(|v| v.trim().parse::<i16>().ok())
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Local>> {
+    Ok(DateTime::parse_from_rfc3339(value.trim())
+        .context("time is not a date")?
+        .with_timezone(&Local))
+}
+
+/// Map the tcx sport attribute to the sport names used in fit files.
+fn normalize_sport(value: &str) -> String {
+    match value {
+        "Running" => "running",
+        "Biking" => "cycling",
+        _ => "generic",
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_tcx_file;
+
+    const TCX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<TrainingCenterDatabase xmlns="http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2" xmlns:ns3="http://www.garmin.com/xmlschemas/ActivityExtension/v2">
+ <Activities>
+  <Activity Sport="Biking">
+   <Id>2023-05-01T06:00:00Z</Id>
+   <Lap StartTime="2023-05-01T06:00:00Z">
+    <TotalTimeSeconds>60.0</TotalTimeSeconds>
+    <DistanceMeters>500.0</DistanceMeters>
+    <MaximumSpeed>9.5</MaximumSpeed>
+    <Calories>12</Calories>
+    <AverageHeartRateBpm><Value>130</Value></AverageHeartRateBpm>
+    <MaximumHeartRateBpm><Value>135</Value></MaximumHeartRateBpm>
+    <Track>
+     <Trackpoint>
+      <Time>2023-05-01T06:00:00Z</Time>
+      <Position><LatitudeDegrees>47.3769</LatitudeDegrees><LongitudeDegrees>8.5417</LongitudeDegrees></Position>
+      <AltitudeMeters>408.0</AltitudeMeters>
+      <DistanceMeters>0.0</DistanceMeters>
+      <HeartRateBpm><Value>125</Value></HeartRateBpm>
+      <Cadence>85</Cadence>
+     </Trackpoint>
+     <Trackpoint>
+      <Time>2023-05-01T06:01:00Z</Time>
+      <DistanceMeters>500.0</DistanceMeters>
+      <HeartRateBpm><Value>135</Value></HeartRateBpm>
+      <Extensions><ns3:TPX><ns3:Speed>8.2</ns3:Speed></ns3:TPX></Extensions>
+     </Trackpoint>
+    </Track>
+   </Lap>
+   <Lap StartTime="2023-05-01T06:01:00Z">
+    <TotalTimeSeconds>30.0</TotalTimeSeconds>
+    <DistanceMeters>250.0</DistanceMeters>
+    <Calories>6</Calories>
+   </Lap>
+  </Activity>
+ </Activities>
+</TrainingCenterDatabase>"#;
+
+    #[test]
+    fn test_parse_tcx() {
+        let parsed = parse_tcx_file(TCX.as_bytes()).unwrap();
+        assert_eq!(parsed.laps.len(), 2);
+        assert_eq!(parsed.records.len(), 2);
+        assert_eq!(parsed.activity.state.duration, 90.0);
+        let session = &parsed.sessions[0].state;
+        assert_eq!(session.sport.as_deref(), Some("cycling"));
+        assert_eq!(session.distance, Some(750.0));
+        assert_eq!(session.calories, Some(18));
+        let lap = &parsed.laps[0].state;
+        assert_eq!(lap.average_heartrate, Some(130));
+        assert_eq!(lap.max_speed, Some(9.5));
+        let lap = &parsed.laps[1].state;
+        assert_eq!((lap.end_time - lap.start_time).num_seconds(), 30);
+        assert_eq!(parsed.records[1].state.speed, Some(8.2));
+        assert_eq!(parsed.records[0].state.cadence, Some(85.0));
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use chrono::Local;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use super::{process_file, DuplicateStrategy, UploadStatus};
+
+const DONE_DIRECTORY: &str = "done";
+const FAILED_DIRECTORY: &str = "failed";
+const EXTENSIONS: [&str; 3] = ["fit", "gpx", "tcx"];
+
+/// A directory whose activity files get imported for a user automatically.
+#[derive(Deserialize, Debug, Clone)]
+pub struct WatchDirectory {
+    pub username: String,
+    pub path: PathBuf,
+}
+
+/// Start polling the configured directories for new activity files.
+///
+/// Files are only imported once their size didn't change between two polls, so files that are
+/// still being copied aren't picked up. Imported files are moved to `done/`, files that couldn't
+/// be imported to `failed/`.
+pub async fn start_watchers(
+    pool: PgPool,
+    directories: &[WatchDirectory],
+    interval: Duration,
+) -> Result<()> {
+    for directory in directories {
+        let user_id =
+            sqlx::query_scalar!("SELECT id FROM users WHERE username=$1", directory.username)
+                .fetch_optional(&pool)
+                .await?
+                .with_context(|| {
+                    format!("Unknown user {} for watch directory", directory.username)
+                })?;
+        for subdirectory in [DONE_DIRECTORY, FAILED_DIRECTORY] {
+            tokio::fs::create_dir_all(directory.path.join(subdirectory))
+                .await
+                .with_context(|| format!("Couldn't set up {}", directory.path.display()))?;
+        }
+        let path = directory.path.clone();
+        let pool = pool.clone();
+        tokio::spawn(async move {
+            let mut sizes = HashMap::new();
+            loop {
+                if let Err(e) = scan_directory(&path, user_id, &mut sizes, &pool).await {
+                    leptos::logging::error!("Couldn't import from {}: {:#}", path.display(), e);
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn scan_directory(
+    path: &Path,
+    user_id: i64,
+    sizes: &mut HashMap<PathBuf, u64>,
+    pool: &PgPool,
+) -> Result<()> {
+    let mut seen = HashMap::new();
+    let mut entries = tokio::fs::read_dir(path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file = entry.path();
+        let metadata = entry.metadata().await?;
+        let supported = file
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| EXTENSIONS.contains(&e.to_lowercase().as_str()));
+        if !metadata.is_file() || !supported {
+            continue;
+        }
+        let size = metadata.len();
+        seen.insert(file.clone(), size);
+        if sizes.get(&file) != Some(&size) {
+            // still being written, or new since the last scan
+            continue;
+        }
+        let data = tokio::fs::read(&file).await?;
+        let file_name = file
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let status = process_file(
+            &file_name,
+            Bytes::from(data),
+            user_id,
+            DuplicateStrategy::Skip,
+            pool.clone(),
+        )
+        .await;
+        let target = match status {
+            UploadStatus::Failed { error } => {
+                // not a problem with the file, try again on the next scan
+                leptos::logging::warn!("Couldn't import {}: {}", file.display(), error);
+                continue;
+            }
+            status if status.is_error() => {
+                leptos::logging::warn!("Couldn't import {}: {:?}", file.display(), status);
+                FAILED_DIRECTORY
+            }
+            _ => DONE_DIRECTORY,
+        };
+        move_file(&file, &path.join(target)).await?;
+        seen.remove(&file);
+    }
+    *sizes = seen;
+    Ok(())
+}
+
+/// Move a file into a directory, without overwriting files of the same name.
+async fn move_file(file: &Path, directory: &Path) -> Result<()> {
+    let name = file.file_name().context("file without a name")?;
+    let mut target = directory.join(name);
+    if tokio::fs::try_exists(&target).await? {
+        target = directory.join(format!(
+            "{}_{}",
+            Local::now().format("%Y%m%d%H%M%S"),
+            name.to_string_lossy()
+        ));
+    }
+    tokio::fs::rename(file, &target)
+        .await
+        .with_context(|| format!("Couldn't move {}", file.display()))
+}
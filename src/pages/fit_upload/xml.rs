@@ -0,0 +1,26 @@
+use roxmltree::Node;
+
+pub fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name_local(name))
+}
+
+pub fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).and_then(|n| n.text())
+}
+
+pub fn descendant_text<'a>(node: Node<'a, '_>, names: &[&str]) -> Option<&'a str> {
+    node.descendants()
+        .find(|n| n.is_element() && names.contains(&n.tag_name().name()))
+        .and_then(|n| n.text())
+}
+
+pub trait LocalName {
+    fn has_tag_name_local(&self, name: &str) -> bool;
+}
+
+impl LocalName for Node<'_, '_> {
+    /// Compare only the local part of the tag name, as files come with varying namespaces.
+    fn has_tag_name_local(&self, name: &str) -> bool {
+        self.is_element() && self.tag_name().name() == name
+    }
+}
@@ -4,5 +4,6 @@ pub mod fit_upload;
 pub mod home;
 pub mod landing;
 pub mod overview;
+pub mod records;
 pub mod user;
 pub mod workout_schedule;
@@ -1,79 +1,138 @@
 #[cfg(feature = "ssr")]
-use crate::app::{auth, pool};
+use crate::{
+    app::{auth, pool},
+    authentication::AuthSession,
+    models::{
+        fitness::{fitness, get_daily_fitness},
+        user_preferences::get_user_preferences,
+    },
+    pages::workout_schedule::planned_daily_loads,
+    state::AppState,
+};
+#[cfg(feature = "ssr")]
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
 use charming::{
     component::{Axis, Grid},
-    element::{AxisType, Tooltip, Trigger},
+    element::{AxisType, ItemStyle, LineStyle, LineStyleType, Tooltip, Trigger},
     series::Line,
     Chart, WasmRenderer,
 };
-use chrono::{DateTime, Days, Duration, Local};
+#[cfg(feature = "ssr")]
+use chrono::Duration;
+use chrono::{DateTime, Days, Local, NaiveDate};
 use itertools::MultiUnzip;
 use leptos::{html::Div, *};
 use leptos_use::{use_element_size, UseElementSizeReturn};
-use serde::{Deserialize, Serialize};
-use std::{cmp, f64::consts};
+use serde::Deserialize;
+use std::cmp;
 
-use crate::{app::FitFileUploaded, error_template::ErrorTemplate};
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-pub struct TrainingLoad {
-    pub load: i64,
-    pub date: DateTime<Local>,
-}
+use crate::{app::FitFileUploaded, error_template::ErrorTemplate, models::fitness::DailyFitness};
 
-#[cfg(feature = "ssr")]
-pub async fn daily_training_load(
-    user_id: i64,
-    to: DateTime<Local>,
-    executor: sqlx::PgPool,
-) -> Result<Vec<TrainingLoad>, sqlx::Error> {
-    let result: Vec<TrainingLoad> = sqlx::query_as!(
-        TrainingLoad,
-        r#"
-            SELECT 
-                SUM(a.load) as "load!",
-                a.date as "date!"
-            FROM (
-                SELECT 
-                    COALESCE(activities.load, 0) as load,
-                    d.dt as date
-                FROM
-                (
-                    SELECT 
-                        dt
-                    FROM
-                        generate_series(
-                            (SELECT date_trunc('day', MIN(a.start_time)) from activities a WHERE a.user_id=$1::bigint),
-                            date_trunc('day', $2::timestamptz) + interval '1' day,
-                            '1 day') dt
-                ) d
-                
-                LEFT JOIN activities on date_trunc('day',activities.start_time) = d.dt AND activities.user_id=$1::bigint
-             ) a
-            
-            GROUP BY a.date
-            ORDER BY a.date ASC
-            "#
-,
-        &user_id,
-        &to
+/// Chronic and acute training load and form of every day from `from` up to and including `to`,
+/// the last 120 days by default.
+#[server]
+pub async fn daily_fitness_action(
+    from: Option<DateTime<Local>>,
+    to: Option<DateTime<Local>>,
+) -> Result<Vec<DailyFitness>, ServerFnError> {
+    let auth = auth()?;
+    if auth.current_user.is_none() {
+        return Err(ServerFnError::new("Not logged in".to_string()));
+    }
+    let user = auth.current_user.expect("the user to be logged in");
+    let pool = pool()?;
+    let mut conn = pool.acquire().await?;
+    let days = get_daily_fitness(
+        user.id,
+        from.unwrap_or(Local::now() - Duration::try_days(120).unwrap()),
+        to.unwrap_or(Local::now()),
+        &mut conn,
     )
-    .fetch_all(&executor)
     .await?;
-    Ok(result)
+    Ok(days)
 }
 
+/// Projected chronic and acute training load and form from tomorrow up to and including `to`,
+/// from the load of the scheduled workouts.
 #[server]
-pub async fn daily_training_load_action(
-    to: Option<DateTime<Local>>,
-) -> Result<Vec<TrainingLoad>, ServerFnError> {
+pub async fn projected_fitness_action(
+    to: DateTime<Local>,
+) -> Result<Vec<DailyFitness>, ServerFnError> {
     let auth = auth()?;
     if auth.current_user.is_none() {
         return Err(ServerFnError::new("Not logged in".to_string()));
     }
     let user = auth.current_user.expect("the user to be logged in");
     let pool = pool()?;
-    let summary = daily_training_load(user.id, to.unwrap_or(Local::now()), pool).await?;
-    Ok(summary)
+    let mut conn = pool.acquire().await?;
+    let now = Local::now();
+    let tomorrow = now.date_naive() + Duration::try_days(1).unwrap();
+    if to.date_naive() < tomorrow {
+        return Ok(Vec::new());
+    }
+    let today = get_daily_fitness(user.id, now, now, &mut conn).await?.pop();
+    let preferences = get_user_preferences(user.id, now, &mut *conn).await;
+    let loads: Vec<_> = planned_daily_loads(tomorrow, to.date_naive())
+        .await?
+        .into_iter()
+        .filter_map(|(date, load)| {
+            date.and_hms_opt(0, 0, 0)?
+                .and_local_timezone(Local)
+                .earliest()
+                .map(|day| (day, load))
+        })
+        .collect();
+    Ok(fitness(
+        today.map_or((0.0, 0.0), |today| (today.ctl, today.atl)),
+        &loads,
+        preferences.ctl_days,
+        preferences.atl_days,
+    ))
+}
+
+/// Query of the daily fitness API, dates are `%Y-%m-%d`.
+#[derive(Debug, Deserialize)]
+pub struct FitnessRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+/// Daily fitness as JSON, for use outside of the app.
+#[cfg(feature = "ssr")]
+pub async fn export_daily_fitness(
+    State(state): State<AppState>,
+    auth: AuthSession,
+    Query(range): Query<FitnessRange>,
+) -> Response {
+    let user = if let Some(user) = auth.current_user {
+        user
+    } else {
+        return (StatusCode::FORBIDDEN, "Not logged in".to_string()).into_response();
+    };
+    let day = |date: NaiveDate| {
+        date.and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_local_timezone(Local)
+            .earliest()
+    };
+    let from = range
+        .from
+        .and_then(day)
+        .unwrap_or(Local::now() - Duration::try_days(120).unwrap());
+    let to = range.to.and_then(day).unwrap_or(Local::now());
+    let mut conn = match state.pool.acquire().await {
+        Ok(conn) => conn,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("{}", e)).into_response(),
+    };
+    match get_daily_fitness(user.id, from, to, &mut conn).await {
+        Ok(days) => (StatusCode::OK, Json(days)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{}", e)).into_response(),
+    }
 }
 
 #[component]
@@ -82,69 +141,87 @@ pub fn FitnessLevelChart(
     #[prop(into)] to: Memo<Option<DateTime<Local>>>,
 ) -> impl IntoView {
     let uploaded = use_context::<FitFileUploaded>().unwrap();
+    let chart_end = move || {
+        to.get()
+            .unwrap_or(Local::now())
+            .checked_add_days(Days::new(2))
+    };
+    // past days come from the activities, days after today from the scheduled workouts
     let training_load = create_resource(
-        move || (to.get(), uploaded.0()),
-        move |(to, _)| daily_training_load_action(to),
+        move || (from.get(), chart_end(), uploaded.0()),
+        move |(from, to, _)| daily_fitness_action(from, to.map(|to| to.min(Local::now()))),
+    );
+    let projected_load = create_resource(
+        move || (chart_end(), uploaded.0()),
+        move |(to, _)| async move {
+            match to {
+                Some(to) => projected_fitness_action(to).await,
+                None => Ok(Vec::new()),
+            }
+        },
     );
     let fitness_chart = create_node_ref::<Div>();
     let UseElementSizeReturn { width, height: _ } = use_element_size(fitness_chart);
     let _chart = create_local_resource(
-        move || (from.get(), to.get(), training_load.get(), width()),
-        move |(from, to, training_load, width)| async move {
-            if let Some(Ok(training_load)) = training_load {
-                let from = from.unwrap_or(Local::now() - Duration::try_days(120).unwrap());
-                let to = to
-                    .unwrap_or(Local::now())
-                    .checked_add_days(Days::new(2))
-                    .unwrap();
-                let moving_average_historic = consts::E.powf(-1.0 / 42.0); //moving average for last 42 days
-                let moving_average_acute = consts::E.powf(-1.0 / 7.0); //moving average for last 7 days
-                let (date, historic_load, acute_load, intensity): (Vec<_>, Vec<_>, Vec<_>, Vec<_>) =
+        move || (training_load.get(), projected_load.get(), width()),
+        move |(training_load, projected_load, width)| async move {
+            if let (Some(Ok(training_load)), Some(Ok(projected_load))) =
+                (training_load, projected_load)
+            {
+                let (date, fitness, fatigue, form): (Vec<_>, Vec<_>, Vec<_>, Vec<_>) =
                     training_load
                         .iter()
-                        .scan((0.0, 0.0), |acc, x| {
-                            *acc = (
-                                acc.0 * moving_average_historic
-                                    + x.load as f64 * (1.0 - moving_average_historic),
-                                acc.1 * moving_average_acute
-                                    + x.load as f64 * (1.0 - moving_average_acute),
-                            );
-                            Some((x.date, acc.0, acc.1, acc.1 - acc.0))
-                        })
-                        .filter(|t| t.0 >= from && t.0 <= to)
-                        .map(|e| {
+                        .chain(projected_load.iter())
+                        .map(|day| {
                             (
-                                format!("{}", e.0.format("%Y-%m-%d")),
-                                e.1.round() as i32,
-                                e.2.round() as i32,
-                                e.3.round() as i32,
+                                format!("{}", day.day.format("%Y-%m-%d")),
+                                day.ctl.round() as i32,
+                                day.atl.round() as i32,
+                                day.form.round() as i32,
                             )
                         })
                         .multiunzip();
-
-                let chart = Chart::new()
+                // projections continue from the last past day, by index on the category axis
+                let past_days = training_load.len();
+                let start = past_days.saturating_sub(1);
+                let projected = |values: &[i32]| -> Vec<Vec<i64>> {
+                    if projected_load.is_empty() {
+                        return Vec::new();
+                    }
+                    values
+                        .iter()
+                        .enumerate()
+                        .skip(start)
+                        .map(|(i, value)| vec![i as i64, *value as i64])
+                        .collect()
+                };
+                let mut chart = Chart::new()
                     .grid(Grid::new().top(10).bottom(20))
                     .tooltip(Tooltip::new().trigger(Trigger::Axis))
                     .x_axis(Axis::new().type_(AxisType::Category).data(date))
-                    .y_axis(Axis::new().type_(AxisType::Value))
-                    .series(
-                        Line::new()
-                            .name("Fitness")
-                            .show_symbol(false)
-                            .data(historic_load),
-                    )
-                    .series(
-                        Line::new()
-                            .name("Acute Load")
-                            .show_symbol(false)
-                            .data(acute_load),
-                    )
-                    .series(
-                        Line::new()
-                            .name("Intensity")
-                            .show_symbol(false)
-                            .data(intensity),
-                    );
+                    .y_axis(Axis::new().type_(AxisType::Value));
+                for (name, values, color) in [
+                    ("Fitness", fitness, "#8aadf4"),
+                    ("Fatigue", fatigue, "#ed8796"),
+                    ("Form", form, "#a6da95"),
+                ] {
+                    chart = chart
+                        .series(
+                            Line::new()
+                                .name(name)
+                                .show_symbol(false)
+                                .item_style(ItemStyle::new().color(color))
+                                .data(values[..past_days].to_vec()),
+                        )
+                        .series(
+                            Line::new()
+                                .name(format!("Planned {}", name))
+                                .show_symbol(false)
+                                .item_style(ItemStyle::new().color(color))
+                                .line_style(LineStyle::new().type_(LineStyleType::Dashed))
+                                .data(projected(&values)),
+                        );
+                }
                 let renderer = WasmRenderer::new(cmp::max(width as u32, 300), 155);
                 let _rendered = renderer.render("fitness_chart", &chart);
             }
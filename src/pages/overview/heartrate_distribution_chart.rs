@@ -1,6 +1,14 @@
+use crate::{
+    app::FitFileUploaded,
+    error_template::ErrorTemplate,
+    models::zones::{zone_index, Zone, BELOW_ZONES_COLOR},
+    pages::user::get_preferences,
+};
 #[cfg(feature = "ssr")]
-use crate::app::{auth, pool};
-use crate::{app::FitFileUploaded, error_template::ErrorTemplate, pages::user::get_preferences};
+use crate::{
+    app::{auth, pool},
+    models::user_preferences::get_user_preferences,
+};
 use charming::{
     component::{Axis, Grid, VisualMap, VisualMapPiece, VisualMapType},
     datatype::DataPointItem,
@@ -24,6 +32,7 @@ pub struct HeartrateDistributionEntry {
     pub count: i32,
 }
 
+/// Number of records at each heart rate, above 55% of the current max heart rate.
 #[cfg(feature = "ssr")]
 pub async fn heartrate_zone_summary(
     user_id: i64,
@@ -31,18 +40,26 @@ pub async fn heartrate_zone_summary(
     to: DateTime<Local>,
     executor: sqlx::PgPool,
 ) -> Result<Vec<HeartrateDistributionEntry>, sqlx::Error> {
-    let result = sqlx::query_as!(HeartrateDistributionEntry, r#"
-    SELECT
-        r.heartrate::int4 as "heartrate!",
-        COALESCE(COUNT(*),0)::int4 as "count!"
-    FROM activities a 
-    JOIN records r on r.activity_id = a.id
-    LEFT JOIN user_preferences up ON up.user_id=a.user_id
-    WHERE a.user_id = $1::bigint AND a.start_time >= $2::timestamptz AND a.end_time <= $3::timestamptz
-        AND r.heartrate IS NOT NULL AND r.heartrate >= COALESCE(up.max_heartrate * 0.55, 100)
-    GROUP BY r.heartrate
-    ORDER BY r.heartrate ASC
-"#, &user_id, &from,&to).fetch_all(&executor).await?;
+    let preferences = get_user_preferences(user_id, Local::now(), &executor).await;
+    let result = sqlx::query_as!(
+        HeartrateDistributionEntry,
+        r#"
+        SELECT
+            heartrate::int4 as "heartrate!",
+            SUM(count)::int4 as "count!"
+        FROM daily_heartrates
+        WHERE user_id = $1::bigint AND day >= date_trunc('day', $2::timestamptz) AND day <= $3::timestamptz
+            AND heartrate >= $4::int4 * 0.55
+        GROUP BY heartrate
+        ORDER BY heartrate ASC
+        "#,
+        &user_id,
+        &from,
+        &to,
+        preferences.max_heartrate
+    )
+    .fetch_all(&executor)
+    .await?;
     Ok(result)
 }
 
@@ -67,6 +84,28 @@ pub async fn heartrate_distribution_action(
     Ok(summary)
 }
 
+/// Visual map pieces coloring heart rates by zone.
+pub fn zone_pieces(zones: &[Zone]) -> Vec<VisualMapPiece> {
+    let below = zones.first().map(|zone| {
+        VisualMapPiece::new()
+            .gt(0)
+            .lte(zone.low - 1)
+            .color(BELOW_ZONES_COLOR)
+    });
+    below
+        .into_iter()
+        .chain(zones.iter().map(|zone| {
+            let piece = VisualMapPiece::new()
+                .gt(zone.low - 1)
+                .color(zone.color.as_str());
+            match zone.high {
+                Some(high) => piece.lte(high - 1),
+                None => piece,
+            }
+        }))
+        .collect()
+}
+
 #[component]
 pub fn HeartrateDistributionChart(
     #[prop(into)] from: Memo<Option<DateTime<Local>>>,
@@ -83,15 +122,8 @@ pub fn HeartrateDistributionChart(
     let _chart = create_local_resource(
         move || (zone_distribution.get(), user_prefs.get(), width()),
         move |(zone_distribution, user_prefs, width)| async move {
-            let (aerobic, anaerobic, max_hr) = if let Some(Ok(prefs)) = user_prefs {
-                (
-                    prefs.aerobic_threshold,
-                    prefs.anaerobic_threshold,
-                    prefs.max_heartrate,
-                )
-            } else {
-                (155, 173, 183)
-            };
+            let prefs = user_prefs.and_then(|prefs| prefs.ok()).unwrap_or_default();
+            let zones = prefs.zones();
             if let Some(Ok(zone_distribution)) = zone_distribution {
                 let chart = Chart::new()
                     .grid(Grid::new().top(10).bottom(20))
@@ -102,25 +134,8 @@ pub fn HeartrateDistributionChart(
                             .show(false)
                             .type_(VisualMapType::Piecewise)
                             .min(0)
-                            .max(max_hr)
-                            .pieces(vec![
-                                VisualMapPiece::new()
-                                    .gt(0)
-                                    .lte(aerobic as f32 * 0.7)
-                                    .color("#7dc4e4"),
-                                VisualMapPiece::new()
-                                    .gt(aerobic as f32 * 0.7)
-                                    .lte(aerobic)
-                                    .color("#a6da95"),
-                                VisualMapPiece::new()
-                                    .gt(aerobic)
-                                    .lte(anaerobic)
-                                    .color("#eed49f"),
-                                VisualMapPiece::new()
-                                    .gt(anaerobic)
-                                    .lte(max_hr)
-                                    .color("#ed8796"),
-                            ]),
+                            .max(prefs.max_heartrate)
+                            .pieces(zone_pieces(&zones)),
                     )
                     .x_axis(
                         Axis::new().type_(AxisType::Category).data(
@@ -138,15 +153,10 @@ pub fn HeartrateDistributionChart(
                                 .map(|d| {
                                     DataPointItem::new(d.count as i64).item_style(
                                         ItemStyle::new().color(
-                                            if d.heartrate < (aerobic as f32 * 0.7) as i32 {
-                                                "#7dc4e4"
-                                            } else if d.heartrate < aerobic {
-                                                "#a6da95"
-                                            } else if d.heartrate < anaerobic {
-                                                "#eed49f"
-                                            } else {
-                                                "#ed8796"
-                                            },
+                                            zone_index(&zones, d.heartrate)
+                                                .map_or(BELOW_ZONES_COLOR, |i| {
+                                                    zones[i].color.as_str()
+                                                }),
                                         ),
                                     )
                                 })
@@ -1,10 +1,15 @@
+use crate::{app::FitFileUploaded, error_template::ErrorTemplate, models::zones::Zone};
 #[cfg(feature = "ssr")]
-use crate::app::{auth, pool};
-use crate::{app::FitFileUploaded, error_template::ErrorTemplate};
+use crate::{
+    app::{auth, pool},
+    models::{
+        user_preferences::{get_user_preferences, get_user_preferences_history},
+        zones::{zone_index, ZoneModel},
+    },
+};
 use charming::{
     component::{Grid, Legend},
     datatype::DataPointItem,
-    df,
     element::{ItemStyle, Orient, Tooltip, Trigger},
     series::Pie,
     Chart, WasmRenderer,
@@ -19,12 +24,19 @@ use serde::{Deserialize, Serialize};
 use sqlx::*;
 use std::cmp;
 
+/// Time in each heart rate zone of the current zone model.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct HeartrateSummary {
-    pub zone1: Option<i64>,
-    pub zone2: Option<i64>,
-    pub zone3: Option<i64>,
+    pub zones: Vec<Zone>,
+    /// Seconds in each zone.
+    pub seconds: Vec<f64>,
 }
+
+/// Sum up the time in each zone.
+///
+/// The zones are those of the current zone model, with the heart rates of the preferences at the
+/// time of each activity. The daily time in zones is used where it is of the current model, the
+/// heart rates of other activities are counted as a second per record.
 #[cfg(feature = "ssr")]
 pub async fn heartrate_zone_summary(
     user_id: i64,
@@ -32,25 +44,67 @@ pub async fn heartrate_zone_summary(
     to: DateTime<Local>,
     executor: sqlx::PgPool,
 ) -> Result<HeartrateSummary, sqlx::Error> {
-    let result = sqlx::query_as!(HeartrateSummary, r#"SELECT
-    COUNT(*) FILTER (WHERE m.zone = 3) AS zone3,
-    COUNT(*) FILTER (WHERE m.zone = 2) AS zone2,
-    COUNT(*) FILTER (WHERE m.zone = 1) AS zone1
-FROM (
-    SELECT record.heartrate,
-        CASE
-            WHEN record.heartrate >= COALESCE(up.max_heartrate * 0.55, 100) AND record.heartrate < COALESCE(up.aerobic_threshold,155) THEN 1
-            WHEN record.heartrate >= COALESCE(up.aerobic_threshold, 155) AND record.heartrate < COALESCE(up.anaerobic_threshold,172) THEN 2
-            WHEN record.heartrate >= COALESCE(up.anaerobic_threshold, 172) THEN 3
-        END as zone
-    FROM activities as activities
-    LEFT JOIN records as record ON record.activity_id = activities.id
-    LEFT JOIN user_preferences up ON up.user_id=activities.user_id
-    WHERE activities.user_id = $1::bigint AND activities.start_time >= $2::timestamptz AND activities.end_time <= $3::timestamptz
-        AND record.heartrate IS NOT NULL AND record.heartrate >= COALESCE(up.max_heartrate * 0.55, 100)
-) m
-"#, &user_id, &from,&to).fetch_one(&executor).await?;
-    Ok(result)
+    let current = get_user_preferences(user_id, Local::now(), &executor).await;
+    let periods = get_user_preferences_history(user_id, &executor).await?;
+    let stored = sqlx::query!(
+        r#"
+        SELECT zone::int4 as "zone!", SUM(seconds)::float8 as "seconds!"
+        FROM daily_time_in_zones
+        WHERE user_id = $1::bigint AND day >= date_trunc('day', $2::timestamptz) AND day <= $3::timestamptz
+            AND zone_model = $4
+        GROUP BY zone
+        "#,
+        &user_id,
+        &from,
+        &to,
+        current.zone_model as ZoneModel
+    )
+    .fetch_all(&executor)
+    .await?;
+    let heartrates = sqlx::query!(
+        r#"
+            SELECT up.start_time as "start_time?: DateTime<Local>",
+                up.end_time as "end_time?: DateTime<Local>",
+                h.heartrate::int4 as "heartrate!", SUM(h.count)::int8 as "count!"
+            FROM activities a
+            JOIN activity_heartrates h ON h.activity_id = a.id
+            LEFT JOIN user_preferences up ON up.user_id = a.user_id
+                AND (up.start_time IS NULL OR up.start_time <= a.start_time)
+                AND (up.end_time IS NULL OR a.start_time < up.end_time)
+            WHERE a.user_id = $1::bigint
+                AND date_trunc('day', a.start_time) >= date_trunc('day', $2::timestamptz)
+                AND date_trunc('day', a.start_time) <= $3::timestamptz
+                AND NOT EXISTS (
+                    SELECT 1 FROM time_in_zones t WHERE t.activity_id = a.id AND t.zone_model = $4
+                )
+            GROUP BY up.start_time, up.end_time, h.heartrate
+            "#,
+        &user_id,
+        &from,
+        &to,
+        current.zone_model as ZoneModel
+    )
+    .fetch_all(&executor)
+    .await?;
+    let zones = current.zones();
+    let mut seconds = vec![0.0; zones.len()];
+    for row in stored {
+        if let Some(seconds) = seconds.get_mut(row.zone as usize) {
+            *seconds += row.seconds;
+        }
+    }
+    for row in heartrates {
+        // activities outside of all periods use the defaults, like get_user_preferences
+        let period = periods
+            .iter()
+            .find(|p| p.start_time == row.start_time && p.end_time == row.end_time)
+            .cloned()
+            .unwrap_or_default();
+        if let Some(zone) = zone_index(&current.zone_model.zones(&period), row.heartrate) {
+            seconds[zone] += row.count as f64;
+        }
+    }
+    Ok(HeartrateSummary { zones, seconds })
 }
 
 #[server(HeartrateSummaryAction, "/api")]
@@ -94,17 +148,20 @@ pub fn HeartrateZoneSummaryChart(
                     .grid(Grid::new().top(10).bottom(10))
                     .legend(Legend::new().orient(Orient::Vertical).left("left"))
                     .tooltip(Tooltip::new().trigger(Trigger::Item))
-                    .series(Pie::new().radius("75%").data(df!(
-                        DataPointItem::new(zone_summary.zone1.unwrap_or(0))
-                            .name("Zone 1")
-                            .item_style(ItemStyle::new().color("#a6da95")),
-                        DataPointItem::new(zone_summary.zone2.unwrap_or(0))
-                            .name( "Zone 2")
-                            .item_style(ItemStyle::new().color("#eed49f")),
-                        DataPointItem::new(zone_summary.zone3.unwrap_or(0))
-                            .name( "Zone 3")
-                            .item_style(ItemStyle::new().color("#ed8796")),
-                    )));
+                    .series(
+                        Pie::new().radius("75%").data(
+                            zone_summary
+                                .zones
+                                .iter()
+                                .zip(zone_summary.seconds)
+                                .map(|(zone, seconds)| {
+                                    DataPointItem::new((seconds / 60.0).round() as i64)
+                                        .name(zone.name.clone())
+                                        .item_style(ItemStyle::new().color(zone.color.as_str()))
+                                })
+                                .collect::<Vec<_>>(),
+                        ),
+                    );
                 let renderer = WasmRenderer::new(cmp::max(width as u32, 300), 155);
                 let _rendered = renderer.render("heartrate_summary_chart", &chart);
             }
@@ -1,14 +1,18 @@
-mod fitness_level_chart;
-mod heartrate_distribution_chart;
+pub mod fitness_level_chart;
+pub mod heartrate_distribution_chart;
 mod heartrate_summary_chart;
+mod performance_model;
 mod training_load_chart;
+pub mod workload_chart;
 
 use chrono::{Duration, Local, NaiveDate, TimeZone};
 use fitness_level_chart::FitnessLevelChart;
 use heartrate_distribution_chart::HeartrateDistributionChart;
 use heartrate_summary_chart::HeartrateZoneSummaryChart;
 use leptos::*;
+use performance_model::PerformanceModel;
 use training_load_chart::TrainingLoadChart;
+use workload_chart::WorkloadChart;
 #[component]
 pub fn Overview() -> impl IntoView {
     //overview page
@@ -130,6 +134,26 @@ pub fn Overview() -> impl IntoView {
                         </div>
                     </div>
                 </div>
+                <div class="column is-full-mobile is-half-desktop is-one-third-fullhd">
+                    <div class="card is-fullwidth">
+                        <div class="card-header">
+                            <p class="card-header-title">Performance Model</p>
+                        </div>
+                        <div class="card-content ">
+                            <PerformanceModel/>
+                        </div>
+                    </div>
+                </div>
+                <div class="column is-full-mobile is-half-desktop is-one-third-fullhd">
+                    <div class="card is-fullwidth">
+                        <div class="card-header">
+                            <p class="card-header-title">Monotony, Strain and ACWR</p>
+                        </div>
+                        <div class="card-content ">
+                            <WorkloadChart from=from_memo to=to_memo/>
+                        </div>
+                    </div>
+                </div>
             </div>
         </div>
     }
@@ -0,0 +1,309 @@
+use crate::{
+    app::FitFileUploaded,
+    error_template::ErrorTemplate,
+    models::banister::{BanisterModel, Benchmark, ModelDay},
+};
+#[cfg(feature = "ssr")]
+use crate::{
+    app::{auth, pool},
+    models::banister::{
+        fit_banister_model, get_banister_model, get_benchmarks, model_days, MIN_BENCHMARKS,
+    },
+};
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone};
+use leptos::*;
+use leptos_router::*;
+
+#[server]
+pub async fn get_performance_model() -> Result<(BanisterModel, Vec<Benchmark>), ServerFnError> {
+    let pool = pool()?;
+    let auth = auth()?;
+    let user = auth
+        .current_user
+        .ok_or(ServerFnError::new("Not logged in".to_string()))?;
+    let model = get_banister_model(user.id, &pool).await?;
+    let benchmarks = get_benchmarks(user.id, &pool).await?;
+    Ok((model, benchmarks))
+}
+
+/// Add a race result or test effort, `date` is `%Y-%m-%d`.
+#[server]
+pub async fn add_benchmark(
+    date: String,
+    performance: f64,
+    note: String,
+) -> Result<(), ServerFnError> {
+    let pool = pool()?;
+    let auth = auth()?;
+    let user = auth
+        .current_user
+        .ok_or(ServerFnError::new("Not logged in".to_string()))?;
+    let day = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+        .ok()
+        .and_then(|day| {
+            Local
+                .from_local_datetime(&day.and_hms_opt(0, 0, 0).unwrap())
+                .single()
+        })
+        .ok_or(ServerFnError::new("Invalid date".to_string()))?;
+    if !performance.is_finite() {
+        return Err(ServerFnError::new(
+            "Performance has to be a number".to_string(),
+        ));
+    }
+    let note = note.trim();
+    sqlx::query!(
+        "INSERT INTO benchmarks (user_id, day, performance, note) VALUES ($1, $2, $3, $4)",
+        user.id as i32,
+        day,
+        performance,
+        (!note.is_empty()).then_some(note),
+    )
+    .execute(&pool)
+    .await
+    .map_err(|e| ServerFnError::new(format!("Couldn't add benchmark:{}", e)))?;
+    Ok(())
+}
+
+#[server]
+pub async fn delete_benchmark(id: i32) -> Result<(), ServerFnError> {
+    let pool = pool()?;
+    let auth = auth()?;
+    let user = auth
+        .current_user
+        .ok_or(ServerFnError::new("Not logged in".to_string()))?;
+    sqlx::query!(
+        "DELETE FROM benchmarks WHERE user_id=$1 AND id=$2",
+        user.id as i32,
+        id
+    )
+    .execute(&pool)
+    .await
+    .map_err(|e| ServerFnError::new(format!("Couldn't delete benchmark:{}", e)))?;
+    Ok(())
+}
+
+/// Fit the time constants and gains of the performance model to the user's benchmarks.
+#[server]
+pub async fn fit_performance_model() -> Result<BanisterModel, ServerFnError> {
+    let pool = pool()?;
+    let auth = auth()?;
+    let user = auth
+        .current_user
+        .ok_or(ServerFnError::new("Not logged in".to_string()))?;
+    let mut conn = pool.acquire().await?;
+    fit_banister_model(user.id, &mut conn)
+        .await
+        .map_err(|e| ServerFnError::new(format!("Couldn't fit model:{}", e)))?
+        .ok_or(ServerFnError::new(format!(
+            "Fitting needs at least {} benchmarks after the first activity",
+            MIN_BENCHMARKS
+        )))
+}
+
+/// Predicted fitness, fatigue, form and performance on a date, assuming no training from today
+/// on. `None` if there are no activities yet.
+#[server]
+pub async fn predict_form(date: DateTime<Local>) -> Result<Option<ModelDay>, ServerFnError> {
+    let pool = pool()?;
+    let auth = auth()?;
+    let user = auth
+        .current_user
+        .ok_or(ServerFnError::new("Not logged in".to_string()))?;
+    let mut conn = pool.acquire().await?;
+    let days = model_days(user.id, date, &mut conn).await?;
+    Ok(days.last().cloned())
+}
+
+#[component]
+pub fn PerformanceModel() -> impl IntoView {
+    let uploaded = use_context::<FitFileUploaded>().unwrap();
+    let add_benchmark = create_server_action::<AddBenchmark>();
+    let delete_benchmark = create_server_action::<DeleteBenchmark>();
+    let fit_model = create_server_action::<FitPerformanceModel>();
+    let performance_model = create_resource(
+        move || {
+            (
+                add_benchmark.version().get(),
+                delete_benchmark.version().get(),
+                fit_model.version().get(),
+            )
+        },
+        move |_| get_performance_model(),
+    );
+    let prediction_date = create_rw_signal(
+        (Local::now() + Duration::try_days(7).unwrap())
+            .format("%Y-%m-%d")
+            .to_string(),
+    );
+    let prediction = create_resource(
+        move || (prediction_date.get(), fit_model.version().get()),
+        move |(date, _)| async move {
+            let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| Local.from_local_datetime(&d.and_hms_opt(0, 0, 0)?).single());
+            match date {
+                Some(date) => predict_form(date).await,
+                None => Ok(None),
+            }
+        },
+    );
+    // the fitness chart shows the fitted model
+    create_effect(move |_| {
+        if let Some(Ok(_)) = fit_model.value().get() {
+            uploaded.0.update(|v| *v += 1);
+        }
+    });
+    let fit_error = move || {
+        fit_model
+            .value()
+            .get()
+            .and_then(|result| result.err())
+            .map(|e| view! { <p class="help is-danger">{e.to_string()}</p> })
+    };
+    view! {
+        <Transition fallback=move || view! { <p>"Loading..."</p> }>
+            <ErrorBoundary fallback=|errors| {
+                view! { <ErrorTemplate errors=errors/> }
+            }>
+                {move || {
+                    performance_model
+                        .get()
+                        .map(|result| {
+                            result
+                                .map(|(model, benchmarks)| {
+                                    view! {
+                                        <p>
+                                            {match model.rmse {
+                                                Some(rmse) => {
+                                                    format!(
+                                                        "Fitness {:.0} days, fatigue {:.0} days, k1 {:.2}, k2 {:.2}, p0 {:.1}, error {:.2}",
+                                                        model.fitness_tau,
+                                                        model.fatigue_tau,
+                                                        model.k1,
+                                                        model.k2,
+                                                        model.p0,
+                                                        rmse,
+                                                    )
+                                                }
+                                                None => {
+                                                    "Not fitted yet, using 42 and 7 day time constants."
+                                                        .to_string()
+                                                }
+                                            }}
+
+                                        </p>
+                                        <table class="table is-narrow is-fullwidth">
+                                            <thead>
+                                                <tr>
+                                                    <th>"Date"</th>
+                                                    <th>"Performance"</th>
+                                                    <th>"Note"</th>
+                                                    <th></th>
+                                                </tr>
+                                            </thead>
+                                            <tbody>
+                                                {benchmarks
+                                                    .into_iter()
+                                                    .map(|benchmark| {
+                                                        view! {
+                                                            <tr>
+                                                                <td>{benchmark.day.format("%Y-%m-%d").to_string()}</td>
+                                                                <td>{benchmark.performance}</td>
+                                                                <td>{benchmark.note}</td>
+                                                                <td>
+                                                                    <a
+                                                                        href="#!"
+                                                                        on:click=move |_| {
+                                                                            delete_benchmark
+                                                                                .dispatch(DeleteBenchmark {
+                                                                                    id: benchmark.id,
+                                                                                });
+                                                                        }
+                                                                    >
+
+                                                                        <i class="material-symbols-rounded">delete</i>
+                                                                    </a>
+                                                                </td>
+                                                            </tr>
+                                                        }
+                                                    })
+                                                    .collect_view()}
+                                            </tbody>
+                                        </table>
+                                    }
+                                })
+                        })
+                }}
+
+            </ErrorBoundary>
+        </Transition>
+        <ActionForm action=add_benchmark>
+            <div class="field has-addons">
+                <div class="control">
+                    <input class="input" type="date" name="date" required/>
+                </div>
+                <div class="control">
+                    <input
+                        class="input"
+                        type="number"
+                        step="any"
+                        name="performance"
+                        placeholder="Performance"
+                        title="Race result or test effort, higher is better"
+                        required
+                    />
+                </div>
+                <div class="control">
+                    <input class="input" type="text" name="note" placeholder="Note"/>
+                </div>
+                <div class="control">
+                    <button type="submit" class="button">
+                        "Add"
+                    </button>
+                </div>
+            </div>
+        </ActionForm>
+        <div class="field">
+            <button
+                class="button is-primary"
+                class:is-loading=fit_model.pending()
+                on:click=move |_| fit_model.dispatch(FitPerformanceModel {})
+            >
+                "Fit Model"
+            </button>
+            {fit_error}
+        </div>
+        <div class="field">
+            <label class="label">"Predicted form on"</label>
+            <div class="control">
+                <input
+                    class="input"
+                    type="date"
+                    prop:value=prediction_date
+                    on:change=move |ev| prediction_date.set(event_target_value(&ev))
+                />
+            </div>
+            <Transition fallback=|| ()>
+                {move || {
+                    prediction
+                        .get()
+                        .and_then(|prediction| prediction.ok().flatten())
+                        .map(|day| {
+                            view! {
+                                <p class="help">
+                                    {format!(
+                                        "Form {:.0} (fitness {:.0}, fatigue {:.0}), performance {:.1}",
+                                        day.form,
+                                        day.fitness,
+                                        day.fatigue,
+                                        day.performance,
+                                    )}
+                                </p>
+                            }
+                        })
+                }}
+            </Transition>
+        </div>
+    }
+}
@@ -9,6 +9,7 @@ use charming::{
 #[cfg(feature = "ssr")]
 use chrono::Duration;
 use chrono::{DateTime, Local};
+use itertools::Itertools;
 use leptos::{html::Div, *};
 use leptos_use::{use_element_size, UseElementSizeReturn};
 use serde::{Deserialize, Serialize};
@@ -19,8 +20,13 @@ use crate::{app::FitFileUploaded, error_template::ErrorTemplate};
 pub struct TrainingLoad {
     pub load: i64,
     pub date: DateTime<Local>,
+    /// None for weeks without any load
+    pub sport: Option<String>,
 }
 
+/// Weekly load per sport from the daily totals. Sessions of multisport
+/// activities count towards their own sport, activities without session loads
+/// count towards the sport of their first session.
 #[cfg(feature = "ssr")]
 pub async fn training_load(
     user_id: i64,
@@ -30,30 +36,31 @@ pub async fn training_load(
 ) -> Result<Vec<TrainingLoad>, sqlx::Error> {
     let result: Vec<TrainingLoad> = sqlx::query_as!(
         TrainingLoad,
-        r#" 
+        r#"
     WITH weeks as (
         SELECT generate_series(
             date_trunc('week', $2::timestamptz),
             date_trunc('week', $3::timestamptz),
             '1 week'
         ) as start
+    ),
+    loads as (
+        SELECT
+            date_trunc('week', day) as date,
+            sport,
+            load
+        FROM daily_totals
+        WHERE user_id = $1::bigint
+            AND load IS NOT NULL
     )
     SELECT
         weeks.start as "date!",
-        COALESCE(SUM(activities.load), 0)::int8 as "load!"
+        loads.sport as "sport?",
+        COALESCE(SUM(loads.load), 0)::int8 as "load!"
     FROM weeks
-    LEFT JOIN (
-        SELECT 
-            activities.load as load,
-            date_trunc('week', activities.start_time ) as date
-        FROM activities as activities
-        LEFT JOIN records as record ON record.activity_id = activities.id
-        WHERE activities.user_id = $1::bigint 
-            AND record.heartrate IS NOT NULL
-        GROUP BY activities.id
-    ) activities ON activities.date = weeks.start
-    GROUP BY weeks.start
-    ORDER BY weeks.start
+    LEFT JOIN loads ON loads.date = weeks.start
+    GROUP BY weeks.start, loads.sport
+    ORDER BY weeks.start, loads.sport
 "#,
         &user_id,
         &from,
@@ -101,21 +108,42 @@ pub fn TrainingLoadChart(
         move || (training_load.get(), width()),
         move |(load, width)| async move {
             if let Some(Ok(training_load)) = load {
-                let chart = Chart::new()
+                let weeks = training_load
+                    .iter()
+                    .map(|t| t.date)
+                    .dedup()
+                    .collect::<Vec<_>>();
+                let sports = training_load
+                    .iter()
+                    .filter_map(|t| t.sport.clone())
+                    .sorted()
+                    .dedup()
+                    .collect::<Vec<_>>();
+                let mut chart = Chart::new()
                     .grid(Grid::new().top(10).bottom(20))
                     .tooltip(Tooltip::new().trigger(Trigger::Axis))
                     .x_axis(
                         Axis::new().type_(AxisType::Category).data(
-                            training_load
+                            weeks
                                 .iter()
-                                .map(|t| format!("{}", t.date.format("%Y-%m-%d")))
+                                .map(|date| format!("{}", date.format("%Y-%m-%d")))
                                 .collect::<Vec<_>>(),
                         ),
                     )
-                    .y_axis(Axis::new().type_(AxisType::Value))
-                    .series(
-                        Bar::new().data(training_load.iter().map(|t| t.load).collect::<Vec<_>>()),
-                    );
+                    .y_axis(Axis::new().type_(AxisType::Value));
+                for sport in sports {
+                    let loads = weeks
+                        .iter()
+                        .map(|date| {
+                            training_load
+                                .iter()
+                                .find(|t| t.date == *date && t.sport.as_ref() == Some(&sport))
+                                .map(|t| t.load)
+                                .unwrap_or(0)
+                        })
+                        .collect::<Vec<_>>();
+                    chart = chart.series(Bar::new().name(sport).stack("load").data(loads));
+                }
                 let renderer = WasmRenderer::new(cmp::max(width as u32, 300), 155);
                 let _rendered = renderer.render("training_load_chart", &chart);
             }
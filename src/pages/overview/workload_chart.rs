@@ -0,0 +1,208 @@
+#[cfg(feature = "ssr")]
+use crate::{
+    app::{auth, pool},
+    models::{banister::daily_loads, user_preferences::get_user_preferences, workload::workload},
+};
+use charming::{
+    component::{Axis, Grid},
+    element::{AxisType, MarkLine, MarkLineData, MarkLineVariant, Symbol, Tooltip, Trigger},
+    series::{Bar, Line},
+    Chart, WasmRenderer,
+};
+#[cfg(feature = "ssr")]
+use chrono::Duration;
+use chrono::{DateTime, Local};
+use itertools::MultiUnzip;
+use leptos::{html::Div, *};
+use leptos_use::{use_element_size, UseElementSizeReturn};
+use std::cmp;
+
+use crate::{
+    app::FitFileUploaded,
+    error_template::ErrorTemplate,
+    models::workload::{Workload, WorkloadThresholds},
+};
+
+/// Daily monotony, strain and ACWR from `from` up to and including `to`, with the warning
+/// thresholds of the current preferences.
+#[server]
+pub async fn workload_action(
+    from: Option<DateTime<Local>>,
+    to: Option<DateTime<Local>>,
+) -> Result<(Vec<Workload>, WorkloadThresholds), ServerFnError> {
+    let pool = pool()?;
+    let auth = auth()?;
+    let user = auth
+        .current_user
+        .ok_or(ServerFnError::new("Not logged in".to_string()))?;
+    let from = from.unwrap_or(Local::now() - Duration::try_days(120).unwrap());
+    let loads = daily_loads(user.id, to.unwrap_or(Local::now()), &pool).await?;
+    let days = workload(&loads)
+        .into_iter()
+        .filter(|day| day.day.date_naive() >= from.date_naive())
+        .collect();
+    let preferences = get_user_preferences(user.id, Local::now(), &pool).await;
+    Ok((days, WorkloadThresholds::from(&preferences)))
+}
+
+/// The current ACWR if it is outside of the safe band.
+#[server]
+pub async fn acwr_warning() -> Result<Option<f64>, ServerFnError> {
+    let pool = pool()?;
+    let auth = auth()?;
+    let user = auth
+        .current_user
+        .ok_or(ServerFnError::new("Not logged in".to_string()))?;
+    let loads = daily_loads(user.id, Local::now(), &pool).await?;
+    let preferences = get_user_preferences(user.id, Local::now(), &pool).await;
+    let thresholds = WorkloadThresholds::from(&preferences);
+    Ok(workload(&loads)
+        .last()
+        .and_then(|today| today.acwr)
+        .filter(|acwr| thresholds.acwr_warning(*acwr)))
+}
+
+/// Horizontal lines at thresholds.
+fn threshold_lines(thresholds: &[(f64, &str)]) -> MarkLine {
+    MarkLine::new()
+        .symbol(vec![Symbol::None, Symbol::None])
+        .data(
+            thresholds
+                .iter()
+                .map(|(value, name)| {
+                    MarkLineVariant::Simple(MarkLineData::new().y_axis(*value).name(*name))
+                })
+                .collect::<Vec<_>>(),
+        )
+}
+
+#[component]
+pub fn WorkloadChart(
+    #[prop(into)] from: Memo<Option<DateTime<Local>>>,
+    #[prop(into)] to: Memo<Option<DateTime<Local>>>,
+) -> impl IntoView {
+    let uploaded = use_context::<FitFileUploaded>().unwrap();
+    let metrics = create_resource(
+        move || (from(), to(), uploaded.0()),
+        move |(from, to, _)| workload_action(from, to),
+    );
+    let workload_chart = create_node_ref::<Div>();
+    let UseElementSizeReturn { width, height: _ } = use_element_size(workload_chart);
+    let _chart = create_local_resource(
+        move || (metrics.get(), width()),
+        move |(metrics, width)| async move {
+            if let Some(Ok((days, thresholds))) = metrics {
+                // undefined values, e.g. without enough history, are shown as 0
+                let (date, acwr, monotony, strain): (Vec<_>, Vec<_>, Vec<_>, Vec<_>) = days
+                    .iter()
+                    .map(|day| {
+                        (
+                            format!("{}", day.day.format("%Y-%m-%d")),
+                            (day.acwr.unwrap_or(0.0) * 100.0).round() / 100.0,
+                            (day.monotony.unwrap_or(0.0) * 100.0).round() / 100.0,
+                            day.strain.unwrap_or(0.0).round() as i64,
+                        )
+                    })
+                    .multiunzip();
+                let mut strain_series = Bar::new().name("Strain").y_axis_index(1).data(strain);
+                if let Some(strain) = thresholds.strain {
+                    strain_series = strain_series.mark_line(threshold_lines(&[(strain, "Strain")]));
+                }
+                let chart = Chart::new()
+                    .grid(Grid::new().top(10).bottom(20))
+                    .tooltip(Tooltip::new().trigger(Trigger::Axis))
+                    .x_axis(Axis::new().type_(AxisType::Category).data(date))
+                    .y_axis(Axis::new().type_(AxisType::Value))
+                    .y_axis(Axis::new().type_(AxisType::Value))
+                    .series(strain_series)
+                    .series(
+                        Line::new()
+                            .name("ACWR")
+                            .show_symbol(false)
+                            .data(acwr)
+                            .mark_line(threshold_lines(&[
+                                (thresholds.acwr_low, "ACWR Low"),
+                                (thresholds.acwr_high, "ACWR High"),
+                            ])),
+                    )
+                    .series(
+                        Line::new()
+                            .name("Monotony")
+                            .show_symbol(false)
+                            .data(monotony)
+                            .mark_line(threshold_lines(&[(thresholds.monotony, "Monotony")])),
+                    );
+                let renderer = WasmRenderer::new(cmp::max(width as u32, 300), 155);
+                let _rendered = renderer.render("workload_chart", &chart);
+            }
+        },
+    );
+    let warnings = move || {
+        metrics
+            .get()
+            .and_then(|metrics| metrics.ok())
+            .map(|(days, thresholds)| {
+                days.last().map(|today| {
+                    [
+                        today
+                            .acwr
+                            .filter(|acwr| thresholds.acwr_warning(*acwr))
+                            .map(|acwr| format!("ACWR {:.2}", acwr)),
+                        today
+                            .monotony
+                            .filter(|monotony| thresholds.monotony_warning(*monotony))
+                            .map(|monotony| format!("Monotony {:.2}", monotony)),
+                        today
+                            .strain
+                            .filter(|strain| thresholds.strain_warning(*strain))
+                            .map(|strain| format!("Strain {:.0}", strain)),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .map(|warning| view! { <span class="tag is-warning">{warning}</span> })
+                    .collect_view()
+                })
+            })
+    };
+
+    view! {
+        <Transition fallback=move || view! { <p>"Loading..."</p> }>
+            <ErrorBoundary fallback=|errors| {
+                view! { <ErrorTemplate errors=errors/> }
+            }>
+                <div class="tags">{warnings}</div>
+                <div node_ref=workload_chart id="workload_chart"></div>
+            </ErrorBoundary>
+        </Transition>
+    }
+}
+
+/// Badge for the header while the current ACWR is outside of the safe band.
+#[component]
+pub fn AcwrWarning() -> impl IntoView {
+    let uploaded = use_context::<FitFileUploaded>().unwrap();
+    let warning = create_resource(move || uploaded.0(), move |_| acwr_warning());
+    view! {
+        <Transition fallback=|| ()>
+            {move || {
+                warning
+                    .get()
+                    .and_then(|warning| warning.ok().flatten())
+                    .map(|acwr| {
+                        view! {
+                            <div class="navbar-item">
+                                <span
+                                    class="tag is-warning"
+                                    title="The acute:chronic workload ratio is outside of the safe band"
+                                >
+                                    <i class="material-symbols-rounded">warning</i>
+                                    {format!("ACWR {:.2}", acwr)}
+                                </span>
+                            </div>
+                        }
+                    })
+            }}
+
+        </Transition>
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    app::FitFileUploaded,
+    error_template::ErrorTemplate,
+    models::best_efforts::{BestEffort, BestEffortKind},
+};
+#[cfg(feature = "ssr")]
+use crate::{
+    app::{auth, pool},
+    models::best_efforts::{personal_records, record_years},
+};
+use leptos::*;
+use leptos_router::*;
+
+/// Personal records of all time or of the activities of one year, with the years that have
+/// records.
+#[server]
+pub async fn get_personal_records(
+    year: Option<i32>,
+) -> Result<(Vec<i32>, Vec<BestEffort>), ServerFnError> {
+    let pool = pool()?;
+    let auth = auth()?;
+    let user = auth
+        .current_user
+        .ok_or(ServerFnError::new("Not logged in".to_string()))?;
+    let years = record_years(user.id, &pool).await?;
+    let records = personal_records(user.id, year, &pool).await?;
+    Ok((years, records))
+}
+
+#[component]
+fn SportRecords(sport: String, records: Vec<BestEffort>) -> impl IntoView {
+    view! {
+        <div class="column is-full-mobile is-half-desktop is-one-third-fullhd">
+            <div class="card is-fullwidth">
+                <header class="card-header">
+                    <p class="card-header-title">{sport}</p>
+                </header>
+                <div class="card-content">
+                    <table class="table is-narrow is-fullwidth">
+                        <tbody>
+                            {BestEffortKind::ALL
+                                .into_iter()
+                                .filter_map(|kind| {
+                                    let records: Vec<_> = records
+                                        .iter()
+                                        .filter(|record| record.kind == kind)
+                                        .cloned()
+                                        .collect();
+                                    (!records.is_empty())
+                                        .then(|| {
+                                            view! {
+                                                <tr>
+                                                    <th colspan="3">{kind.label()}</th>
+                                                </tr>
+                                                {records
+                                                    .into_iter()
+                                                    .map(|record| {
+                                                        view! {
+                                                            <tr>
+                                                                <td>{kind.target_label(record.target)}</td>
+                                                                <td>{kind.format(record.value)}</td>
+                                                                <td>
+                                                                    <A href=format!(
+                                                                        "/activities?activity={}",
+                                                                        record.activity_id,
+                                                                    )>
+                                                                        {record.start_time.format("%Y-%m-%d").to_string()}
+                                                                    </A>
+                                                                </td>
+                                                            </tr>
+                                                        }
+                                                    })
+                                                    .collect_view()}
+                                            }
+                                        })
+                                })
+                                .collect_view()}
+                        </tbody>
+                    </table>
+                </div>
+            </div>
+        </div>
+    }
+}
+
+#[component]
+pub fn PersonalRecords() -> impl IntoView {
+    let uploaded = use_context::<FitFileUploaded>().unwrap();
+    let year = create_rw_signal(None::<i32>);
+    let records = create_resource(
+        move || (year.get(), uploaded.0.get()),
+        move |(year, _)| get_personal_records(year),
+    );
+    view! {
+        <div class="container">
+            <Transition fallback=move || view! { <p>"Loading..."</p> }>
+                <ErrorBoundary fallback=|errors| {
+                    view! { <ErrorTemplate errors=errors/> }
+                }>
+                    {move || {
+                        records
+                            .get()
+                            .map(|result| {
+                                result
+                                    .map(|(years, records)| {
+                                        let mut sports: BTreeMap<String, Vec<BestEffort>> = BTreeMap::new();
+                                        for record in records {
+                                            sports.entry(record.sport.clone()).or_default().push(record);
+                                        }
+                                        view! {
+                                            <div class="field">
+                                                <div class="control">
+                                                    <div class="select">
+                                                        <select
+                                                            prop:value=move || {
+                                                                year.get().map(|y| y.to_string()).unwrap_or_default()
+                                                            }
+                                                            on:change=move |ev| {
+                                                                year.set(event_target_value(&ev).parse().ok())
+                                                            }
+                                                        >
+                                                            <option value="">"All Time"</option>
+                                                            {years
+                                                                .into_iter()
+                                                                .map(|y| {
+                                                                    view! { <option value=y.to_string()>{y}</option> }
+                                                                })
+                                                                .collect_view()}
+                                                        </select>
+                                                    </div>
+                                                </div>
+                                            </div>
+                                            {if sports.is_empty() {
+                                                view! {
+                                                    <p>
+                                                        "No records yet. Reprocess activities in the settings to find the best efforts of activities uploaded before."
+                                                    </p>
+                                                }
+                                                    .into_view()
+                                            } else {
+                                                view! {
+                                                    <div class="columns is-multiline">
+                                                        {sports
+                                                            .into_iter()
+                                                            .map(|(sport, records)| {
+                                                                view! { <SportRecords sport records/> }
+                                                            })
+                                                            .collect_view()}
+                                                    </div>
+                                                }
+                                                    .into_view()
+                                            }}
+                                        }
+                                    })
+                            })
+                    }}
+
+                </ErrorBoundary>
+            </Transition>
+        </div>
+    }
+}
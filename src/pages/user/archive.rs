@@ -0,0 +1,725 @@
+use std::rc::Rc;
+
+#[cfg(feature = "ssr")]
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+#[cfg(feature = "ssr")]
+use anyhow::{bail, Context, Result};
+#[cfg(feature = "ssr")]
+use axum::{
+    extract::{Multipart, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+#[cfg(feature = "ssr")]
+use chrono::{DateTime, Local};
+#[cfg(feature = "ssr")]
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use leptos::{ev::SubmitEvent, *};
+use leptos_router::*;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "ssr")]
+use sqlx::{PgPool, Postgres, Transaction};
+use wasm_bindgen_futures::JsFuture;
+
+use crate::app::FitFileUploaded;
+#[cfg(feature = "ssr")]
+use crate::{
+    authentication::AuthSession,
+    models::{
+        activity::insert_activity,
+        aggregates::update_aggregates,
+        base::{DatabaseEntry, New},
+        device::insert_devices,
+        event::insert_events,
+        fitness::invalidate_daily_fitness,
+        lap::insert_laps,
+        record::insert_records,
+        session::insert_sessions,
+        user_preferences::{get_user_preferences_history, UserPreferences},
+        zones::ZoneModel,
+    },
+    pages::{
+        activity_overview::export::{load_activity_data, ActivityData},
+        fit_upload::duplicates::find_duplicate,
+    },
+    state::AppState,
+};
+
+/// Version of the archive format, increased whenever the format changes incompatibly.
+#[cfg(feature = "ssr")]
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// Largest archive that can be imported in bytes, archives hold every record of every activity
+/// and are far larger than the default request body limit.
+#[cfg(feature = "ssr")]
+pub const MAX_ARCHIVE_SIZE: usize = 512 * 1024 * 1024;
+
+/// A full export of a user account.
+///
+/// Entries reference each other by the ids they had on the exporting instance, these get
+/// remapped to new ids on import.
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountArchive {
+    pub version: u32,
+    pub exported_at: DateTime<Local>,
+    pub activities: Vec<ActivityData>,
+    pub user_preferences: Vec<UserPreferences>,
+    pub workout_templates: Vec<ArchivedTemplate>,
+    pub workout_parameters: Vec<ArchivedParameter>,
+    pub workout_instances: Vec<ArchivedInstance>,
+    pub parameter_links: Vec<ArchivedParameterLink>,
+    pub weekly_scaling: Vec<ArchivedScaling>,
+    pub workout_exclusion_dates: Vec<ArchivedExclusionDate>,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedTemplate {
+    pub id: i64,
+    pub template_name: String,
+    pub workout_type: String,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedParameter {
+    pub id: i64,
+    pub workout_template_id: i64,
+    pub name: String,
+    pub parameter_type: String,
+    pub value: i32,
+    pub scaling: bool,
+    pub position: i32,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedInstance {
+    pub id: i64,
+    pub workout_template_id: i64,
+    pub start_date: DateTime<Local>,
+    pub rrule: String,
+    pub active: bool,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedParameterLink {
+    pub instance_id: i64,
+    pub parameter_id: i64,
+    pub value_override: Option<i32>,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedScaling {
+    pub year: i32,
+    pub week: i32,
+    pub scaling: i32,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedExclusionDate {
+    pub workout_instance_id: i64,
+    pub exclusion_date: DateTime<Local>,
+}
+
+/// What was restored from an archive.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportSummary {
+    pub activities: usize,
+    /// Activities that were already stored, as the same file or at an overlapping time.
+    pub skipped_activities: usize,
+    pub user_preferences: usize,
+    pub workout_templates: usize,
+    pub workout_instances: usize,
+    pub weekly_scaling: usize,
+}
+
+#[cfg(feature = "ssr")]
+pub async fn export_account(State(state): State<AppState>, auth: AuthSession) -> Response {
+    let user = if let Some(user) = auth.current_user {
+        user
+    } else {
+        return (StatusCode::FORBIDDEN, "Not logged in".to_string()).into_response();
+    };
+    let archive = match build_archive(user.id, &state.pool).await {
+        Ok(archive) => archive,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("{}", e)).into_response(),
+    };
+    match write_archive(&archive) {
+        Ok(data) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "application/gzip".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!(
+                        "attachment; filename=\"toedi-account-{}.json.gz\"",
+                        archive.exported_at.format("%Y-%m-%d")
+                    ),
+                ),
+            ],
+            data,
+        )
+            .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{}", e)).into_response(),
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub async fn import_account(
+    State(state): State<AppState>,
+    auth: AuthSession,
+    mut multipart: Multipart,
+) -> Response {
+    let user = if let Some(user) = auth.current_user {
+        user
+    } else {
+        return (StatusCode::FORBIDDEN, "Not logged in".to_string()).into_response();
+    };
+    let data = match multipart.next_field().await {
+        Ok(Some(field)) => field.bytes().await,
+        Ok(None) => return (StatusCode::BAD_REQUEST, "No archive uploaded").into_response(),
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("{}", e)).into_response(),
+    };
+    let result = match data {
+        Ok(data) => match read_archive(&data) {
+            Ok(archive) => restore_archive(archive, user.id, &state.pool).await,
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e.into()),
+    };
+    match result {
+        Ok(summary) => (StatusCode::OK, axum::Json(summary)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, format!("{}", e)).into_response(),
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub fn write_archive(archive: &AccountArchive) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    serde_json::to_writer(&mut encoder, archive)?;
+    encoder.flush()?;
+    Ok(encoder.finish()?)
+}
+
+/// Read an archive, either gzip compressed or as plain json.
+#[cfg(feature = "ssr")]
+pub fn read_archive(data: &[u8]) -> Result<AccountArchive> {
+    let archive: AccountArchive = if data.starts_with(&[0x1f, 0x8b]) {
+        let mut json = Vec::new();
+        GzDecoder::new(data)
+            .read_to_end(&mut json)
+            .context("Couldn't decompress archive")?;
+        serde_json::from_slice(&json).context("Couldn't read archive")?
+    } else {
+        serde_json::from_slice(data).context("Couldn't read archive")?
+    };
+    if archive.version > ARCHIVE_VERSION {
+        bail!(
+            "Archive version {} is newer than the supported version {}",
+            archive.version,
+            ARCHIVE_VERSION
+        );
+    }
+    Ok(archive)
+}
+
+#[cfg(feature = "ssr")]
+pub async fn build_archive(user_id: i64, pool: &PgPool) -> Result<AccountArchive> {
+    let activity_ids = sqlx::query_scalar!(
+        r#"
+        SELECT id
+        FROM activities
+        WHERE user_id=$1
+        ORDER BY start_time
+        "#,
+        user_id as i32
+    )
+    .fetch_all(pool)
+    .await
+    .context("Couldn't load activities")?;
+    let mut activities = Vec::with_capacity(activity_ids.len());
+    for activity_id in activity_ids {
+        if let Some(activity) = load_activity_data(activity_id, user_id, pool).await? {
+            activities.push(activity);
+        }
+    }
+    let user_preferences = get_user_preferences_history(user_id, pool)
+        .await
+        .context("Couldn't load user preferences")?;
+    let workout_templates = sqlx::query_as!(
+        ArchivedTemplate,
+        r#"
+        SELECT id, template_name, workout_type::text AS "workout_type!"
+        FROM workout_templates
+        WHERE user_id=$1
+        "#,
+        user_id as i32
+    )
+    .fetch_all(pool)
+    .await
+    .context("Couldn't load workout templates")?;
+    let workout_parameters = sqlx::query_as!(
+        ArchivedParameter,
+        r#"
+        SELECT p.id, p.workout_template_id::int8 AS "workout_template_id!", p.name,
+            p.parameter_type::text AS "parameter_type!", p.value, p.scaling, p.position
+        FROM workout_parameters p
+        INNER JOIN workout_templates t ON t.id=p.workout_template_id
+        WHERE t.user_id=$1
+        "#,
+        user_id as i32
+    )
+    .fetch_all(pool)
+    .await
+    .context("Couldn't load workout parameters")?;
+    let workout_instances = sqlx::query_as!(
+        ArchivedInstance,
+        r#"
+        SELECT id, workout_template_id::int8 AS "workout_template_id!", start_date, rrule, active
+        FROM workout_instances
+        WHERE user_id=$1
+        "#,
+        user_id as i32
+    )
+    .fetch_all(pool)
+    .await
+    .context("Couldn't load workout instances")?;
+    let parameter_links = sqlx::query_as!(
+        ArchivedParameterLink,
+        r#"
+        SELECT l.instance_id::int8 AS "instance_id!", l.parameter_id::int8 AS "parameter_id!",
+            l.value_override
+        FROM parameter_links l
+        INNER JOIN workout_instances i ON i.id=l.instance_id
+        WHERE i.user_id=$1
+        "#,
+        user_id as i32
+    )
+    .fetch_all(pool)
+    .await
+    .context("Couldn't load parameter links")?;
+    let weekly_scaling = sqlx::query_as!(
+        ArchivedScaling,
+        r#"
+        SELECT year, week, scaling
+        FROM weekly_scaling
+        WHERE user_id=$1
+        "#,
+        user_id as i32
+    )
+    .fetch_all(pool)
+    .await
+    .context("Couldn't load weekly scaling")?;
+    let workout_exclusion_dates = sqlx::query_as!(
+        ArchivedExclusionDate,
+        r#"
+        SELECT e.workout_instance_id::int8 AS "workout_instance_id!", e.exclusion_date
+        FROM workout_exclusion_dates e
+        INNER JOIN workout_instances i ON i.id=e.workout_instance_id
+        WHERE i.user_id=$1
+        "#,
+        user_id as i32
+    )
+    .fetch_all(pool)
+    .await
+    .context("Couldn't load exclusion dates")?;
+    Ok(AccountArchive {
+        version: ARCHIVE_VERSION,
+        exported_at: Local::now(),
+        activities,
+        user_preferences,
+        workout_templates,
+        workout_parameters,
+        workout_instances,
+        parameter_links,
+        weekly_scaling,
+        workout_exclusion_dates,
+    })
+}
+
+/// Restore an archive into an account, remapping all ids.
+///
+/// Everything is restored in a single transaction. Activities that are already stored are
+/// skipped, with the same duplicate check as uploads, so an archive can be imported again or
+/// into an account with overlapping activities. Preferences are only restored if the user has
+/// none yet, as overlapping preference histories can't be merged sensibly.
+#[cfg(feature = "ssr")]
+pub async fn restore_archive(
+    archive: AccountArchive,
+    user_id: i64,
+    pool: &PgPool,
+) -> Result<ImportSummary> {
+    let mut summary = ImportSummary::default();
+    let mut tx = pool.begin().await?;
+
+    let mut activity_ids = Vec::with_capacity(archive.activities.len());
+    for data in archive.activities {
+        let ActivityData {
+            activity,
+            sessions,
+            laps,
+            records,
+            events,
+            devices,
+        } = data;
+        if find_duplicate(&activity, user_id, &mut tx).await?.is_some() {
+            summary.skipped_activities += 1;
+            continue;
+        }
+        let activity = insert_activity(
+            DatabaseEntry {
+                state: Box::new(activity),
+                extra: New,
+            },
+            user_id,
+            &mut *tx,
+        )
+        .await?;
+        let activity_id = activity.extra.activity_id;
+        insert_records(new_entries(records), activity_id, &mut *tx).await?;
+        insert_sessions(new_entries(sessions), activity_id, &mut *tx).await?;
+        insert_laps(new_entries(laps), activity_id, &mut *tx).await?;
+        insert_events(new_entries(events), activity_id, &mut *tx).await?;
+        insert_devices(new_entries(devices), activity_id, &mut *tx).await?;
+        activity_ids.push(activity_id);
+        summary.activities += 1;
+    }
+
+    let has_preferences = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM user_preferences WHERE user_id=$1) AS "exists!""#,
+        user_id as i32
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if !has_preferences {
+        for preferences in archive.user_preferences {
+            sqlx::query!(
+                r#"
+                INSERT INTO user_preferences (user_id,start_time,end_time,aerobic_threshold, anaerobic_threshold,max_heartrate, tau, c, ftp, power_load_sports, threshold_pace, pace_load_sports, zone_model, resting_heartrate, lactate_threshold_heartrate, ctl_days, atl_days, acwr_low, acwr_high, monotony_threshold, strain_threshold)
+                VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12,$13,$14,$15,$16,$17,$18,$19,$20,$21)
+                "#,
+                user_id as i32,
+                preferences.start_time,
+                preferences.end_time,
+                preferences.aerobic_threshold,
+                preferences.anaerobic_threshold,
+                preferences.max_heartrate,
+                preferences.tau,
+                preferences.c,
+                preferences.ftp,
+                &preferences.power_load_sports,
+                preferences.threshold_pace,
+                &preferences.pace_load_sports,
+                preferences.zone_model as ZoneModel,
+                preferences.resting_heartrate,
+                preferences.lactate_threshold_heartrate,
+                preferences.ctl_days,
+                preferences.atl_days,
+                preferences.acwr_low,
+                preferences.acwr_high,
+                preferences.monotony_threshold,
+                preferences.strain_threshold
+            )
+            .execute(&mut *tx)
+            .await?;
+            summary.user_preferences += 1;
+        }
+        invalidate_daily_fitness(user_id, None, &mut *tx).await?;
+    }
+    // the time in zones depends on the preferences, so only now they are there
+    for activity_id in activity_ids {
+        update_aggregates(activity_id, user_id, &mut tx).await?;
+    }
+
+    let mut template_ids = HashMap::new();
+    for template in archive.workout_templates {
+        let name = unique_template_name(&template.template_name, &mut tx).await?;
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO workout_templates (user_id, template_name, workout_type)
+            VALUES ($1, $2, $3::text::workout_type)
+            RETURNING id
+            "#,
+            user_id as i32,
+            name,
+            template.workout_type
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        template_ids.insert(template.id, id);
+        summary.workout_templates += 1;
+    }
+
+    let mut parameter_ids = HashMap::new();
+    for parameter in archive.workout_parameters {
+        let template_id = template_ids
+            .get(&parameter.workout_template_id)
+            .context("workout parameter references unknown template")?;
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO workout_parameters(workout_template_id,name,parameter_type,value,scaling,position)
+            VALUES ($1, $2, $3::text::workout_parameter_type, $4, $5, $6)
+            RETURNING id
+            "#,
+            *template_id as i32,
+            parameter.name,
+            parameter.parameter_type,
+            parameter.value,
+            parameter.scaling,
+            parameter.position
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        parameter_ids.insert(parameter.id, id);
+    }
+
+    let mut instance_ids = HashMap::new();
+    for instance in archive.workout_instances {
+        let template_id = template_ids
+            .get(&instance.workout_template_id)
+            .context("workout instance references unknown template")?;
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO workout_instances (user_id, workout_template_id, start_date, rrule, active)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id
+            "#,
+            user_id as i32,
+            *template_id as i32,
+            instance.start_date,
+            instance.rrule,
+            instance.active
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        instance_ids.insert(instance.id, id);
+        summary.workout_instances += 1;
+    }
+
+    for link in archive.parameter_links {
+        let (Some(instance_id), Some(parameter_id)) = (
+            instance_ids.get(&link.instance_id),
+            parameter_ids.get(&link.parameter_id),
+        ) else {
+            bail!("parameter link references unknown instance or parameter");
+        };
+        sqlx::query!(
+            r#"
+            INSERT INTO parameter_links (instance_id, parameter_id, value_override)
+            VALUES ($1, $2, $3)
+            "#,
+            *instance_id as i32,
+            *parameter_id as i32,
+            link.value_override
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for exclusion in archive.workout_exclusion_dates {
+        let instance_id = instance_ids
+            .get(&exclusion.workout_instance_id)
+            .context("exclusion date references unknown instance")?;
+        sqlx::query!(
+            r#"
+            INSERT INTO workout_exclusion_dates(workout_instance_id, exclusion_date)
+            VALUES ($1, $2)
+            "#,
+            *instance_id as i32,
+            exclusion.exclusion_date
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for scaling in archive.weekly_scaling {
+        let updated = sqlx::query!(
+            r#"
+            UPDATE weekly_scaling
+            SET scaling=$4
+            WHERE user_id=$1 AND year=$2 AND week=$3
+            "#,
+            user_id as i32,
+            scaling.year,
+            scaling.week,
+            scaling.scaling
+        )
+        .execute(&mut *tx)
+        .await?;
+        if updated.rows_affected() == 0 {
+            sqlx::query!(
+                r#"
+                INSERT INTO weekly_scaling (user_id, year, week, scaling)
+                VALUES ($1, $2, $3, $4)
+                "#,
+                user_id as i32,
+                scaling.year,
+                scaling.week,
+                scaling.scaling
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        summary.weekly_scaling += 1;
+    }
+
+    tx.commit().await.context("Transaction failed, try again")?;
+    Ok(summary)
+}
+
+#[cfg(feature = "ssr")]
+fn new_entries<T>(entries: Vec<T>) -> Vec<DatabaseEntry<New, T>> {
+    entries
+        .into_iter()
+        .map(|e| DatabaseEntry {
+            state: Box::new(e),
+            extra: New,
+        })
+        .collect()
+}
+
+/// Template names are unique across all users, so imported templates get a suffix if needed.
+#[cfg(feature = "ssr")]
+async fn unique_template_name(name: &str, tx: &mut Transaction<'_, Postgres>) -> Result<String> {
+    let mut candidate = name.to_string();
+    let mut counter = 1;
+    loop {
+        let exists = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM workout_templates WHERE template_name=$1) AS "exists!""#,
+            candidate
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+        if !exists {
+            return Ok(candidate);
+        }
+        counter += 1;
+        candidate = format!("{} ({})", name, counter);
+    }
+}
+
+#[component]
+pub fn AccountImportForm(show: RwSignal<bool>) -> impl IntoView {
+    let uploaded = use_context::<FitFileUploaded>().unwrap();
+    let importing = create_rw_signal(false);
+    let result = create_rw_signal(Option::<Result<ImportSummary, String>>::None);
+    let on_submit = move |_ev: SubmitEvent| {
+        importing.set(true);
+        result.set(None);
+    };
+    let on_response: Rc<dyn Fn(&web_sys::Response)> = Rc::new(move |response| {
+        let status = response.status();
+        let body = response.text();
+        spawn_local(async move {
+            let text = match body {
+                Ok(body) => JsFuture::from(body).await.ok().and_then(|t| t.as_string()),
+                Err(_) => None,
+            };
+            let summary = match (status, text) {
+                (200, Some(text)) => serde_json::from_str::<ImportSummary>(&text)
+                    .map_err(|e| format!("Couldn't read import summary: {}", e)),
+                (_, Some(text)) if !text.is_empty() => Err(text),
+                _ => Err(format!("Import failed with status {}", status)),
+            };
+            if summary.is_ok() {
+                uploaded.0.update(|v| *v += 1);
+            }
+            result.set(Some(summary));
+            importing.set(false);
+        });
+    });
+    let close = move |_| {
+        show.set(false);
+        result.set(None);
+    };
+    view! {
+        <Show when=move || { show() } fallback=|| {}>
+            <Form
+                action="/api/account/import"
+                method="POST"
+                enctype="multipart/form-data".to_string()
+                on:submit=on_submit
+                on_response=on_response.clone()
+            >
+                <div class="modal is-active">
+                    <div class="modal-background" on:click=close></div>
+                    <div class="modal-card">
+                        <div class="modal-card-head">
+                            <p class="modal-card-title">"Import Account Archive"</p>
+                            <button
+                                type="button"
+                                class="delete"
+                                aria-label="close"
+                                on:click=close
+                            ></button>
+                        </div>
+                        <div class="modal-card-body">
+                            <p class="mb-4">
+                                "Activities, settings and workouts from the archive are added to this account."
+                            </p>
+                            <div class="file">
+                                <label class="file-label">
+                                    <input class="file-input" type="file" name="archive"/>
+                                    <span class="file-cta">
+                                        <span class="file-icon">
+                                            <i class="fas fa-upload"></i>
+                                        </span>
+                                        <span class="file-label">Choose Archive...</span>
+                                    </span>
+                                </label>
+                            </div>
+                            <ImportResult result=result/>
+                        </div>
+                        <div class="modal-card-foot">
+                            <button
+                                type="submit"
+                                class="button is-success"
+                                class:is-loading=importing
+                            >
+                                <i class="material-symbols-rounded right">upload</i>
+                                Import
+                            </button>
+                            <button type="button" class="button" on:click=close>
+                                Close
+                            </button>
+                        </div>
+                    </div>
+                </div>
+            </Form>
+        </Show>
+    }
+}
+
+#[component]
+fn ImportResult(result: RwSignal<Option<Result<ImportSummary, String>>>) -> impl IntoView {
+    move || match result() {
+        None => ().into_view(),
+        Some(Err(error)) => view! {
+            <div class="notification is-danger mt-4">{error}</div>
+        }
+        .into_view(),
+        Some(Ok(summary)) => view! {
+            <div class="notification is-success mt-4">
+                <p>"Imported:"</p>
+                <ul>
+                    <li>{summary.activities} " activities"</li>
+                    <li>{summary.user_preferences} " preference periods"</li>
+                    <li>{summary.workout_templates} " workout templates"</li>
+                    <li>{summary.workout_instances} " scheduled workouts"</li>
+                    <li>{summary.weekly_scaling} " weekly scalings"</li>
+                </ul>
+                <p>{summary.skipped_activities} " already stored activities were skipped"</p>
+            </div>
+        }
+        .into_view(),
+    }
+}
@@ -1,8 +1,18 @@
+pub mod archive;
+
+use self::archive::AccountImportForm;
+use crate::pages::fit_upload::ReprocessActivities;
 #[cfg(feature = "ssr")]
 use crate::app::{auth, pool};
 #[cfg(feature = "ssr")]
+use crate::models::aggregates::backfill_time_in_zones;
+#[cfg(feature = "ssr")]
+use crate::models::fitness::invalidate_daily_fitness;
+#[cfg(feature = "ssr")]
 use crate::models::user_preferences::get_user_preferences;
-use crate::models::user_preferences::UserPreferences;
+#[cfg(feature = "ssr")]
+use crate::models::pace::parse_pace;
+use crate::models::{pace::format_pace, user_preferences::UserPreferences, zones::ZoneModel};
 #[cfg(feature = "ssr")]
 use chrono::Local;
 #[cfg(feature = "ssr")]
@@ -53,11 +63,26 @@ fn curve_fit(aerobic: f64, anaerobic: f64, max_heartrate: f64) -> (f64, f64) {
     (alpha[0], c[0])
 }
 
+// the fields of the settings form
+#[allow(clippy::too_many_arguments)]
 #[server]
 pub async fn update_user_preferences(
     aerobic_threshold: u32,
     anaerobic_threshold: u32,
     max_heartrate: u32,
+    ftp: u32,
+    power_load_sports: String,
+    threshold_pace: String,
+    pace_load_sports: String,
+    zone_model: ZoneModel,
+    resting_heartrate: u32,
+    lactate_threshold_heartrate: u32,
+    ctl_days: u32,
+    atl_days: u32,
+    acwr_low: f64,
+    acwr_high: f64,
+    monotony_threshold: f64,
+    strain_threshold: u32,
 ) -> Result<(), ServerFnError> {
     let pool = pool()?;
     let auth = auth()?;
@@ -69,6 +94,44 @@ pub async fn update_user_preferences(
         anaerobic_threshold as f64,
         max_heartrate as f64,
     );
+    // 0 means no FTP, resting or lactate threshold heart rate set
+    let ftp = (ftp > 0).then_some(ftp as i32);
+    let resting_heartrate = (resting_heartrate > 0).then_some(resting_heartrate as i32);
+    let lactate_threshold_heartrate =
+        (lactate_threshold_heartrate > 0).then_some(lactate_threshold_heartrate as i32);
+    if !(1..=365).contains(&ctl_days) || !(1..=365).contains(&atl_days) {
+        return Err(ServerFnError::new(
+            "Time constants have to be between 1 and 365 days".to_string(),
+        ));
+    }
+    if !(0.0 < acwr_low && acwr_low < acwr_high) || monotony_threshold <= 0.0 {
+        return Err(ServerFnError::new(
+            "The ACWR band and monotony threshold have to be positive".to_string(),
+        ));
+    }
+    // 0 means no strain threshold set
+    let strain_threshold = (strain_threshold > 0).then_some(strain_threshold as f64);
+    let sport_list = |sports: String| -> Vec<String> {
+        sports
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect()
+    };
+    let power_load_sports = sport_list(power_load_sports);
+    let pace_load_sports = sport_list(pace_load_sports);
+    // empty means no threshold pace set
+    let threshold_pace = match threshold_pace.trim() {
+        "" => None,
+        pace => Some(parse_pace(pace).ok_or(ServerFnError::new(
+            "Threshold pace has to be m:ss".to_string(),
+        ))?),
+    };
+    // the cached fitness has to be dropped together with the time constants it depends on
+    let mut transaction = pool
+        .begin()
+        .await
+        .map_err(|e| ServerFnError::new(format!("Couldn't start transaction:{}", e)))?;
     let current = sqlx::query!(
         r#"
         SELECT id
@@ -77,19 +140,19 @@ pub async fn update_user_preferences(
         "#,
         user.id as _
     )
-    .fetch_optional(&pool)
+    .fetch_optional(&mut *transaction)
     .await
     .map_err(|e| ServerFnError::new(format!("Couldn't query user preferences:{}", e)))?;
+    let zone_model_changed = get_user_preferences(user.id, Local::now(), &mut *transaction)
+        .await
+        .zone_model
+        != zone_model;
     match current {
         Some(current) => {
-            let mut transaction = pool
-                .begin()
-                .await
-                .map_err(|e| ServerFnError::new(format!("Couldn't start transaction:{}", e)))?;
             sqlx::query!(
                 r#"
-                INSERT INTO user_preferences (user_id,start_time,end_time,aerobic_threshold, anaerobic_threshold,max_heartrate, tau, c)
-                VALUES ($1,$2,$3,$4,$5,$6,$7,$8)
+                INSERT INTO user_preferences (user_id,start_time,end_time,aerobic_threshold, anaerobic_threshold,max_heartrate, tau, c, ftp, power_load_sports, threshold_pace, pace_load_sports, zone_model, resting_heartrate, lactate_threshold_heartrate, ctl_days, atl_days, acwr_low, acwr_high, monotony_threshold, strain_threshold)
+                VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12,$13,$14,$15,$16,$17,$18,$19,$20,$21)
                 "#,
                 user.id as _,
                 Utc::now(),
@@ -98,7 +161,20 @@ pub async fn update_user_preferences(
                 anaerobic_threshold as i32,
                 max_heartrate as i32,
                 tau,
-                c
+                c,
+                ftp,
+                &power_load_sports,
+                threshold_pace,
+                &pace_load_sports,
+                zone_model as ZoneModel,
+                resting_heartrate,
+                lactate_threshold_heartrate,
+                ctl_days as i32,
+                atl_days as i32,
+                acwr_low,
+                acwr_high,
+                monotony_threshold,
+                strain_threshold
             ).execute(&mut *transaction).await.map_err(|e|ServerFnError::new(format!("Couldn't update preferences:{}",e)))?;
 
             sqlx::query!(
@@ -113,16 +189,12 @@ pub async fn update_user_preferences(
             .execute(&mut *transaction)
             .await
             .map_err(|e| ServerFnError::new(format!("Couldn't update preferences:{}", e)))?;
-            transaction
-                .commit()
-                .await
-                .map_err(|e| ServerFnError::new(format!("Couldn't commit changes:{}", e)))?;
         }
         None => {
             sqlx::query!(
                 r#"
-                INSERT INTO user_preferences (user_id,start_time,end_time,aerobic_threshold, anaerobic_threshold,max_heartrate, tau, c)
-                VALUES ($1,$2,$3,$4,$5,$6,$7,$8)
+                INSERT INTO user_preferences (user_id,start_time,end_time,aerobic_threshold, anaerobic_threshold,max_heartrate, tau, c, ftp, power_load_sports, threshold_pace, pace_load_sports, zone_model, resting_heartrate, lactate_threshold_heartrate, ctl_days, atl_days, acwr_low, acwr_high, monotony_threshold, strain_threshold)
+                VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12,$13,$14,$15,$16,$17,$18,$19,$20,$21)
                 "#,
                 user.id as _,
                 Option::<DateTime<Utc>>::None,
@@ -131,10 +203,52 @@ pub async fn update_user_preferences(
                 anaerobic_threshold as i32,
                 max_heartrate as i32,
                 tau,
-                c
-            ).execute(&pool).await.map_err(|e|ServerFnError::new(format!("Couldn't update preferences:{}",e)))?;
+                c,
+                ftp,
+                &power_load_sports,
+                threshold_pace,
+                &pace_load_sports,
+                zone_model as ZoneModel,
+                resting_heartrate,
+                lactate_threshold_heartrate,
+                ctl_days as i32,
+                atl_days as i32,
+                acwr_low,
+                acwr_high,
+                monotony_threshold,
+                strain_threshold
+            ).execute(&mut *transaction).await.map_err(|e|ServerFnError::new(format!("Couldn't update preferences:{}",e)))?;
         }
     }
+    invalidate_daily_fitness(user.id, None, &mut *transaction)
+        .await
+        .map_err(|e| ServerFnError::new(format!("Couldn't update fitness:{}", e)))?;
+    if zone_model_changed {
+        // the zone summary falls back to the heart rates until they are recalculated
+        sqlx::query!(
+            r#"
+            WITH daily AS (DELETE FROM daily_time_in_zones WHERE user_id=$1)
+            DELETE FROM time_in_zones
+            USING activities
+            WHERE activities.id = time_in_zones.activity_id AND activities.user_id=$1
+            "#,
+            user.id as i32
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| ServerFnError::new(format!("Couldn't update time in zones:{}", e)))?;
+    }
+    transaction
+        .commit()
+        .await
+        .map_err(|e| ServerFnError::new(format!("Couldn't commit changes:{}", e)))?;
+    if zone_model_changed {
+        tokio::spawn(async move {
+            if let Err(e) = backfill_time_in_zones(Some(user.id), &pool).await {
+                leptos::logging::error!("Couldn't recalculate time in zones: {}", e);
+            }
+        });
+    }
     Ok(())
 }
 
@@ -149,12 +263,71 @@ pub async fn get_preferences() -> Result<UserPreferences, ServerFnError> {
     Ok(preferences)
 }
 
+/// Sports the user has activities for, to choose the load model of.
+#[server]
+pub async fn get_sports() -> Result<Vec<String>, ServerFnError> {
+    let pool = pool()?;
+    let auth = auth()?;
+    let user = auth
+        .current_user
+        .ok_or(ServerFnError::new("Not logged in".to_string()))?;
+    let sports = sqlx::query_scalar!(
+        r#"
+        SELECT DISTINCT sessions.sport
+        FROM sessions
+        JOIN activities ON activities.id = sessions.activity_id
+        WHERE activities.user_id = $1
+            AND sessions.sport IS NOT NULL
+            AND sessions.sport <> 'transition'
+        ORDER BY sessions.sport
+        "#,
+        user.id as i32
+    )
+    .fetch_all(&pool)
+    .await?;
+    Ok(sports)
+}
+
 #[component]
 pub fn UserSettings(show: RwSignal<bool>) -> impl IntoView {
     let close = move |_| show.set(false);
     let aerobic_threshold = create_rw_signal(140);
     let anaerobic_threshold = create_rw_signal(160);
     let max_heartrate = create_rw_signal(180);
+    // 0 means no FTP set
+    let ftp = create_rw_signal(0);
+    let power_load_sports = create_rw_signal(Vec::<String>::new());
+    let threshold_pace = create_rw_signal(String::new());
+    let pace_load_sports = create_rw_signal(Vec::<String>::new());
+    let zone_model = create_rw_signal(ZoneModel::default());
+    // 0 means not set
+    let resting_heartrate = create_rw_signal(0);
+    let lactate_threshold_heartrate = create_rw_signal(0);
+    let ctl_days = create_rw_signal(42);
+    let atl_days = create_rw_signal(7);
+    let acwr_low = create_rw_signal(0.8);
+    let acwr_high = create_rw_signal(1.3);
+    let monotony_threshold = create_rw_signal(2.0);
+    // 0 means no strain threshold set
+    let strain_threshold = create_rw_signal(0);
+    let zones = move || {
+        UserPreferences {
+            aerobic_threshold: aerobic_threshold.get() as i32,
+            anaerobic_threshold: anaerobic_threshold.get() as i32,
+            max_heartrate: max_heartrate.get() as i32,
+            zone_model: zone_model.get(),
+            resting_heartrate: Some(resting_heartrate.get() as i32),
+            lactate_threshold_heartrate: Some(lactate_threshold_heartrate.get() as i32),
+            ..Default::default()
+        }
+        .zones()
+    };
+    let sports = create_resource(
+        show,
+        |_| async move { get_sports().await.unwrap_or_default() },
+    );
+    let show_import = create_rw_signal(false);
+    let reprocess = create_server_action::<ReprocessActivities>();
     let update_user_preferences = create_server_action::<UpdateUserPreferences>();
     spawn_local(async move {
         let preferences = get_preferences().await;
@@ -163,6 +336,25 @@ pub fn UserSettings(show: RwSignal<bool>) -> impl IntoView {
             aerobic_threshold.set(preferences.aerobic_threshold as u32);
             anaerobic_threshold.set(preferences.anaerobic_threshold as u32);
             max_heartrate.set(preferences.max_heartrate as u32);
+            ftp.set(preferences.ftp.unwrap_or(0) as u32);
+            power_load_sports.set(preferences.power_load_sports);
+            threshold_pace.set(
+                preferences
+                    .threshold_pace
+                    .map(|pace| format_pace(pace as f64))
+                    .unwrap_or_default(),
+            );
+            pace_load_sports.set(preferences.pace_load_sports);
+            zone_model.set(preferences.zone_model);
+            resting_heartrate.set(preferences.resting_heartrate.unwrap_or(0) as u32);
+            lactate_threshold_heartrate
+                .set(preferences.lactate_threshold_heartrate.unwrap_or(0) as u32);
+            ctl_days.set(preferences.ctl_days as u32);
+            atl_days.set(preferences.atl_days as u32);
+            acwr_low.set(preferences.acwr_low);
+            acwr_high.set(preferences.acwr_high);
+            monotony_threshold.set(preferences.monotony_threshold);
+            strain_threshold.set(preferences.strain_threshold.unwrap_or(0.0).round() as u32);
         }
     });
     view! {
@@ -284,6 +476,486 @@ pub fn UserSettings(show: RwSignal<bool>) -> impl IntoView {
                                     </div>
                                 </div>
                             </div>
+                            <div class="columns">
+                                <div class="column is-full">
+                                    <div class="field">
+                                        <label class="label">Heartrate Zones</label>
+                                        <div class="control">
+                                            <div class="select">
+                                                <select
+                                                    name="zone_model"
+                                                    prop:value=move || zone_model.get().value()
+                                                    on:change=move |ev| {
+                                                        let value = event_target_value(&ev);
+                                                        if let Some(model) = ZoneModel::ALL
+                                                            .into_iter()
+                                                            .find(|model| model.value() == value)
+                                                        {
+                                                            zone_model.set(model);
+                                                        }
+                                                    }
+                                                >
+
+                                                    {ZoneModel::ALL
+                                                        .into_iter()
+                                                        .map(|model| {
+                                                            view! {
+                                                                <option value=model.value()>{model.label()}</option>
+                                                            }
+                                                        })
+                                                        .collect_view()}
+                                                </select>
+                                            </div>
+                                        </div>
+                                    </div>
+                                    <div class="field">
+                                        <label class="label">Resting Heartrate</label>
+                                        <div class="control">
+                                            <div class="field has-addons">
+                                                <div class="control is-expanded">
+                                                    <input
+                                                        class="input"
+                                                        type="range"
+                                                        name="resting_heartrate"
+                                                        min="0"
+                                                        max="100"
+                                                        step="1"
+                                                        value=resting_heartrate
+                                                        on:input=move |ev| {
+                                                            let value = event_target_value(&ev).parse::<u32>();
+                                                            if let Ok(value) = value {
+                                                                resting_heartrate.set(value);
+                                                            }
+                                                        }
+                                                    />
+
+                                                </div>
+                                                <div class="control">
+                                                    <span class="tag is-medium is-link ml-2">
+                                                        {move || match resting_heartrate() {
+                                                            0 => "Off".to_string(),
+                                                            hr => hr.to_string(),
+                                                        }}
+                                                    </span>
+                                                </div>
+                                            </div>
+                                        </div>
+                                    </div>
+                                    <div class="field">
+                                        <label class="label">Lactate Threshold Heartrate</label>
+                                        <div class="control">
+                                            <div class="field has-addons">
+                                                <div class="control is-expanded">
+                                                    <input
+                                                        class="input"
+                                                        type="range"
+                                                        name="lactate_threshold_heartrate"
+                                                        min="0"
+                                                        max=max_heartrate
+                                                        step="1"
+                                                        value=lactate_threshold_heartrate
+                                                        on:input=move |ev| {
+                                                            let value = event_target_value(&ev).parse::<u32>();
+                                                            if let Ok(value) = value {
+                                                                lactate_threshold_heartrate.set(value);
+                                                            }
+                                                        }
+                                                    />
+
+                                                </div>
+                                                <div class="control">
+                                                    <span class="tag is-medium is-warning ml-2">
+                                                        {move || match lactate_threshold_heartrate() {
+                                                            0 => "Anaerobic".to_string(),
+                                                            hr => hr.to_string(),
+                                                        }}
+                                                    </span>
+                                                </div>
+                                            </div>
+                                        </div>
+                                    </div>
+                                    <div class="tags">
+                                        {move || {
+                                            zones()
+                                                .into_iter()
+                                                .map(|zone| {
+                                                    view! {
+                                                        <span
+                                                            class="tag"
+                                                            style=format!("background-color: {}", zone.color)
+                                                        >
+                                                            {match zone.high {
+                                                                Some(high) => {
+                                                                    format!("{} {}-{}", zone.name, zone.low, high - 1)
+                                                                }
+                                                                None => format!("{} {}+", zone.name, zone.low),
+                                                            }}
+                                                        </span>
+                                                    }
+                                                })
+                                                .collect_view()
+                                        }}
+                                    </div>
+                                    <p class="help">
+                                        "Karvonen zones use the resting heartrate, Friel zones the lactate threshold heartrate or else the anaerobic threshold."
+                                    </p>
+                                </div>
+                            </div>
+                            <div class="columns">
+                                <div class="column is-full">
+                                    <div class="field">
+                                        <label class="label">FTP</label>
+                                        <div class="control">
+                                            <div class="field has-addons">
+                                                <div class="control is-expanded">
+                                                    <input
+                                                        class="input"
+                                                        type="range"
+                                                        name="ftp"
+                                                        min="0"
+                                                        max="500"
+                                                        step="5"
+                                                        value=ftp
+                                                        on:input=move |ev| {
+                                                            let value = event_target_value(&ev).parse::<u32>();
+                                                            if let Ok(value) = value {
+                                                                ftp.set(value);
+                                                            }
+                                                        }
+                                                    />
+
+                                                </div>
+                                                <div class="control">
+                                                    <span class="tag is-medium is-primary ml-2">
+                                                        {move || match ftp() {
+                                                            0 => "Off".to_string(),
+                                                            ftp => format!("{} W", ftp),
+                                                        }}
+                                                    </span>
+                                                </div>
+                                            </div>
+                                        </div>
+                                    </div>
+                                </div>
+                            </div>
+                            <div class="columns">
+                                <div class="column is-full">
+                                    <div class="field">
+                                        <label class="label">Threshold Pace</label>
+                                        <div class="control">
+                                            <input
+                                                class="input"
+                                                type="text"
+                                                name="threshold_pace"
+                                                placeholder="m:ss per km"
+                                                pattern="[0-9]{1,2}:[0-5][0-9]"
+                                                prop:value=threshold_pace
+                                                on:input=move |ev| threshold_pace.set(event_target_value(&ev))
+                                            />
+                                        </div>
+                                    </div>
+                                </div>
+                            </div>
+                            <div class="columns">
+                                <div class="column is-full">
+                                    <label class="label">Load Model</label>
+                                    <input
+                                        type="hidden"
+                                        name="power_load_sports"
+                                        prop:value=move || power_load_sports.get().join(",")
+                                    />
+                                    <input
+                                        type="hidden"
+                                        name="pace_load_sports"
+                                        prop:value=move || pace_load_sports.get().join(",")
+                                    />
+                                    <Transition fallback=|| {}>
+                                        <div class="field is-grouped is-grouped-multiline">
+                                            {move || {
+                                                sports
+                                                    .get()
+                                                    .unwrap_or_default()
+                                                    .into_iter()
+                                                    .map(|sport| {
+                                                        let selected_sport = sport.clone();
+                                                        let changed_sport = sport.clone();
+                                                        let model = move || {
+                                                            if power_load_sports.get().contains(&selected_sport) {
+                                                                "power"
+                                                            } else if pace_load_sports.get().contains(&selected_sport) {
+                                                                "pace"
+                                                            } else {
+                                                                "heartrate"
+                                                            }
+                                                        };
+                                                        view! {
+                                                            <div class="control">
+                                                                <div class="field has-addons">
+                                                                    <div class="control">
+                                                                        <span class="button is-static is-small">{sport}</span>
+                                                                    </div>
+                                                                    <div class="control">
+                                                                        <div class="select is-small">
+                                                                            <select
+                                                                                prop:value=model
+                                                                                on:change=move |ev| {
+                                                                                    let value = event_target_value(&ev);
+                                                                                    for (model, sports) in [
+                                                                                        ("power", power_load_sports),
+                                                                                        ("pace", pace_load_sports),
+                                                                                    ] {
+                                                                                        sports
+                                                                                            .update(|sports| {
+                                                                                                sports.retain(|s| *s != changed_sport);
+                                                                                                if value == model {
+                                                                                                    sports.push(changed_sport.clone());
+                                                                                                }
+                                                                                            });
+                                                                                    }
+                                                                                }
+                                                                            >
+                                                                                <option value="heartrate">"Heart Rate"</option>
+                                                                                <option value="power">"Power"</option>
+                                                                                <option value="pace">"Pace"</option>
+                                                                            </select>
+                                                                        </div>
+                                                                    </div>
+                                                                </div>
+                                                            </div>
+                                                        }
+                                                    })
+                                                    .collect_view()
+                                            }}
+
+                                        </div>
+                                    </Transition>
+                                    <p class="help">
+                                        "Power uses TSS as load when an FTP is set, pace uses rTSS from the grade adjusted pace when a threshold pace is set, otherwise heart rate is used. Reprocess activities to apply changes to existing ones."
+                                    </p>
+                                </div>
+                            </div>
+                            <div class="columns">
+                                <div class="column is-half">
+                                    <div class="field">
+                                        <label class="label">Fitness (CTL) Days</label>
+                                        <div class="control">
+                                            <div class="field has-addons">
+                                                <div class="control is-expanded">
+                                                    <input
+                                                        class="input"
+                                                        type="range"
+                                                        name="ctl_days"
+                                                        min="14"
+                                                        max="90"
+                                                        step="1"
+                                                        value=ctl_days
+                                                        on:input=move |ev| {
+                                                            let value = event_target_value(&ev).parse::<u32>();
+                                                            if let Ok(value) = value {
+                                                                ctl_days.set(value);
+                                                            }
+                                                        }
+                                                    />
+
+                                                </div>
+                                                <div class="control">
+                                                    <span class="tag is-medium is-primary ml-2">
+                                                        {move || format!("{} d", ctl_days())}
+                                                    </span>
+                                                </div>
+                                            </div>
+                                        </div>
+                                    </div>
+                                </div>
+                                <div class="column is-half">
+                                    <div class="field">
+                                        <label class="label">Fatigue (ATL) Days</label>
+                                        <div class="control">
+                                            <div class="field has-addons">
+                                                <div class="control is-expanded">
+                                                    <input
+                                                        class="input"
+                                                        type="range"
+                                                        name="atl_days"
+                                                        min="3"
+                                                        max="21"
+                                                        step="1"
+                                                        value=atl_days
+                                                        on:input=move |ev| {
+                                                            let value = event_target_value(&ev).parse::<u32>();
+                                                            if let Ok(value) = value {
+                                                                atl_days.set(value);
+                                                            }
+                                                        }
+                                                    />
+
+                                                </div>
+                                                <div class="control">
+                                                    <span class="tag is-medium is-warning ml-2">
+                                                        {move || format!("{} d", atl_days())}
+                                                    </span>
+                                                </div>
+                                            </div>
+                                        </div>
+                                    </div>
+                                </div>
+                            </div>
+                            <p class="help">
+                                "Time constants of the chronic and acute training load shown as fitness and fatigue."
+                            </p>
+                            <div class="columns">
+                                <div class="column is-one-quarter">
+                                    <div class="field">
+                                        <label class="label">ACWR Low</label>
+                                        <div class="control">
+                                            <input
+                                                class="input"
+                                                type="number"
+                                                name="acwr_low"
+                                                min="0.05"
+                                                step="0.05"
+                                                required
+                                                prop:value=acwr_low
+                                                on:input=move |ev| {
+                                                    if let Ok(value) = event_target_value(&ev).parse::<f64>() {
+                                                        acwr_low.set(value);
+                                                    }
+                                                }
+                                            />
+                                        </div>
+                                    </div>
+                                </div>
+                                <div class="column is-one-quarter">
+                                    <div class="field">
+                                        <label class="label">ACWR High</label>
+                                        <div class="control">
+                                            <input
+                                                class="input"
+                                                type="number"
+                                                name="acwr_high"
+                                                min="0.05"
+                                                step="0.05"
+                                                required
+                                                prop:value=acwr_high
+                                                on:input=move |ev| {
+                                                    if let Ok(value) = event_target_value(&ev).parse::<f64>() {
+                                                        acwr_high.set(value);
+                                                    }
+                                                }
+                                            />
+                                        </div>
+                                    </div>
+                                </div>
+                                <div class="column is-one-quarter">
+                                    <div class="field">
+                                        <label class="label">Monotony</label>
+                                        <div class="control">
+                                            <input
+                                                class="input"
+                                                type="number"
+                                                name="monotony_threshold"
+                                                min="0.1"
+                                                step="0.1"
+                                                required
+                                                prop:value=monotony_threshold
+                                                on:input=move |ev| {
+                                                    if let Ok(value) = event_target_value(&ev).parse::<f64>() {
+                                                        monotony_threshold.set(value);
+                                                    }
+                                                }
+                                            />
+                                        </div>
+                                    </div>
+                                </div>
+                                <div class="column is-one-quarter">
+                                    <div class="field">
+                                        <label class="label">Strain</label>
+                                        <div class="control">
+                                            <input
+                                                class="input"
+                                                type="number"
+                                                name="strain_threshold"
+                                                min="0"
+                                                step="100"
+                                                required
+                                                prop:value=strain_threshold
+                                                on:input=move |ev| {
+                                                    if let Ok(value) = event_target_value(&ev).parse::<u32>() {
+                                                        strain_threshold.set(value);
+                                                    }
+                                                }
+                                            />
+                                        </div>
+                                    </div>
+                                </div>
+                            </div>
+                            <p class="help">
+                                "Warn when the acute:chronic workload ratio leaves the band or the weekly monotony or strain exceed their threshold, a strain of 0 never warns."
+                            </p>
+                            <div class="columns">
+                                <div class="column is-full">
+                                    <label class="label">Account</label>
+                                    <div class="buttons">
+                                        <a
+                                            class="button"
+                                            href="/api/account/export"
+                                            rel="external"
+                                            download
+                                        >
+                                            <i class="material-symbols-rounded">download</i>
+                                            Export Account
+                                        </a>
+                                        <button
+                                            type="button"
+                                            class="button"
+                                            on:click=move |_| {
+                                                show.set(false);
+                                                show_import.set(true);
+                                            }
+                                        >
+
+                                            <i class="material-symbols-rounded">upload</i>
+                                            Import Account
+                                        </button>
+                                        <button
+                                            type="button"
+                                            class="button"
+                                            class:is-loading=reprocess.pending()
+                                            on:click=move |_| reprocess.dispatch(ReprocessActivities {})
+                                        >
+                                            <i class="material-symbols-rounded">refresh</i>
+                                            Reprocess Activities
+                                        </button>
+                                    </div>
+                                    {move || {
+                                        reprocess
+                                            .value()
+                                            .get()
+                                            .map(|result| match result {
+                                                Ok((count, merged)) => {
+                                                    view! {
+                                                        <p class="help">
+                                                            {format!(
+                                                                "Reprocessing {} activities in the background",
+                                                                count,
+                                                            )}
+                                                            {(merged > 0)
+                                                                .then(|| {
+                                                                    format!(
+                                                                        ", {} merged from several files keep their records and are only recalculated",
+                                                                        merged,
+                                                                    )
+                                                                })}
+                                                        </p>
+                                                    }
+                                                }
+                                                Err(e) => {
+                                                    view! { <p class="help is-danger">{e.to_string()}</p> }
+                                                }
+                                            })
+                                    }}
+                                </div>
+                            </div>
 
                         </div>
                         <div class="modal-card-foot">
@@ -304,6 +976,7 @@ pub fn UserSettings(show: RwSignal<bool>) -> impl IntoView {
                 on:click=move |_| { show.set(false) }
             ></div>
         </Show>
+        <AccountImportForm show=show_import/>
     }
 }
 
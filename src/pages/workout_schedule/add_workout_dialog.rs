@@ -58,7 +58,7 @@ impl sqlx::FromRow<'_, PgRow> for WorkoutTemplate {
             id: row.get("id"),
             user_id: row.get("user_id"),
             template_name: row.get("template_name"),
-            workout_type: WorkoutType::from_str(&row.get::<&str, _>("workout_type")).unwrap(),
+            workout_type: WorkoutType::from_str(row.get::<&str, _>("workout_type")).unwrap(),
             parameters: row.get::<Vec<WorkoutParameter>, _>("parameters"),
         })
     }
@@ -121,7 +121,7 @@ pub async fn add_workout(
     .await
     .map_err(|e| ServerFnError::new(format!("Error saving workout template: {}", e)))?;
     if let Some(param) = param {
-        if param.len() > 0 {
+        if !param.is_empty() {
             let instance_ids: Vec<i64> = std::iter::repeat(result.id).take(param.len()).collect();
             let (param_ids, param_values): (Vec<_>, Vec<_>) =
                 param.iter().map(|p| (p.id, p.value)).multiunzip();
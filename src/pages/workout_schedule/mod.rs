@@ -362,7 +362,7 @@ impl sqlx::FromRow<'_, PgRow> for WorkoutInstance {
             start_date: row.get("start_date"),
             rrule: row.get("rrule"),
             active: row.get("active"),
-            template: template,
+            template,
             exclusion_dates: row.try_get("exclusion_dates").unwrap_or_default(),
         })
     }
@@ -750,13 +750,43 @@ pub async fn get_week_workouts(
         .map(|(week, m)| WorkoutWeek {
             week: (week.year(), week.week()),
             workouts: m.clone(),
-            scaling: *scalings.get(&week).unwrap_or(&0),
+            scaling: *scalings.get(week).unwrap_or(&0),
         })
         .collect();
     result.sort_by(|a, b| a.week.partial_cmp(&b.week).unwrap());
     Ok(result)
 }
 
+/// Load of the scheduled workouts on every day from `from` up to and including `to`, from their
+/// scaled `trainingload` steps.
+#[cfg(feature = "ssr")]
+pub async fn planned_daily_loads(
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<(NaiveDate, f64)>, ServerFnError> {
+    // occurences are only included before the start of `to`
+    let weeks = get_week_workouts(from, to + Duration::try_days(1).unwrap()).await?;
+    let mut loads: HashMap<NaiveDate, f64> = HashMap::new();
+    for week in weeks {
+        for (weekday, workouts) in week.workouts {
+            let Some(date) = NaiveDate::from_isoywd_opt(week.week.0, week.week.1, weekday) else {
+                continue;
+            };
+            *loads.entry(date).or_default() += workouts
+                .iter()
+                .flat_map(|workout| workout.steps.iter())
+                .filter(|step| step.param_type == "trainingload")
+                .map(|step| step.value as f64)
+                .sum::<f64>();
+        }
+    }
+    Ok(from
+        .iter_days()
+        .take_while(|date| *date <= to)
+        .map(|date| (date, loads.get(&date).copied().unwrap_or(0.0)))
+        .collect())
+}
+
 #[component]
 pub fn WorkoutCalendar() -> impl IntoView {
     let today = Local::now();